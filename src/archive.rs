@@ -0,0 +1,542 @@
+//! Write extracted files into a single tar or zip archive.
+//!
+//! Files are buffered and compressed on the worker thread that produced
+//! them, then appended under a lock. The archive is completed by
+//! [`FileOpen::finish`] or when it is dropped. A path written more than once
+//! keeps its first contents.
+
+use std::cell::Cell;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io;
+use std::io::BufWriter;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+use byteorder::WriteBytesExt;
+use byteorder::LE;
+use flate2::Compression;
+use flate2::Crc;
+use flate2::write::DeflateEncoder;
+
+use crate::scoped_fs::FileOpen;
+
+// archive path of a file relative to the output root
+pub(crate) fn archive_path(path: &Path) -> io::Result<String> {
+    let mut out = String::new();
+    for part in path.components() {
+        match part {
+            Component::Normal(part) => {
+                let Some(part) = part.to_str() else {
+                    return Err(io::Error::new(io::ErrorKind::InvalidInput, "path is not UTF-8"));
+                };
+                if !out.is_empty() {
+                    out.push('/');
+                }
+                out.push_str(part);
+            }
+            Component::CurDir => (),
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput,
+                format!("path {} escapes the archive", path.display()))),
+        }
+    }
+    Ok(out)
+}
+
+// run `scope` into a reused per-thread buffer
+fn buffered(
+    scope: &mut dyn FnMut(&mut dyn io::Write) -> io::Result<u64>,
+    f: impl FnOnce(&[u8]) -> io::Result<()>,
+) -> io::Result<u64> {
+    thread_local!(static BUFFER: Cell<Vec<u8>> = const { Cell::new(Vec::new()) });
+
+    let mut buffer = BUFFER.take();
+    buffer.clear();
+    let res = scope(&mut buffer).and_then(|wrote| f(&buffer).map(|()| wrote));
+    BUFFER.set(buffer);
+    res
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|time| time.as_secs())
+        .unwrap_or(0)
+}
+
+struct TarState {
+    out: BufWriter<File>,
+    // files in several bundles are only added once
+    names: HashSet<String>,
+}
+
+pub(crate) struct TarArchive {
+    state: Mutex<Option<TarState>>,
+    mtime: u64,
+}
+
+impl TarArchive {
+    pub(crate) fn create(path: &Path) -> io::Result<Self> {
+        Ok(Self {
+            state: Mutex::new(Some(TarState {
+                out: BufWriter::new(File::create(path)?),
+                names: HashSet::new(),
+            })),
+            mtime: now(),
+        })
+    }
+
+    fn finish_(&self) -> io::Result<()> {
+        let Some(mut state) = self.state.lock().unwrap().take() else {
+            return Ok(());
+        };
+        // end of archive is two empty blocks
+        state.out.write_all(&[0; 1024])?;
+        state.out.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+// ustar header for one entry
+fn tar_header(name: &str, size: u64, mtime: u64, kind: u8) -> io::Result<[u8; 512]> {
+    fn octal(field: &mut [u8], value: u64) -> io::Result<()> {
+        let digits = field.len() - 1;
+        let s = format!("{value:0digits$o}");
+        if s.len() > digits {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "value too large for tar header"));
+        }
+        field[..digits].copy_from_slice(s.as_bytes());
+        Ok(())
+    }
+
+    let mut header = [0_u8; 512];
+    let (prefix, name) = if name.len() <= 100 {
+        ("", name)
+    } else {
+        // split at a `/` so the prefix and name fields both fit
+        let split = name.char_indices()
+            .filter(|&(i, c)| c == '/' && i <= 155 && name.len() - i - 1 <= 100)
+            .map(|(i, _)| i)
+            .next();
+        match split {
+            Some(i) => (&name[..i], &name[i + 1..]),
+            None => return Err(io::Error::new(io::ErrorKind::InvalidInput, "name too long for tar header")),
+        }
+    };
+    header[..name.len()].copy_from_slice(name.as_bytes());
+    octal(&mut header[100..108], 0o644)?;
+    octal(&mut header[108..116], 0)?;
+    octal(&mut header[116..124], 0)?;
+    octal(&mut header[124..136], size)?;
+    octal(&mut header[136..148], mtime)?;
+    header[156] = kind;
+    header[257..263].copy_from_slice(b"ustar\0");
+    header[263..265].copy_from_slice(b"00");
+    header[345..345 + prefix.len()].copy_from_slice(prefix.as_bytes());
+
+    // checksum is computed with its own field as spaces
+    header[148..156].fill(b' ');
+    let sum = header.iter().map(|b| *b as u64).sum::<u64>();
+    header[148..155].copy_from_slice(format!("{sum:06o}\0").as_bytes());
+    Ok(header)
+}
+
+fn tar_entry(out: &mut dyn Write, header: &[u8; 512], data: &[u8]) -> io::Result<()> {
+    out.write_all(header)?;
+    out.write_all(data)?;
+    let pad = (512 - data.len() % 512) % 512;
+    out.write_all(&[0; 512][..pad])
+}
+
+impl FileOpen for TarArchive {
+    fn open(
+        &self,
+        path: &Path,
+        scope: &mut dyn FnMut(&mut dyn io::Write) -> io::Result<u64>,
+    ) -> io::Result<u64> {
+        let name = archive_path(path)?;
+        buffered(scope, |data| {
+            let header = tar_header(&name, data.len() as u64, self.mtime, b'0');
+            let mut state = self.state.lock().unwrap();
+            let Some(state) = state.as_mut() else {
+                return Err(io::Error::other("archive already finished"));
+            };
+            if !state.names.insert(name.clone()) {
+                return Ok(());
+            }
+            let out = &mut state.out;
+            match header {
+                Ok(header) => tar_entry(out, &header, data),
+                Err(_) => {
+                    // pax extended header carries names that do not fit
+                    let mut record = format!(" path={name}\n");
+                    let mut len = record.len();
+                    while len != record.len() + len.to_string().len() {
+                        len = record.len() + len.to_string().len();
+                    }
+                    record.insert_str(0, &len.to_string());
+                    let pax = tar_header("././@PaxHeader", record.len() as u64, self.mtime, b'x')?;
+                    tar_entry(out, &pax, record.as_bytes())?;
+
+                    // readers without pax support still get the file name
+                    let short = name.rsplit('/').next().unwrap_or(&name);
+                    let mut end = short.len().min(100);
+                    while !short.is_char_boundary(end) {
+                        end -= 1;
+                    }
+                    let short = &short[..end];
+                    let header = tar_header(short, data.len() as u64, self.mtime, b'0')?;
+                    tar_entry(out, &header, data)
+                }
+            }
+        })
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        self.finish_()
+    }
+}
+
+impl Drop for TarArchive {
+    fn drop(&mut self) {
+        let _ = self.finish_();
+    }
+}
+
+struct ZipEntry {
+    name: String,
+    method: u16,
+    crc: u32,
+    compressed: u64,
+    size: u64,
+    offset: u64,
+}
+
+struct ZipState {
+    out: BufWriter<File>,
+    offset: u64,
+    entries: Vec<ZipEntry>,
+    names: HashSet<String>,
+}
+
+pub(crate) struct ZipArchive {
+    state: Mutex<Option<ZipState>>,
+    deflate: bool,
+    // MS-DOS time and date
+    time: u16,
+    date: u16,
+}
+
+// https://pkware.cachefly.net/webdocs/casestudies/APPNOTE.TXT
+const ZIP_LOCAL: u32 = 0x04034b50;
+const ZIP_CENTRAL: u32 = 0x02014b50;
+const ZIP_END: u32 = 0x06054b50;
+const ZIP64_END: u32 = 0x06064b50;
+const ZIP64_LOCATOR: u32 = 0x07064b50;
+// general purpose flag for UTF-8 names
+const ZIP_UTF8: u16 = 1 << 11;
+
+impl ZipArchive {
+    pub(crate) fn create(path: &Path, deflate: bool) -> io::Result<Self> {
+        let (time, date) = dos_time(now());
+        Ok(Self {
+            state: Mutex::new(Some(ZipState {
+                out: BufWriter::new(File::create(path)?),
+                offset: 0,
+                entries: Vec::new(),
+                names: HashSet::new(),
+            })),
+            deflate,
+            time,
+            date,
+        })
+    }
+
+    fn finish_(&self) -> io::Result<()> {
+        let Some(mut state) = self.state.lock().unwrap().take() else {
+            return Ok(());
+        };
+        let out = &mut state.out;
+
+        let cd_offset = state.offset;
+        let mut cd_size = 0;
+        for entry in &state.entries {
+            let mut extra = Vec::new();
+            let size = zip64_field(&mut extra, entry.size);
+            let compressed = zip64_field(&mut extra, entry.compressed);
+            let offset = zip64_field(&mut extra, entry.offset);
+            if !extra.is_empty() {
+                let len = extra.len() as u16;
+                extra.splice(0..0, [1, 0, len as u8, (len >> 8) as u8]);
+            }
+
+            out.write_u32::<LE>(ZIP_CENTRAL)?;
+            // made by unix, spec version 4.5
+            out.write_u16::<LE>(3 << 8 | 45)?;
+            out.write_u16::<LE>(if extra.is_empty() { 20 } else { 45 })?;
+            out.write_u16::<LE>(ZIP_UTF8)?;
+            out.write_u16::<LE>(entry.method)?;
+            out.write_u16::<LE>(self.time)?;
+            out.write_u16::<LE>(self.date)?;
+            out.write_u32::<LE>(entry.crc)?;
+            out.write_u32::<LE>(compressed)?;
+            out.write_u32::<LE>(size)?;
+            out.write_u16::<LE>(entry.name.len() as u16)?;
+            out.write_u16::<LE>(extra.len() as u16)?;
+            out.write_u16::<LE>(0)?;
+            out.write_u16::<LE>(0)?;
+            out.write_u16::<LE>(0)?;
+            out.write_u32::<LE>(0o100644 << 16)?;
+            out.write_u32::<LE>(offset)?;
+            out.write_all(entry.name.as_bytes())?;
+            out.write_all(&extra)?;
+            cd_size += 46 + entry.name.len() as u64 + extra.len() as u64;
+        }
+
+        let num_entries = state.entries.len() as u64;
+        let is_zip64 = num_entries >= 0xffff
+            || cd_size >= 0xffffffff
+            || cd_offset >= 0xffffffff;
+        if is_zip64 {
+            let end_offset = cd_offset + cd_size;
+            out.write_u32::<LE>(ZIP64_END)?;
+            out.write_u64::<LE>(44)?;
+            out.write_u16::<LE>(3 << 8 | 45)?;
+            out.write_u16::<LE>(45)?;
+            out.write_u32::<LE>(0)?;
+            out.write_u32::<LE>(0)?;
+            out.write_u64::<LE>(num_entries)?;
+            out.write_u64::<LE>(num_entries)?;
+            out.write_u64::<LE>(cd_size)?;
+            out.write_u64::<LE>(cd_offset)?;
+
+            out.write_u32::<LE>(ZIP64_LOCATOR)?;
+            out.write_u32::<LE>(0)?;
+            out.write_u64::<LE>(end_offset)?;
+            out.write_u32::<LE>(1)?;
+        }
+
+        out.write_u32::<LE>(ZIP_END)?;
+        out.write_u16::<LE>(0)?;
+        out.write_u16::<LE>(0)?;
+        out.write_u16::<LE>(num_entries.min(0xffff) as u16)?;
+        out.write_u16::<LE>(num_entries.min(0xffff) as u16)?;
+        out.write_u32::<LE>(cd_size.min(0xffffffff) as u32)?;
+        out.write_u32::<LE>(cd_offset.min(0xffffffff) as u32)?;
+        out.write_u16::<LE>(0)?;
+
+        state.out.into_inner().map_err(|e| e.into_error())?.sync_all()
+    }
+}
+
+// value for a 32-bit header field, moving it into the zip64 extra field
+// when it does not fit
+fn zip64_field(extra: &mut Vec<u8>, value: u64) -> u32 {
+    if value >= 0xffffffff {
+        extra.extend_from_slice(&value.to_le_bytes());
+        0xffffffff
+    } else {
+        value as u32
+    }
+}
+
+// MS-DOS (time, date) of a unix timestamp in UTC
+fn dos_time(secs: u64) -> (u16, u16) {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // https://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + (month <= 2) as i64;
+
+    if year < 1980 {
+        return (0, 1 << 5 | 1);
+    }
+    let time = (rem / 3600) << 11 | (rem % 3600 / 60) << 5 | (rem % 60 / 2);
+    let date = ((year - 1980).min(127) << 9) | (month << 5) | day;
+    (time as u16, date as u16)
+}
+
+impl FileOpen for ZipArchive {
+    fn open(
+        &self,
+        path: &Path,
+        scope: &mut dyn FnMut(&mut dyn io::Write) -> io::Result<u64>,
+    ) -> io::Result<u64> {
+        let name = archive_path(path)?;
+        if name.len() > u16::MAX as usize {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "name too long for zip"));
+        }
+
+        buffered(scope, |data| {
+            let mut crc = Crc::new();
+            crc.update(data);
+
+            // compress before taking the lock
+            let deflated = if self.deflate {
+                let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+                encoder.write_all(data)?;
+                Some(encoder.finish()?)
+            } else {
+                None
+            };
+            let (method, body) = match &deflated {
+                Some(deflated) => (8, &deflated[..]),
+                None => (0, data),
+            };
+
+            let mut extra = Vec::new();
+            let size = zip64_field(&mut extra, data.len() as u64);
+            let compressed = zip64_field(&mut extra, body.len() as u64);
+            if !extra.is_empty() {
+                // local zip64 extra holds both sizes
+                extra.clear();
+                extra.extend_from_slice(&[1, 0, 16, 0]);
+                extra.extend_from_slice(&(data.len() as u64).to_le_bytes());
+                extra.extend_from_slice(&(body.len() as u64).to_le_bytes());
+            }
+
+            let mut state = self.state.lock().unwrap();
+            let Some(state) = state.as_mut() else {
+                return Err(io::Error::other("archive already finished"));
+            };
+            if !state.names.insert(name.clone()) {
+                return Ok(());
+            }
+            let out = &mut state.out;
+            out.write_u32::<LE>(ZIP_LOCAL)?;
+            out.write_u16::<LE>(if extra.is_empty() { 20 } else { 45 })?;
+            out.write_u16::<LE>(ZIP_UTF8)?;
+            out.write_u16::<LE>(method)?;
+            out.write_u16::<LE>(self.time)?;
+            out.write_u16::<LE>(self.date)?;
+            out.write_u32::<LE>(crc.sum())?;
+            out.write_u32::<LE>(if extra.is_empty() { compressed } else { 0xffffffff })?;
+            out.write_u32::<LE>(if extra.is_empty() { size } else { 0xffffffff })?;
+            out.write_u16::<LE>(name.len() as u16)?;
+            out.write_u16::<LE>(extra.len() as u16)?;
+            out.write_all(name.as_bytes())?;
+            out.write_all(&extra)?;
+            out.write_all(body)?;
+
+            let offset = state.offset;
+            state.offset += 30 + name.len() as u64 + extra.len() as u64 + body.len() as u64;
+            state.entries.push(ZipEntry {
+                name,
+                method,
+                crc: crc.sum(),
+                compressed: body.len() as u64,
+                size: data.len() as u64,
+                offset,
+            });
+            Ok(())
+        })
+    }
+
+    fn finish(&self) -> io::Result<()> {
+        self.finish_()
+    }
+}
+
+impl Drop for ZipArchive {
+    fn drop(&mut self) {
+        let _ = self.finish_();
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::fs;
+    use std::io::Read;
+    use flate2::read::DeflateDecoder;
+
+    // walk the central directory and read every entry back
+    fn read_zip(data: &[u8]) -> Vec<(String, Vec<u8>)> {
+        let end = data.len() - 22;
+        assert_eq!(&data[end..end + 4], &ZIP_END.to_le_bytes());
+        let u16_at = |i: usize| u16::from_le_bytes([data[i], data[i + 1]]) as usize;
+        let u32_at = |i: usize| u32::from_le_bytes(data[i..i + 4].try_into().unwrap()) as usize;
+
+        let mut files = Vec::new();
+        let mut cd = u32_at(end + 16);
+        for _ in 0..u16_at(end + 10) {
+            assert_eq!(u32_at(cd), ZIP_CENTRAL as usize);
+            let method = u16_at(cd + 10);
+            let compressed = u32_at(cd + 20);
+            let name_len = u16_at(cd + 28);
+            let name = String::from_utf8(data[cd + 46..cd + 46 + name_len].to_vec()).unwrap();
+            let local = u32_at(cd + 42);
+            let body = local + 30 + u16_at(local + 26) + u16_at(local + 28);
+            let body = &data[body..body + compressed];
+            let contents = if method == 8 {
+                let mut out = Vec::new();
+                DeflateDecoder::new(body).read_to_end(&mut out).unwrap();
+                out
+            } else {
+                body.to_vec()
+            };
+            let mut crc = Crc::new();
+            crc.update(&contents);
+            assert_eq!(crc.sum() as usize, u32_at(cd + 16));
+            files.push((name, contents));
+            cd += 46 + name_len + u16_at(cd + 30);
+        }
+        files
+    }
+
+    #[test]
+    fn zip_and_tar() {
+        let dir = std::env::temp_dir().join(format!("limn-archive-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let long = format!("./{}/{}.lua", "a".repeat(160), "b".repeat(90));
+        let write = |archive: &dyn FileOpen| {
+            archive.open(Path::new("./scripts/foo.lua"), &mut |out| {
+                out.write_all(b"hello hello hello")?;
+                Ok(17)
+            }).unwrap();
+            // a file in several bundles is only added once
+            archive.open(Path::new("scripts/foo.lua"), &mut |out| {
+                out.write_all(b"again")?;
+                Ok(5)
+            }).unwrap();
+            archive.open(Path::new(&long), &mut |out| {
+                out.write_all(b"long")?;
+                Ok(4)
+            }).unwrap();
+            assert!(archive.open(Path::new("../escape"), &mut |_| Ok(0)).is_err());
+            archive.finish().unwrap();
+        };
+
+        for deflate in [false, true] {
+            let path = dir.join("out.zip");
+            write(&ZipArchive::create(&path, deflate).unwrap());
+            let files = read_zip(&fs::read(&path).unwrap());
+            assert_eq!(files, [
+                ("scripts/foo.lua".to_string(), b"hello hello hello".to_vec()),
+                (long[2..].to_string(), b"long".to_vec()),
+            ]);
+        }
+
+        let path = dir.join("out.tar");
+        write(&TarArchive::create(&path).unwrap());
+        let data = fs::read(&path).unwrap();
+        assert_eq!(&data[..15], b"scripts/foo.lua");
+        assert_eq!(&data[257..263], b"ustar\0");
+        assert_eq!(&data[512..529], b"hello hello hello");
+        // pax header for the long name, then the entry itself
+        assert_eq!(data[1024 + 156], b'x');
+        assert!(data.len().is_multiple_of(512) && data[data.len() - 1024..].iter().all(|b| *b == 0));
+
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -2,22 +2,107 @@ use std::io;
 use std::io::Read;
 use std::io::Seek;
 use std::io::SeekFrom;
+use std::io::Write;
+use std::fs;
+use std::fs::File;
+use std::mem;
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::thread;
 use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
 use byteorder::LE;
 
-use crate::oodle;
+use crate::error::ensure;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::error::Result;
+use crate::decompress::Decompressor;
+use crate::read::ChunkReader;
 
 pub trait ReadSeek: Read + Seek {}
 impl<T: Read + Seek> ReadSeek for T {}
 
+/// Bundle name hash from the file name of a bundle.
+pub fn bundle_hash_from(path: &Path) -> Option<u64> {
+    let name = path.file_stem()?;
+    u64::from_str_radix(name.to_str()?, 16).ok()
+}
+
+/// Bundles in a directory with their name hash.
+///
+/// Files with an extension (`.stream`, `.patch_001`, ...) are skipped.
+pub fn list_bundles(dir: &Path) -> io::Result<Vec<(PathBuf, u64)>> {
+    let mut bundles = Vec::new();
+    for fd in fs::read_dir(dir)? {
+        let fd = fd?;
+        if fd.metadata()?.is_file() {
+            let path = fd.path();
+            if path.extension().is_some() {
+                continue;
+            }
+
+            if let Some(bundle_hash) = bundle_hash_from(&path) {
+                bundles.push((path, bundle_hash));
+            }
+        }
+    }
+    Ok(bundles)
+}
+
+/// Read every bundle in `bundles` on `num_threads` threads.
+///
+/// `read` gets the index of the bundle in `bundles`, the opened bundle and a
+/// scratch buffer kept by each thread. Results are in no particular order.
+/// The first error stops the other threads from starting more bundles.
+pub fn read_parallel<R: Send>(
+    bundles: &[(PathBuf, u64)],
+    num_threads: usize,
+    read: impl Fn(usize, &mut BundleFd<'_>, &mut Vec<u8>) -> Result<R> + Sync,
+) -> Result<Vec<R>> {
+    let next = AtomicUsize::new(0);
+    let results = Mutex::new(Vec::with_capacity(bundles.len()));
+    thread::scope(|s| {
+        let mut threads = Vec::new();
+        for _ in 0..num_threads.max(1) {
+            threads.push(s.spawn(|| -> Result<()> {
+                let mut buffer_reader = vec![0_u8; 0x80000];
+                let mut scratch = Vec::new();
+                loop {
+                    let i = next.fetch_add(1, Ordering::AcqRel);
+                    let Some((path, hash)) = bundles.get(i) else {
+                        return Ok(());
+                    };
+                    let res = File::open(path)
+                        .map_err(|e| Error::from(e).with_bundle(Some(*hash)))
+                        .and_then(|fd| {
+                            let mut rdr = ChunkReader::new(&mut buffer_reader, fd);
+                            let mut bundle = BundleFd::new(Some(*hash), &mut rdr)?;
+                            read(i, &mut bundle, &mut scratch)
+                        })
+                        .inspect_err(|_| next.store(bundles.len(), Ordering::Release))?;
+                    results.lock().unwrap().push(res);
+                }
+            }));
+        }
+        threads.into_iter().try_for_each(|t| t.join().unwrap())
+    })?;
+    Ok(results.into_inner().unwrap())
+}
+
 pub struct BundleFd<'a> {
     rdr: &'a mut dyn ReadSeek,
     pub name: Option<u64>,
     pub num_files: u32,
+    locations: Option<Vec<FileLocation>>,
 }
 
 impl<'a> BundleFd<'a> {
-    pub fn new(name: Option<u64>, rdr: &'a mut dyn ReadSeek) -> io::Result<Self> {
+    pub fn new(name: Option<u64>, rdr: &'a mut dyn ReadSeek) -> Result<Self> {
         let mut header = [0_u8; 8];
         rdr.read_exact(&mut header)?;
         if header != [
@@ -37,7 +122,8 @@ impl<'a> BundleFd<'a> {
             0x03, 0x00,
             0x00, 0x00,
         ] {
-            panic!("unknown bundle version {}", header[0]);
+            let version = u16::from_le_bytes([header[0], header[1]]);
+            return Err(Error::new(ErrorKind::UnknownVersion(version)).with_bundle(name));
         }
 
         let num_files = rdr.read_u32::<LE>()?;
@@ -46,44 +132,232 @@ impl<'a> BundleFd<'a> {
             rdr,
             name,
             num_files,
+            locations: None,
         })
     }
 
-    pub fn index(&mut self) -> IndexIter<'_> {
+    pub fn index(&mut self) -> Result<IndexIter<'_>> {
         IndexIter::new(self.rdr, self.num_files)
+            .map_err(|e| e.with_bundle(self.name))
     }
 
-    fn reader<'b>(&'b mut self, oodle: &'b oodle::Oodle, scratch: &'b mut Vec<u8>) -> OodleRead<'b> {
-        let needed = oodle.memory_size_needed().unwrap() as usize;
+    fn reader<'b>(&'b mut self, decompressor: &'b dyn Decompressor, scratch: &'b mut Vec<u8>) -> Result<OodleRead<'b>> {
+        let needed = decompressor.scratch_size();
         if scratch.len() < CHUNK_SIZE * 2 + needed {
             scratch.resize(CHUNK_SIZE * 2 + needed, 0);
         }
         let (in_buf, scratch) = scratch.split_at_mut(CHUNK_SIZE);
         let (out_buf, scratch) = scratch.split_at_mut(CHUNK_SIZE);
         let (scratch, _) = scratch.split_at_mut(needed);
-        match OodleRead::new(
-            oodle,
+        OodleRead::new(
+            decompressor,
             self.rdr,
             self.num_files,
             <&mut [u8; CHUNK_SIZE]>::try_from(in_buf).unwrap(),
             <&mut [u8; CHUNK_SIZE]>::try_from(out_buf).unwrap(),
             scratch,
-        ) {
-            Ok(o) => o,
-            Err(e) => if let Some(bundle_hash) = self.name {
-                panic!("{bundle_hash:016x} {e}");
-            } else {
-                panic!("anonymous bundle {e}");
-            }
+        ).map_err(|e| e.with_bundle(self.name))
+    }
+
+    pub fn files<'b>(&'b mut self, decompressor: &'b dyn Decompressor, scratch: &'b mut Vec<u8>) -> Result<FilesIter<'b>> {
+        let num_files = self.num_files;
+        let name = self.name;
+        Ok(FilesIter::new(self.reader(decompressor, scratch)?, name, num_files))
+    }
+
+    /// Iterate files while chunks are decompressed ahead on worker threads.
+    ///
+    /// Starts `num_workers` workers, at most [`workers.len()`](ChunkWorkers::len).
+    /// Without workers chunks are decompressed on the calling thread.
+    pub fn files_pipelined<R>(
+        &mut self,
+        decompressor: &dyn Decompressor,
+        scratch: &mut Vec<u8>,
+        workers: &mut ChunkWorkers,
+        num_workers: usize,
+        f: impl FnOnce(&mut FilesIter<'_>) -> Result<R>,
+    ) -> Result<R> {
+        let num_workers = num_workers.min(workers.len());
+        if num_workers == 0 {
+            return f(&mut self.files(decompressor, scratch)?);
         }
+
+        let needed = decompressor.scratch_size();
+        let (jobs_tx, jobs_rx) = mpsc::channel();
+        let (done_tx, done_rx) = mpsc::channel();
+        let jobs_rx = Mutex::new(jobs_rx);
+        let ChunkWorkers { scratch: worker_scratch, jobs: spare } = workers;
+
+        thread::scope(|s| {
+            for worker in &mut worker_scratch[..num_workers] {
+                if worker.len() < needed {
+                    worker.resize(needed, 0);
+                }
+                let done_tx = done_tx.clone();
+                let jobs_rx = &jobs_rx;
+                s.spawn(move || pipeline_worker(decompressor, &mut worker[..needed], jobs_rx, done_tx));
+            }
+            drop(done_tx);
+
+            let mut files = self.files(decompressor, scratch)?;
+            files.oodle.pipeline = Some(Pipeline {
+                jobs: jobs_tx,
+                done: done_rx,
+                free: mem::take(spare),
+                ready: Vec::new(),
+                generation: 0,
+                expected: 0,
+                next_submit: 0,
+                in_flight: 0,
+                window: num_workers * 2,
+                limit: u32::MAX,
+            });
+            let res = f(&mut files);
+            // workers exit once the job sender is dropped
+            if let Some(pipeline) = files.oodle.pipeline.take() {
+                *spare = pipeline.finish();
+            }
+            res
+        })
     }
 
-    pub fn files<'b>(&'b mut self, oodle: &'b oodle::Oodle, scratch: &'b mut Vec<u8>) -> FilesIter<'b> {
+    /// Check the bundle without extracting it.
+    ///
+    /// Every chunk is decompressed and checked against the chunk table,
+    /// the index is compared with the file headers in the stream and, with
+    /// `data_dir`, resource files referenced by files must exist. Problems
+    /// are collected instead of stopping at the first one. `Err` is only
+    /// returned when the chunk table itself can't be read.
+    pub fn verify(
+        &mut self,
+        decompressor: &dyn Decompressor,
+        scratch: &mut Vec<u8>,
+        data_dir: Option<&Path>,
+    ) -> Result<Vec<Error>> {
+        let bundle = self.name;
         let num_files = self.num_files;
-        FilesIter::new(self.reader(oodle, scratch), num_files)
+        let mut problems = Vec::new();
+
+        let index = self.index()?.collect::<Vec<_>>();
+        if index.len() != num_files as usize {
+            problems.push(Error::layout(format!("index ends after {} of {num_files} files", index.len())));
+        }
+
+        let mut rdr = self.reader(decompressor, scratch)?;
+        if !rdr.verify_chunks(&mut problems)? {
+            // file headers can't be found past a broken chunk
+            return Ok(problems.into_iter().map(|e| e.with_bundle(bundle)).collect());
+        }
+
+        let mut files = FilesIter::new(rdr, bundle, num_files);
+        let mut data = Vec::new();
+        for i in 0.. {
+            let mut file = match files.next_file() {
+                Ok(Some(file)) => file,
+                Ok(None) => break,
+                Err(e) => {
+                    problems.push(e);
+                    break;
+                }
+            };
+
+            match index.get(i) {
+                Some(entry) if entry.ext == file.ext && entry.name == file.name => (),
+                Some(entry) => problems.push(Error::layout(format!(
+                    "index entry {i} is {:016x}.{:016x} but stream has {:016x}.{:016x}",
+                    entry.name, entry.ext, file.name, file.ext))
+                    .with_offset(file.offset)),
+                None => problems.push(Error::layout(format!("file {i} is missing from the index"))
+                    .with_offset(file.offset)),
+            }
+
+            let Some(data_dir) = data_dir else {
+                continue;
+            };
+
+            data.clear();
+            if let Err(e) = file.read_to_end(&mut data) {
+                problems.push(Error::from(e).with_entry(file.ext, file.name).with_bundle(bundle));
+                break;
+            }
+            match crate::file::resource_path(file.ext, file.variants(), &data) {
+                Ok(Some(path)) => {
+                    let path = data_dir.join(path);
+                    if !path.is_file() {
+                        problems.push(Error::new(ErrorKind::MissingResource(path))
+                            .with_entry(file.ext, file.name));
+                    }
+                }
+                Ok(None) => (),
+                Err(e) => problems.push(e.with_entry(file.ext, file.name)),
+            }
+        }
+
+        Ok(problems.into_iter().map(|e| e.with_bundle(bundle)).collect())
+    }
+
+    /// Offsets of every file header in the decompressed bundle stream.
+    ///
+    /// Built on first use by walking the file headers and cached for later
+    /// calls.
+    pub fn locations(&mut self, decompressor: &dyn Decompressor, scratch: &mut Vec<u8>) -> Result<&[FileLocation]> {
+        if self.locations.is_none() {
+            let mut locations = Vec::with_capacity(self.num_files as usize);
+            let mut files = self.files(decompressor, scratch)?;
+            while let Some(file) = files.next_file()? {
+                locations.push(FileLocation {
+                    ext: file.ext,
+                    name: file.name,
+                    offset: file.offset - header_size(file.variants.len()),
+                    variants: file.variants.clone(),
+                });
+            }
+            self.locations = Some(locations);
+        }
+        Ok(self.locations.as_deref().unwrap())
+    }
+
+    /// Open a single file without reading the files before it.
+    ///
+    /// Only the chunks the file spans are decompressed.
+    pub fn open_file<'b>(
+        &'b mut self,
+        decompressor: &'b dyn Decompressor,
+        scratch: &'b mut Vec<u8>,
+        ext: u64,
+        name: u64,
+    ) -> Result<Option<Entry<'b, 'b>>> {
+        let Some(offset) = self.locations(decompressor, scratch)?
+            .iter()
+            .find(|l| l.ext == ext && l.name == name)
+            .map(|l| l.offset)
+        else {
+            return Ok(None);
+        };
+
+        let bundle = self.name;
+        let mut rdr = self.reader(decompressor, scratch)?;
+        rdr.seek_to(offset)
+            .and_then(|_| read_header(&mut rdr))
+            .map(|header| Some(Entry::new(EntryReader::Owned(Box::new(rdr)), header, bundle)))
+            .map_err(|e| e.with_offset(offset).with_bundle(bundle))
     }
 }
 
+/// Location of a file header in the decompressed bundle stream.
+#[derive(Clone, Debug)]
+pub struct FileLocation {
+    pub ext: u64,
+    pub name: u64,
+    pub offset: u64,
+    pub variants: Vec<Variant>,
+}
+
+// ext, name, number of variants, padding and 14 bytes per variant
+const fn header_size(num_variants: usize) -> u64 {
+    24 + 14 * num_variants as u64
+}
+
 pub struct IndexIter<'a> {
     rdr: &'a mut dyn ReadSeek,
     num_files: u32,
@@ -91,14 +365,14 @@ pub struct IndexIter<'a> {
 }
 
 impl<'a> IndexIter<'a> {
-    fn new(rdr: &'a mut dyn ReadSeek, num_files: u32) -> Self {
-        rdr.seek(SeekFrom::Start(12 + 256)).unwrap();
+    fn new(rdr: &'a mut dyn ReadSeek, num_files: u32) -> Result<Self> {
+        rdr.seek(SeekFrom::Start(12 + 256))?;
 
-        Self {
+        Ok(Self {
             rdr,
             num_files,
             offset: 0,
-        }
+        })
     }
 }
 
@@ -139,11 +413,16 @@ fn align_16(offset: u64) -> i64 {
 const CHUNK_SIZE: usize = 0x80000;
 
 struct OodleRead<'a> {
-    oodle: &'a oodle::Oodle,
+    decompressor: &'a dyn Decompressor,
     rdr: &'a mut dyn ReadSeek,
     in_buf: &'a mut [u8; CHUNK_SIZE],
     out_buf: &'a mut [u8; CHUNK_SIZE],
     scratch: &'a mut [u8],
+    // compressed size and stream position of each chunk record
+    chunks: Vec<(u32, u64)>,
+    // chunk currently held in `out_buf`
+    loaded: Option<u32>,
+    pipeline: Option<Pipeline>,
     offset: usize,
     total_out: usize,
     total_size: u64,
@@ -153,76 +432,357 @@ struct OodleRead<'a> {
 
 impl<'a> OodleRead<'a> {
     fn new(
-        oodle: &'a oodle::Oodle,
+        decompressor: &'a dyn Decompressor,
         rdr: &'a mut dyn ReadSeek,
         num_files: u32,
         in_buf: &'a mut [u8; CHUNK_SIZE],
         out_buf: &'a mut [u8; CHUNK_SIZE],
         scratch: &'a mut [u8],
-    ) -> io::Result<Self> {
-        rdr.seek(SeekFrom::Start(12 + 256 + u64::from(num_files) * 20)).unwrap();
-        let num_chunks = rdr.read_u32::<LE>().unwrap();
+    ) -> Result<Self> {
+        rdr.seek(SeekFrom::Start(12 + 256 + u64::from(num_files) * 20))?;
+        let num_chunks = rdr.read_u32::<LE>()?;
+        let mut chunks = Vec::with_capacity(num_chunks.min(0x10000) as usize);
         for _ in 0..num_chunks {
-            let _chunk_size = rdr.read_u32::<LE>().unwrap();
+            chunks.push((rdr.read_u32::<LE>()?, 0));
         }
 
-        let padding = align_16(rdr.stream_position().unwrap());
+        let padding = align_16(rdr.stream_position()?);
         if padding > 0 {
-            rdr.seek(SeekFrom::Current(padding)).unwrap();
+            rdr.seek(SeekFrom::Current(padding))?;
+        }
+        let total_size = rdr.read_u32::<LE>()? as u64;
+        let zero = rdr.read_u32::<LE>()?;
+
+        let mut pos = rdr.stream_position()?;
+        for (chunk_size, chunk_pos) in &mut chunks {
+            *chunk_pos = pos;
+            pos += 4;
+            pos += align_16(pos) as u64 + *chunk_size as u64;
         }
-        let total_size = rdr.read_u32::<LE>().unwrap() as u64;
-        let zero = rdr.read_u32::<LE>().unwrap();
 
         if 0 != zero {
-            eprintln!("{}: {:08x} (padding {})", rdr.stream_position()? - 4, zero.swap_bytes(), padding);
-            Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected non-zero"))
+            Err(Error::layout(format!("unexpected non-zero {:08x} (padding {padding})", zero.swap_bytes()))
+                .with_offset(rdr.stream_position()? - 4))
+        } else if total_size > num_chunks as u64 * CHUNK_SIZE as u64 {
+            Err(Error::layout(format!("{num_chunks} chunks are too small for {total_size} bytes")))
         } else {
-            let mut s = Self {
-                oodle,
+            Ok(Self {
+                decompressor,
                 rdr,
                 in_buf,
                 out_buf,
                 scratch,
-                offset: 0,
+                chunks,
+                loaded: None,
+                pipeline: None,
+                // first chunk is decompressed on the first read
+                offset: CHUNK_SIZE,
                 total_out: 0,
                 total_size,
                 num_chunks,
                 current: 0,
-            };
-            s.next()?;
-            Ok(s)
+            })
         }
     }
 
-    fn next(&mut self) -> io::Result<bool> {
+    fn next(&mut self) -> Result<bool> {
         if self.current < self.num_chunks {
+            let index = self.current;
             self.current += 1;
             self.offset = 0;
-            let chunk_size = self.rdr.read_u32::<LE>()? as usize;
+            self.loaded = None;
 
-            let padding = align_16(self.rdr.stream_position().unwrap());
-            if padding > 0 {
-                self.rdr.seek(SeekFrom::Current(padding)).unwrap();
+            if let Some(pipeline) = &mut self.pipeline {
+                pipeline.take(index, self.rdr, &self.chunks, self.out_buf)
+                    .map_err(|e| e.with_offset(self.total_out as u64))?;
+                self.loaded = Some(index);
+                return Ok(true);
             }
 
-            self.rdr.read_exact(&mut self.in_buf[..chunk_size])?;
-
+            let chunk_size = read_chunk(self.rdr, index, &self.chunks, &mut self.in_buf[..])?;
             if chunk_size == CHUNK_SIZE {
                 self.out_buf.copy_from_slice(self.in_buf);
             } else {
-                let size = self.oodle.decompress(
+                self.decompressor.decompress(
                     &self.in_buf[..chunk_size],
                     &mut self.out_buf[..CHUNK_SIZE],
                     self.scratch,
-                )?;
-                assert_eq!(size, CHUNK_SIZE as u64);
+                ).map_err(|e| e.with_offset(self.total_out as u64))?;
             }
+            self.loaded = Some(index);
 
             Ok(true)
         } else {
             Ok(false)
         }
     }
+
+    /// Move to an offset in the decompressed stream.
+    ///
+    /// Chunks between the current and new position are not decompressed.
+    fn seek_to(&mut self, offset: u64) -> Result<()> {
+        ensure!(offset <= self.total_size, "seek to {offset} past end of bundle {}", self.total_size);
+        let chunk = (offset / CHUNK_SIZE as u64) as u32;
+        let within = (offset % CHUNK_SIZE as u64) as usize;
+        self.total_out = offset as usize;
+
+        if self.loaded == Some(chunk) {
+            self.current = chunk + 1;
+            self.offset = within;
+            return Ok(());
+        }
+
+        // `rdr` moves away from the chunk after `loaded`
+        self.loaded = None;
+
+        // the pipeline keeps track of its own position in the bundle
+        if let Some((_, pos)) = self.chunks.get(chunk as usize)
+            && self.pipeline.is_none()
+        {
+            self.rdr.seek(SeekFrom::Start(*pos))?;
+        }
+        self.current = chunk;
+        if within == 0 {
+            // decompress lazily on the next read
+            self.offset = CHUNK_SIZE;
+        } else {
+            self.next()?;
+            self.offset = within;
+        }
+        Ok(())
+    }
+}
+
+impl OodleRead<'_> {
+    // decompress every chunk, recording problems instead of stopping
+    //
+    // returns false if any chunk could not be decompressed
+    fn verify_chunks(&mut self, problems: &mut Vec<Error>) -> Result<bool> {
+        let min_size = (self.num_chunks as u64).saturating_sub(1) * CHUNK_SIZE as u64;
+        if self.num_chunks > 0 && self.total_size <= min_size {
+            problems.push(Error::layout(format!(
+                "{} chunks are too many for {} bytes", self.num_chunks, self.total_size)));
+        }
+
+        let mut readable = true;
+        for index in 0..self.num_chunks {
+            let offset = index as u64 * CHUNK_SIZE as u64;
+            let pos = self.chunks[index as usize].1;
+            self.rdr.seek(SeekFrom::Start(pos))?;
+            self.loaded = None;
+            let res = read_chunk(self.rdr, index, &self.chunks, &mut self.in_buf[..])
+                .and_then(|chunk_size| if chunk_size == CHUNK_SIZE {
+                    self.out_buf.copy_from_slice(self.in_buf);
+                    Ok(())
+                } else {
+                    self.decompressor.decompress(
+                        &self.in_buf[..chunk_size],
+                        &mut self.out_buf[..CHUNK_SIZE],
+                        self.scratch,
+                    ).map(|_| ())
+                });
+            if let Err(e) = res {
+                problems.push(e.with_offset(offset));
+                readable = false;
+            }
+        }
+
+        let end = match self.chunks.last() {
+            Some((size, pos)) => {
+                let pos = pos + 4;
+                pos + align_16(pos) as u64 + *size as u64
+            }
+            None => self.rdr.stream_position()?,
+        };
+        let len = self.rdr.seek(SeekFrom::End(0))?;
+        if len != end {
+            problems.push(Error::layout(format!("bundle is {len} bytes but chunks end at {end}")));
+        }
+
+        // the next read starts over from the first chunk
+        self.rdr.seek(SeekFrom::Start(self.chunks.first().map(|c| c.1).unwrap_or(end)))?;
+        self.current = 0;
+        self.offset = CHUNK_SIZE;
+        self.total_out = 0;
+        Ok(readable)
+    }
+}
+
+// read the chunk record at the current position and return its compressed size
+fn read_chunk(
+    rdr: &mut dyn ReadSeek,
+    index: u32,
+    chunks: &[(u32, u64)],
+    in_buf: &mut [u8],
+) -> Result<usize> {
+    let chunk_size = rdr.read_u32::<LE>()? as usize;
+    ensure!(chunk_size == chunks[index as usize].0 as usize,
+        "chunk {index} has size {chunk_size:#x} instead of {:#x}", chunks[index as usize].0);
+    ensure!(chunk_size <= CHUNK_SIZE, "chunk {index} has size {chunk_size:#x}");
+
+    let padding = align_16(rdr.stream_position()?);
+    if padding > 0 {
+        rdr.seek(SeekFrom::Current(padding))?;
+    }
+
+    rdr.read_exact(&mut in_buf[..chunk_size])?;
+    Ok(chunk_size)
+}
+
+/// Buffers for [`BundleFd::files_pipelined`], kept between bundles.
+#[derive(Default)]
+pub struct ChunkWorkers {
+    // decompressor scratch of each worker
+    scratch: Vec<Vec<u8>>,
+    // chunk buffers left over from the last bundle
+    jobs: Vec<Job>,
+}
+
+impl ChunkWorkers {
+    /// Buffers for up to `num_workers` workers, allocated on first use.
+    pub fn new(num_workers: usize) -> Self {
+        Self {
+            scratch: vec![Vec::new(); num_workers],
+            jobs: Vec::new(),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.scratch.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scratch.is_empty()
+    }
+}
+
+struct Job {
+    generation: u32,
+    index: u32,
+    size: usize,
+    input: Vec<u8>,
+    output: Vec<u8>,
+}
+
+/// Decompresses chunks ahead of the reader on worker threads.
+///
+/// Compressed chunks are still read on the owning thread. Results are put
+/// back in order before being handed to `OodleRead`.
+struct Pipeline {
+    jobs: mpsc::Sender<Job>,
+    done: mpsc::Receiver<(Job, Result<()>)>,
+    // job buffers not in use
+    free: Vec<Job>,
+    // finished chunks waiting for their turn
+    ready: Vec<(Job, Result<()>)>,
+    // bumped on seeks so results from before the seek are dropped
+    generation: u32,
+    expected: u32,
+    next_submit: u32,
+    in_flight: usize,
+    window: usize,
+    // chunks from here on are only read once they are needed
+    limit: u32,
+}
+
+impl Pipeline {
+    fn take(
+        &mut self,
+        index: u32,
+        rdr: &mut dyn ReadSeek,
+        chunks: &[(u32, u64)],
+        out_buf: &mut [u8; CHUNK_SIZE],
+    ) -> Result<()> {
+        if index < self.expected || index > self.next_submit {
+            self.generation += 1;
+            self.free.extend(self.ready.drain(..).map(|(job, _)| job));
+            self.next_submit = index;
+            rdr.seek(SeekFrom::Start(chunks[index as usize].1))?;
+        } else {
+            let (stale, ready) = self.ready.drain(..).partition(|(job, _)| job.index < index);
+            self.ready = ready;
+            self.free.extend(stale.into_iter().map(|(job, _)| job));
+        }
+        self.expected = index;
+
+        loop {
+            if let Some(i) = self.ready.iter().position(|(job, _)| job.index == index) {
+                let (job, res) = self.ready.swap_remove(i);
+                out_buf.copy_from_slice(&job.output[..CHUNK_SIZE]);
+                self.free.push(job);
+                self.expected = index + 1;
+                return res;
+            }
+
+            while self.in_flight < self.window
+                && (self.next_submit as usize) < chunks.len()
+                && (self.next_submit as usize) < index as usize + self.window
+                && (self.next_submit < self.limit || self.next_submit <= index)
+            {
+                let mut job = self.free.pop().unwrap_or_else(|| Job {
+                    generation: 0,
+                    index: 0,
+                    size: 0,
+                    input: vec![0; CHUNK_SIZE],
+                    output: vec![0; CHUNK_SIZE],
+                });
+                job.size = read_chunk(rdr, self.next_submit, chunks, &mut job.input)?;
+                job.generation = self.generation;
+                job.index = self.next_submit;
+                self.jobs.send(job)
+                    .map_err(|_| Error::new(ErrorKind::Decompress))?;
+                self.next_submit += 1;
+                self.in_flight += 1;
+            }
+
+            let Ok((job, res)) = self.done.recv() else {
+                return Err(Error::new(ErrorKind::Decompress));
+            };
+            self.in_flight -= 1;
+            if job.generation == self.generation && job.index >= self.expected {
+                self.ready.push((job, res));
+            } else {
+                self.free.push(job);
+            }
+        }
+    }
+
+    // wait for the workers and return every chunk buffer
+    fn finish(self) -> Vec<Job> {
+        let Self { jobs, done, mut free, ready, in_flight, .. } = self;
+        drop(jobs);
+        free.extend(ready.into_iter().map(|(job, _)| job));
+        free.extend(done.iter().take(in_flight).map(|(job, _)| job));
+        free
+    }
+}
+
+fn pipeline_worker(
+    decompressor: &dyn Decompressor,
+    scratch: &mut [u8],
+    jobs: &Mutex<mpsc::Receiver<Job>>,
+    done: mpsc::Sender<(Job, Result<()>)>,
+) {
+    loop {
+        let Ok(mut job) = jobs.lock()
+            .map_err(|_| ())
+            .and_then(|jobs| jobs.recv().map_err(|_| ()))
+        else {
+            return;
+        };
+
+        let res = if job.size == CHUNK_SIZE {
+            job.output.copy_from_slice(&job.input);
+            Ok(())
+        } else {
+            decompressor.decompress(&job.input[..job.size], &mut job.output, scratch)
+                .map(|_| ())
+        };
+
+        if done.send((job, res)).is_err() {
+            return;
+        }
+    }
 }
 
 impl<'a> Read for OodleRead<'a> {
@@ -232,7 +792,7 @@ impl<'a> Read for OodleRead<'a> {
         let mut read = 0;
 
         while fill > 0 {
-            if self.offset == CHUNK_SIZE && !self.next()?{
+            if self.offset == CHUNK_SIZE && !self.next().map_err(io::Error::other)? {
                 return Ok(buf.len() - fill);
             }
 
@@ -265,83 +825,104 @@ impl<'a> Read for OodleRead<'a> {
 
 pub struct FilesIter<'a> {
     oodle: OodleRead<'a>,
+    bundle: Option<u64>,
     num_files: u32,
     current: u32,
 }
 
 impl<'a, 'b: 'a> FilesIter<'b> {
-    fn new(oodle: OodleRead<'b>, num_files: u32) -> Self {
+    fn new(oodle: OodleRead<'b>, bundle: Option<u64>, num_files: u32) -> Self {
         Self {
             oodle,
+            bundle,
             num_files,
             current: 0,
         }
     }
 
-    pub fn next_file(&'a mut self) -> io::Result<Option<Entry<'a, 'b>>> {
+    pub fn next_file(&'a mut self) -> Result<Option<Entry<'a, 'b>>> {
+        let offset = self.oodle.total_out as u64;
+        let bundle = self.bundle;
+        self.next_file_()
+            .map_err(|e| e.with_offset(offset).with_bundle(bundle))
+    }
+
+    fn next_file_(&'a mut self) -> Result<Option<Entry<'a, 'b>>> {
         if self.current < self.num_files {
             self.current += 1;
-            let ext = self.oodle.read_u64::<LE>()?;
-            let name = self.oodle.read_u64::<LE>()?;
-
-            let num_variants = self.oodle.read_u32::<LE>().unwrap();
+            let header = read_header(&mut self.oodle)?;
 
-            let mut failed = false;
-            for _ in 0..4 {
-                if 0 != self.oodle.read_u8().unwrap() {
-                    failed = true;
+            if self.current == self.num_files {
+                let size = self.oodle.total_out as u64 + header.total_size as u64;
+                if size != self.oodle.total_size {
+                    return Err(Error::layout(format!("last file ends at {size} instead of {}", self.oodle.total_size))
+                        .with_entry(header.ext, header.name));
                 }
             }
+            Ok(Some(Entry::new(EntryReader::Borrowed(&mut self.oodle), header, self.bundle)))
+        } else {
+            Ok(None)
+        }
+    }
+}
 
-            let mut variants = Vec::new();
-            let mut total_size = 0;
-            for _ in 0..num_variants {
-                let variant_kind = self.oodle.read_u32::<LE>()?;
-                let unknown1 = self.oodle.read_u8()?;
-                if unknown1 > 1 {
-                    failed = true;
-                }
-
-                if failed {
-                    eprintln!("{} {} - unexpected values",
-                        self.oodle.rdr.stream_position().unwrap(),
-                        self.oodle.total_out - 13);
-                    return Err(io::Error::new(io::ErrorKind::InvalidData, "unexpected value"));
-                }
+struct FileHeader {
+    ext: u64,
+    name: u64,
+    variants: Vec<Variant>,
+    total_size: u32,
+}
 
-                let file_size = self.oodle.read_u32::<LE>()?;
-                let unknown2 = self.oodle.read_u8()?;
-                assert_eq!(1, unknown2);
-                let tail_size = self.oodle.read_u32::<LE>()?;
+fn read_header(rdr: &mut OodleRead<'_>) -> Result<FileHeader> {
+    let ext = rdr.read_u64::<LE>()?;
+    let name = rdr.read_u64::<LE>()?;
 
-                total_size += file_size + tail_size;
+    let num_variants = rdr.read_u32::<LE>()?;
 
-                variants.push(Variant {
-                    kind: variant_kind,
-                    unknown1,
-                    body_size: file_size,
-                    unknown2,
-                    tail_size,
-                })
-            }
+    let padding = rdr.read_u32::<LE>()?;
+    if padding != 0 {
+        return Err(Error::layout(format!("non-zero header padding {padding:08x}"))
+            .with_entry(ext, name));
+    }
 
-            if self.current == self.num_files {
-                let size = self.oodle.total_out as u64 + total_size as u64;
-                assert_eq!(size, self.oodle.total_size);
-            }
-            Ok(Some(Entry {
-                rdr: &mut self.oodle,
-                variants,
-                remaining: total_size as usize,
-                ext,
-                name,
-            }))
-        } else {
-            Ok(None)
+    let mut variants = Vec::new();
+    let mut total_size = 0_u32;
+    for _ in 0..num_variants {
+        let variant_kind = rdr.read_u32::<LE>()?;
+        let unknown1 = rdr.read_u8()?;
+        let file_size = rdr.read_u32::<LE>()?;
+        let unknown2 = rdr.read_u8()?;
+        let tail_size = rdr.read_u32::<LE>()?;
+        if unknown1 > 1 || unknown2 != 1 {
+            return Err(Error::layout(format!("unexpected variant values {unknown1} {unknown2}"))
+                .with_entry(ext, name));
         }
+
+        let Some(size) = total_size.checked_add(file_size)
+            .and_then(|size| size.checked_add(tail_size))
+        else {
+            return Err(Error::layout("file size overflows u32").with_entry(ext, name));
+        };
+        total_size = size;
+
+        variants.push(Variant {
+            kind: variant_kind,
+            unknown1,
+            body_size: file_size,
+            unknown2,
+            tail_size,
+        })
     }
+
+    Ok(FileHeader {
+        ext,
+        name,
+        variants,
+        total_size,
+    })
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct Variant {
     pub kind: u32,
     pub unknown1: u8,
@@ -350,31 +931,482 @@ pub struct Variant {
     pub tail_size: u32,
 }
 
+enum EntryReader<'a, 'b: 'a> {
+    Borrowed(&'a mut OodleRead<'b>),
+    Owned(Box<OodleRead<'b>>),
+}
+
 pub struct Entry<'a, 'b: 'a> {
-    rdr: &'a mut OodleRead<'b>,
+    rdr: EntryReader<'a, 'b>,
     variants: Vec<Variant>,
     remaining: usize,
+    total: usize,
+    offset: u64,
+    pub bundle: Option<u64>,
     pub ext: u64,
     pub name: u64,
 }
 
 impl<'a, 'b: 'a> Entry<'a, 'b> {
+    fn new(mut rdr: EntryReader<'a, 'b>, header: FileHeader, bundle: Option<u64>) -> Self {
+        let offset = match &mut rdr {
+            EntryReader::Borrowed(rdr) => rdr.total_out,
+            EntryReader::Owned(rdr) => rdr.total_out,
+        };
+        Self {
+            rdr,
+            variants: header.variants,
+            remaining: header.total_size as usize,
+            total: header.total_size as usize,
+            offset: offset as u64,
+            bundle,
+            ext: header.ext,
+            name: header.name,
+        }
+    }
+
+    fn rdr(&mut self) -> &mut OodleRead<'b> {
+        match &mut self.rdr {
+            EntryReader::Borrowed(rdr) => rdr,
+            EntryReader::Owned(rdr) => rdr,
+        }
+    }
+
     pub fn variants(&self) -> &[Variant] {
         &self.variants
     }
+
+    /// Current offset into the decompressed bundle stream.
+    pub fn position(&self) -> u64 {
+        self.offset + (self.total - self.remaining) as u64
+    }
+
+    /// Stop decompressing chunks past the end of this file ahead of time.
+    ///
+    /// For the last file wanted from a bundle read with
+    /// [`BundleFd::files_pipelined`].
+    pub fn stop_read_ahead(&mut self) {
+        let end = self.offset + self.total as u64;
+        if let Some(pipeline) = &mut self.rdr().pipeline {
+            pipeline.limit = end.div_ceil(CHUNK_SIZE as u64) as u32;
+        }
+    }
+
+    /// Skip the unread rest of the file.
+    ///
+    /// Chunks that only hold skipped bytes are seeked past in the bundle
+    /// without being decompressed.
+    pub fn skip(&mut self) -> Result<()> {
+        let end = self.position() + self.remaining as u64;
+        self.remaining = 0;
+        self.rdr().seek_to(end)
+    }
 }
 
 impl<'a, 'b: 'a> Read for Entry<'a, 'b> {
     fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
         let copy = self.remaining.min(buf.len());
-        self.remaining -= copy;
-        self.rdr.read(&mut buf[..copy])
+        let read = self.rdr().read(&mut buf[..copy])?;
+        self.remaining -= read;
+        Ok(read)
     }
 }
 
 impl<'a, 'b: 'a> Drop for Entry<'a, 'b> {
     fn drop(&mut self) {
-        // TODO add empty method
-        io::copy(&mut self.rdr.take(self.remaining as u64), &mut io::sink()).unwrap();
+        // errors resurface on the next read from the bundle
+        if self.remaining > 0 {
+            let _ = self.skip();
+        }
+    }
+}
+
+/// Writer for bundles with uncompressed chunks.
+///
+/// Every chunk is stored at full `CHUNK_SIZE`, which the reader copies through
+/// without decompressing. No compressor is needed to write a bundle and no
+/// decompressor is needed to read it back.
+pub struct BundleWriter {
+    version: u16,
+    files: Vec<PendingFile>,
+}
+
+impl BundleWriter {
+    pub fn new(version: u16) -> Result<Self> {
+        if version != 7 && version != 8 {
+            return Err(ErrorKind::UnknownVersion(version).into());
+        }
+
+        Ok(Self {
+            version,
+            files: Vec::new(),
+        })
+    }
+
+    pub fn add_file(&mut self, ext: u64, name: u64, mode: u32) -> &mut PendingFile {
+        self.files.push(PendingFile {
+            ext,
+            name,
+            mode,
+            variants: Vec::new(),
+            data: Vec::new(),
+        });
+        self.files.last_mut().unwrap()
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> Result<u64> {
+        let mut stream = Vec::new();
+        for file in &self.files {
+            stream.write_u64::<LE>(file.ext)?;
+            stream.write_u64::<LE>(file.name)?;
+            stream.write_u32::<LE>(file.variants.len() as u32)?;
+            stream.write_u32::<LE>(0)?;
+            for variant in &file.variants {
+                stream.write_u32::<LE>(variant.kind)?;
+                stream.write_u8(variant.unknown1)?;
+                stream.write_u32::<LE>(variant.body_size)?;
+                stream.write_u8(variant.unknown2)?;
+                stream.write_u32::<LE>(variant.tail_size)?;
+            }
+            stream.extend_from_slice(&file.data);
+        }
+        let Ok(total_size) = u32::try_from(stream.len()) else {
+            return Err(Error::layout(format!("bundle size {} does not fit in u32", stream.len())));
+        };
+        let num_chunks = stream.len().div_ceil(CHUNK_SIZE);
+        stream.resize(num_chunks * CHUNK_SIZE, 0);
+
+        let mut head = Vec::with_capacity(0x1000);
+        let [v0, v1] = self.version.to_le_bytes();
+        head.extend_from_slice(&[v0, v1, 0x00, 0xF0, 0x03, 0x00, 0x00, 0x00]);
+        head.write_u32::<LE>(self.files.len() as u32)?;
+        head.extend_from_slice(&[0; 256]);
+        for file in &self.files {
+            head.write_u64::<LE>(file.ext)?;
+            head.write_u64::<LE>(file.name)?;
+            head.write_u32::<LE>(file.mode)?;
+        }
+
+        head.write_u32::<LE>(num_chunks as u32)?;
+        for _ in 0..num_chunks {
+            head.write_u32::<LE>(CHUNK_SIZE as u32)?;
+        }
+        let padding = align_16(head.len() as u64) as usize;
+        head.extend_from_slice(&[0; 16][..padding]);
+        head.write_u32::<LE>(total_size)?;
+        head.write_u32::<LE>(0)?;
+        out.write_all(&head)?;
+
+        let mut pos = head.len() as u64;
+        for chunk in stream.chunks_exact(CHUNK_SIZE) {
+            out.write_all(&(CHUNK_SIZE as u32).to_le_bytes())?;
+            let padding = align_16(pos + 4) as usize;
+            out.write_all(&[0; 16][..padding])?;
+            out.write_all(chunk)?;
+            pos += (4 + padding + CHUNK_SIZE) as u64;
+        }
+
+        Ok(pos)
+    }
+}
+
+pub struct PendingFile {
+    ext: u64,
+    name: u64,
+    mode: u32,
+    variants: Vec<Variant>,
+    data: Vec<u8>,
+}
+
+impl PendingFile {
+    pub fn variant(
+        &mut self,
+        kind: u32,
+        unknown1: u8,
+        body: &[u8],
+        tail: &[u8],
+    ) -> &mut Self {
+        self.variants.push(Variant {
+            kind,
+            unknown1,
+            body_size: body.len() as u32,
+            unknown2: 1,
+            tail_size: tail.len() as u32,
+        });
+        self.data.extend_from_slice(body);
+        self.data.extend_from_slice(tail);
+        self
+    }
+}
+
+/// Directory of bundles for tests, removed on drop.
+#[cfg(test)]
+pub(crate) struct TestBundles {
+    pub dir: PathBuf,
+}
+
+#[cfg(test)]
+impl TestBundles {
+    /// Empty directory `limn-{name}-{pid}` in the temp directory.
+    pub fn new(name: &str) -> Self {
+        let dir = std::env::temp_dir().join(format!("limn-{name}-{}", std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        Self { dir }
+    }
+
+    /// Write bundle `hash` holding `lua` files with the given names and bodies.
+    pub fn write(&self, hash: u64, files: &[(u64, &[u8])]) -> PathBuf {
+        let mut writer = BundleWriter::new(8).unwrap();
+        for (name, body) in files {
+            writer.add_file(0xa14e8dfa2cd117e2, *name, 0)
+                .variant(0, 0, body, b"");
+        }
+        let path = self.dir.join(format!("{hash:016x}"));
+        writer.write(&mut File::create(&path).unwrap()).unwrap();
+        path
+    }
+}
+
+#[cfg(test)]
+impl Drop for TestBundles {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.dir);
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::decompress::Passthrough;
+
+    #[test]
+    fn write_index() {
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"body", b"");
+        writer.add_file(0xad9c6d9ed1e5e77a, 2, 3)
+            .variant(0, 0, &[7; CHUNK_SIZE], b"tail")
+            .variant(1, 1, b"", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+
+        let mut rdr = io::Cursor::new(buf);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        assert_eq!(2, bundle.num_files);
+        assert_eq!(bundle.index().unwrap().collect::<Vec<_>>(), [
+            IndexEntry { ext: 0xa14e8dfa2cd117e2, name: 1, mode: 0 },
+            IndexEntry { ext: 0xad9c6d9ed1e5e77a, name: 2, mode: 3 },
+        ]);
+    }
+
+    fn test_bundle() -> Vec<u8> {
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"body", b"");
+        writer.add_file(0xad9c6d9ed1e5e77a, 2, 0)
+            .variant(0, 0, &[7; CHUNK_SIZE * 2], b"tail");
+        writer.add_file(0xa14e8dfa2cd117e2, 3, 0)
+            .variant(0, 0, b"last", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+        buf
+    }
+
+    fn read_all(files: &mut FilesIter<'_>) -> Vec<(u64, Vec<u8>)> {
+        let mut out = Vec::new();
+        while let Some(mut file) = files.next_file().unwrap() {
+            let name = file.name;
+            let mut data = Vec::new();
+            if name == 2 {
+                file.skip().unwrap();
+            } else {
+                file.read_to_end(&mut data).unwrap();
+            }
+            out.push((name, data));
+        }
+        out
+    }
+
+    #[test]
+    fn read_files() {
+        let expected = [(1, b"body".to_vec()), (2, Vec::new()), (3, b"last".to_vec())];
+        let mut rdr = io::Cursor::new(test_bundle());
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+
+        let mut files = bundle.files(&Passthrough, &mut scratch).unwrap();
+        assert_eq!(read_all(&mut files), expected);
+
+        let mut workers = ChunkWorkers::new(2);
+        for _ in 0..2 {
+            let files = bundle.files_pipelined(&Passthrough, &mut scratch, &mut workers, 2, |files| {
+                Ok(read_all(files))
+            }).unwrap();
+            assert_eq!(files, expected);
+            // chunk buffers are kept for the next bundle
+            assert!(!workers.jobs.is_empty());
+        }
+
+        let mut file = bundle.open_file(&Passthrough, &mut scratch, 0xa14e8dfa2cd117e2, 3)
+            .unwrap()
+            .unwrap();
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"last");
+    }
+
+    #[test]
+    fn open_files() {
+        let data = (0..CHUNK_SIZE * 5).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"first", b"");
+        writer.add_file(0xad9c6d9ed1e5e77a, 2, 0)
+            .variant(0, 0, &data, b"");
+        writer.add_file(0xa14e8dfa2cd117e2, 3, 0)
+            .variant(0, 0, b"last", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+        let mut rdr = io::Cursor::new(buf);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+
+        assert!(bundle.open_file(&Passthrough, &mut scratch, 0xa14e8dfa2cd117e2, 2).unwrap().is_none());
+        for (ext, name, expected) in [
+            (0xa14e8dfa2cd117e2, 3, &b"last"[..]),
+            (0xa14e8dfa2cd117e2, 1, b"first"),
+            (0xad9c6d9ed1e5e77a, 2, &data),
+        ] {
+            let mut read = Vec::new();
+            bundle.open_file(&Passthrough, &mut scratch, ext, name).unwrap()
+                .unwrap()
+                .read_to_end(&mut read)
+                .unwrap();
+            assert!(read == expected, "file {name}");
+        }
+
+        let mut all = Vec::new();
+        bundle.reader(&Passthrough, &mut scratch).unwrap().read_to_end(&mut all).unwrap();
+        // back into a loaded chunk after seeking far past it
+        let mut rdr = bundle.reader(&Passthrough, &mut scratch).unwrap();
+        let mut read = vec![0; CHUNK_SIZE];
+        let start = CHUNK_SIZE as u64 + 10;
+        rdr.seek_to(start).unwrap();
+        rdr.read_exact(&mut read[..1]).unwrap();
+        rdr.seek_to(CHUNK_SIZE as u64 * 3).unwrap();
+        rdr.seek_to(start).unwrap();
+        rdr.read_exact(&mut read).unwrap();
+        assert!(read == all[start as usize..start as usize + CHUNK_SIZE]);
+    }
+
+    // counts bytes read from the bundle file
+    struct CountRead<R>(R, usize);
+
+    impl<R: Read> Read for CountRead<R> {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.0.read(buf)?;
+            self.1 += read;
+            Ok(read)
+        }
+    }
+
+    impl<R: Seek> Seek for CountRead<R> {
+        fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+            self.0.seek(pos)
+        }
+    }
+
+    #[test]
+    fn skip_files() {
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xad9c6d9ed1e5e77a, 1, 0)
+            .variant(0, 0, &[7; CHUNK_SIZE * 4], b"tail");
+        writer.add_file(0xa14e8dfa2cd117e2, 2, 0)
+            .variant(0, 0, b"last", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+        let size = buf.len();
+
+        let mut rdr = CountRead(io::Cursor::new(buf), 0);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = bundle.files(&Passthrough, &mut scratch).unwrap();
+        let mut file = files.next_file().unwrap().unwrap();
+        let end = file.position() + CHUNK_SIZE as u64 * 4 + 4;
+        file.skip().unwrap();
+        assert_eq!(file.position(), end);
+        drop(file);
+
+        let mut file = files.next_file().unwrap().unwrap();
+        let mut data = Vec::new();
+        file.read_to_end(&mut data).unwrap();
+        assert_eq!(data, b"last");
+        drop(file);
+        drop(files);
+        drop(bundle);
+        // only the first and last chunks were read
+        assert!(rdr.1 < size / 2, "read {} of {size} bytes", rdr.1);
+    }
+
+    #[test]
+    fn stop_read_ahead() {
+        let data = (0..CHUNK_SIZE * 3).map(|i| (i % 251) as u8).collect::<Vec<_>>();
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xad9c6d9ed1e5e77a, 1, 0)
+            .variant(0, 0, &data, b"");
+        writer.add_file(0xad9c6d9ed1e5e77a, 2, 0)
+            .variant(0, 0, &[7; CHUNK_SIZE * 12], b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+        let size = buf.len();
+
+        let mut rdr = CountRead(io::Cursor::new(buf), 0);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut workers = ChunkWorkers::new(2);
+        let read = bundle.files_pipelined(&Passthrough, &mut scratch, &mut workers, 2, |files| {
+            let mut file = files.next_file()?.unwrap();
+            file.stop_read_ahead();
+            let mut read = Vec::new();
+            file.read_to_end(&mut read)?;
+            Ok(read)
+        }).unwrap();
+        assert!(read == data);
+        drop(bundle);
+        // the second file is not read past the chunk the first one ends in
+        assert!(rdr.1 < CHUNK_SIZE * 5, "read {} of {size} bytes", rdr.1);
+    }
+
+    #[test]
+    fn verify() {
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"body", b"");
+        writer.add_file(0xeac0b497876adedf, 2, 0)
+            .variant(0, 0, b"data/00/0000000000000000000000", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+
+        let mut scratch = Vec::new();
+        let mut rdr = io::Cursor::new(buf.clone());
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        assert!(bundle.verify(&Passthrough, &mut scratch, None).unwrap().is_empty());
+
+        let problems = bundle.verify(&Passthrough, &mut scratch, Some(Path::new("missing"))).unwrap();
+        assert_eq!(problems.len(), 1);
+        assert!(matches!(problems[0].kind(), ErrorKind::MissingResource(_)));
+
+        // swap index entries and append trailing data
+        let index = 12 + 256;
+        let (first, second) = buf[index..index + 40].split_at_mut(20);
+        first.swap_with_slice(second);
+        buf.push(0);
+        let mut rdr = io::Cursor::new(buf);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let problems = bundle.verify(&Passthrough, &mut scratch, None).unwrap();
+        assert_eq!(problems.len(), 3, "{problems:?}");
+        assert!(problems[0].to_string().contains("chunks end at"));
+        assert!(problems[1].to_string().contains("index entry 0"));
     }
 }
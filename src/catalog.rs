@@ -0,0 +1,327 @@
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::io::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use byteorder::ReadBytesExt;
+use byteorder::WriteBytesExt;
+use byteorder::LE;
+
+use crate::bundle;
+use crate::bundle::BundleFd;
+use crate::bundle::Variant;
+use crate::decompress::Decompressor;
+use crate::error::ensure;
+use crate::error::Error;
+use crate::error::Result;
+
+const MAGIC: [u8; 8] = *b"LIMNCAT\0";
+const VERSION: u32 = 1;
+
+/// Every file of every bundle in a bundle directory.
+///
+/// Bundles are only reread by [`refresh`](Catalog::refresh) when their
+/// modified time or size changed.
+#[derive(Clone, Default)]
+pub struct Catalog {
+    bundles: Vec<CatalogBundle>,
+    // (ext, name) -> (bundle, file) indices
+    lookup: HashMap<(u64, u64), Vec<(u32, u32)>>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogBundle {
+    pub hash: u64,
+    // nanoseconds since the unix epoch
+    pub mtime: u64,
+    pub size: u64,
+    pub files: Vec<CatalogFile>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CatalogFile {
+    pub ext: u64,
+    pub name: u64,
+    pub mode: u32,
+    pub variants: Vec<Variant>,
+}
+
+/// Bundle counts from [`Catalog::refresh`].
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Refresh {
+    pub added: u32,
+    pub updated: u32,
+    pub removed: u32,
+    pub unchanged: u32,
+}
+
+impl Catalog {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let mut rdr = io::BufReader::new(File::open(path)?);
+        Self::read(&mut rdr)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // write next to the catalog first so an interrupted save keeps the old one
+        let tmp = path.with_extension("tmp");
+        let mut out = io::BufWriter::new(File::create(&tmp)?);
+        self.write(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+
+    pub fn read(rdr: &mut dyn Read) -> Result<Self> {
+        let mut magic = [0; 8];
+        rdr.read_exact(&mut magic)?;
+        ensure!(magic == MAGIC, "not a catalog file");
+        let version = rdr.read_u32::<LE>()?;
+        ensure!(version == VERSION, "unsupported catalog version {version}");
+
+        let num_bundles = rdr.read_u32::<LE>()?;
+        let mut bundles = Vec::with_capacity(num_bundles.min(0x10000) as usize);
+        for _ in 0..num_bundles {
+            let hash = rdr.read_u64::<LE>()?;
+            let mtime = rdr.read_u64::<LE>()?;
+            let size = rdr.read_u64::<LE>()?;
+            let num_files = rdr.read_u32::<LE>()?;
+            let mut files = Vec::with_capacity(num_files.min(0x10000) as usize);
+            for _ in 0..num_files {
+                let ext = rdr.read_u64::<LE>()?;
+                let name = rdr.read_u64::<LE>()?;
+                let mode = rdr.read_u32::<LE>()?;
+                let num_variants = rdr.read_u32::<LE>()?;
+                let mut variants = Vec::with_capacity(num_variants.min(0x10000) as usize);
+                for _ in 0..num_variants {
+                    variants.push(Variant {
+                        kind: rdr.read_u32::<LE>()?,
+                        unknown1: rdr.read_u8()?,
+                        body_size: rdr.read_u32::<LE>()?,
+                        unknown2: rdr.read_u8()?,
+                        tail_size: rdr.read_u32::<LE>()?,
+                    });
+                }
+                files.push(CatalogFile {
+                    ext,
+                    name,
+                    mode,
+                    variants,
+                });
+            }
+            bundles.push(CatalogBundle {
+                hash,
+                mtime,
+                size,
+                files,
+            });
+        }
+
+        let mut catalog = Self {
+            bundles,
+            lookup: HashMap::new(),
+        };
+        catalog.build_lookup();
+        Ok(catalog)
+    }
+
+    pub fn write(&self, out: &mut dyn Write) -> Result<()> {
+        out.write_all(&MAGIC)?;
+        out.write_u32::<LE>(VERSION)?;
+        out.write_u32::<LE>(self.bundles.len() as u32)?;
+        for bundle in &self.bundles {
+            out.write_u64::<LE>(bundle.hash)?;
+            out.write_u64::<LE>(bundle.mtime)?;
+            out.write_u64::<LE>(bundle.size)?;
+            out.write_u32::<LE>(bundle.files.len() as u32)?;
+            for file in &bundle.files {
+                out.write_u64::<LE>(file.ext)?;
+                out.write_u64::<LE>(file.name)?;
+                out.write_u32::<LE>(file.mode)?;
+                out.write_u32::<LE>(file.variants.len() as u32)?;
+                for variant in &file.variants {
+                    out.write_u32::<LE>(variant.kind)?;
+                    out.write_u8(variant.unknown1)?;
+                    out.write_u32::<LE>(variant.body_size)?;
+                    out.write_u8(variant.unknown2)?;
+                    out.write_u32::<LE>(variant.tail_size)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Bring the catalog up to date with the bundles in `dir`.
+    ///
+    /// New and changed bundles are read on `num_threads` threads. Bundles
+    /// no longer in `dir` are dropped. The catalog is unchanged on error.
+    pub fn refresh(
+        &mut self,
+        dir: &Path,
+        decompressor: &dyn Decompressor,
+        num_threads: usize,
+    ) -> Result<Refresh> {
+        let mut refresh = Refresh::default();
+        let mut old = self.bundles.iter()
+            .enumerate()
+            .map(|(i, bundle)| (bundle.hash, i))
+            .collect::<HashMap<_, _>>();
+
+        let mut unchanged = vec![false; self.bundles.len()];
+        // bundles to read again with their (mtime, size)
+        let mut stale = Vec::new();
+        let mut stale_meta = Vec::new();
+        for (path, hash) in bundle::list_bundles(dir)? {
+            let meta = fs::metadata(&path)?;
+            let mtime = meta.modified().ok()
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map(|time| time.as_nanos() as u64)
+                .unwrap_or(0);
+            let size = meta.len();
+
+            match old.remove(&hash).map(|i| (i, &self.bundles[i])) {
+                Some((i, bundle)) if bundle.mtime == mtime && bundle.size == size => {
+                    refresh.unchanged += 1;
+                    unchanged[i] = true;
+                }
+                Some(_) => {
+                    refresh.updated += 1;
+                    stale.push((path, hash));
+                    stale_meta.push((mtime, size));
+                }
+                None => {
+                    refresh.added += 1;
+                    stale.push((path, hash));
+                    stale_meta.push((mtime, size));
+                }
+            }
+        }
+        refresh.removed = old.len() as u32;
+
+        let mut bundles = bundle::read_parallel(&stale, num_threads, |i, bundle, scratch| {
+            let (mtime, size) = stale_meta[i];
+            Ok(CatalogBundle {
+                hash: stale[i].1,
+                mtime,
+                size,
+                files: read_bundle(bundle, decompressor, scratch)?,
+            })
+        })?;
+        bundles.extend(std::mem::take(&mut self.bundles)
+            .into_iter()
+            .zip(unchanged)
+            .filter_map(|(bundle, unchanged)| unchanged.then_some(bundle)));
+        bundles.sort_unstable_by_key(|bundle| bundle.hash);
+        self.bundles = bundles;
+        self.build_lookup();
+        Ok(refresh)
+    }
+
+    pub fn bundles(&self) -> &[CatalogBundle] {
+        &self.bundles
+    }
+
+    /// Forget a bundle so the next refresh reads it again.
+    pub fn remove(&mut self, hash: u64) -> bool {
+        let len = self.bundles.len();
+        self.bundles.retain(|bundle| bundle.hash != hash);
+        if self.bundles.len() == len {
+            return false;
+        }
+        self.build_lookup();
+        true
+    }
+
+    /// Bundles containing a file.
+    pub fn find(&self, ext: u64, name: u64) -> impl Iterator<Item = (&CatalogBundle, &CatalogFile)> {
+        self.lookup.get(&(ext, name))
+            .into_iter()
+            .flatten()
+            .map(|&(bundle, file)| {
+                let bundle = &self.bundles[bundle as usize];
+                (bundle, &bundle.files[file as usize])
+            })
+    }
+
+    fn build_lookup(&mut self) {
+        self.lookup.clear();
+        for (bundle_i, bundle) in self.bundles.iter().enumerate() {
+            for (file_i, file) in bundle.files.iter().enumerate() {
+                self.lookup.entry((file.ext, file.name))
+                    .or_default()
+                    .push((bundle_i as u32, file_i as u32));
+            }
+        }
+    }
+}
+
+fn read_bundle(
+    bundle: &mut BundleFd<'_>,
+    decompressor: &dyn Decompressor,
+    scratch: &mut Vec<u8>,
+) -> Result<Vec<CatalogFile>> {
+    let hash = bundle.name;
+    let index = bundle.index()?.collect::<Vec<_>>();
+    ensure!(index.len() == bundle.num_files as usize, "bundle index ends after {} files", index.len());
+
+    let locations = bundle.locations(decompressor, scratch)?;
+    let mut files = Vec::with_capacity(index.len());
+    for (entry, location) in index.into_iter().zip(locations) {
+        if entry.ext != location.ext || entry.name != location.name {
+            return Err(Error::layout("bundle index does not match file order").with_bundle(hash));
+        }
+        files.push(CatalogFile {
+            ext: entry.ext,
+            name: entry.name,
+            mode: entry.mode,
+            variants: location.variants.clone(),
+        });
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TestBundles;
+    use crate::decompress::Passthrough;
+
+    #[test]
+    fn refresh_and_reload() {
+        let bundles = TestBundles::new("catalog");
+        bundles.write(1, &[(10, b"body"), (11, b"body")]);
+        let second = bundles.write(2, &[(11, b"body")]);
+        let dir = &bundles.dir;
+
+        let mut catalog = Catalog::new();
+        let refresh = catalog.refresh(dir, &Passthrough, 2).unwrap();
+        assert_eq!(refresh, Refresh { added: 2, ..Refresh::default() });
+        let bundles = catalog.find(0xa14e8dfa2cd117e2, 11)
+            .map(|(bundle, _)| bundle.hash)
+            .collect::<Vec<_>>();
+        assert_eq!(bundles, [1, 2]);
+
+        let path = dir.join("catalog.bin");
+        catalog.save(&path).unwrap();
+        let mut catalog = Catalog::load(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(catalog.bundles()[0].files[0].variants[0].body_size, 4);
+
+        fs::remove_file(second).unwrap();
+        let refresh = catalog.refresh(dir, &Passthrough, 1).unwrap();
+        assert_eq!(refresh, Refresh { removed: 1, unchanged: 1, ..Refresh::default() });
+        assert_eq!(catalog.find(0xa14e8dfa2cd117e2, 11).count(), 1);
+
+        // counts from a corrupt catalog are not trusted for allocations
+        let mut corrupt = MAGIC.to_vec();
+        corrupt.extend(VERSION.to_le_bytes());
+        corrupt.extend(u32::MAX.to_le_bytes());
+        assert!(Catalog::read(&mut &corrupt[..]).is_err());
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use limn::catalog::Catalog;
+use limn::crack;
+use limn::crack::Case;
+use limn::crack::Template;
+use limn::hash;
+
+use super::CmdResult;
+
+// more candidates than this need --max-candidates
+const DEFAULT_MAX_CANDIDATES: u64 = 10_000_000_000;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe crack [OPTIONS] [TEMPLATE]...");
+    println!();
+    println!("Find names of unknown files by hashing names generated from templates.");
+    println!("Names found are appended to the output in dictionary format.");
+    println!();
+    println!("ARGS:");
+    println!("    <TEMPLATE>  Path with placeholders: {{WORDLIST}}, {{a|b|c}} or {{0..99}}.");
+    println!("                {{00..99}} pads numbers to the width of the first.");
+    println!();
+    println!("OPTIONS:");
+    println!("        --hashes <PATH>       Files to name from `--dump-hashes`. Default is `hashes.bin`.");
+    println!("    -i, --input <PATH>        Scan a directory of bundles for files to name instead.");
+    println!("    -w, --wordlist <[NAME=]PATH>");
+    println!("                              Wordlist for {{NAME}}. Default NAME is the file stem.");
+    println!("        --templates <PATH>    Read templates from PATH, one per line.");
+    println!("        --case <CASES>        Comma delimited spellings of words (lower, upper, title).");
+    println!("    -e, --ext <EXT>           Only name files with extension.");
+    println!("        --max-candidates <N>  Refuse to hash more than N candidates. Default is {DEFAULT_MAX_CANDIDATES}.");
+    println!("        --dict <PATH>         Load dictionary. Default is `dictionary.txt`.");
+    println!("        --oodle <PATH>        Load the Oodle library from PATH.");
+    println!("    -o, --output <PATH>       Names found. Default is `cracked.txt`.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    let mut hashes_path = None;
+    let mut input = None;
+    let mut wordlist_paths = Vec::new();
+    let mut patterns = Vec::new();
+    let mut case = Case::default();
+    let mut exts = HashSet::new();
+    let mut dictionary = Vec::new();
+    let mut oodle = None;
+    let mut output = PathBuf::from("cracked.txt");
+    let mut max_candidates = DEFAULT_MAX_CANDIDATES;
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "--hashes" => hashes_path = Some(PathBuf::from(super::param(args, "--hashes"))),
+            "-i" | "--input" => input = Some(PathBuf::from(super::param(args, "--input"))),
+            "-w" | "--wordlist" => wordlist_paths.push(super::param_str(args, "--wordlist")),
+            "--templates" => {
+                let path = PathBuf::from(super::param(args, "--templates"));
+                let Ok(text) = fs::read_to_string(&path) else {
+                    return Err(format!("failed to read templates \"{}\"", path.display()).into());
+                };
+                patterns.extend(text.lines()
+                    .map(str::trim)
+                    .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                    .map(str::to_string));
+            }
+            "--case" => {
+                for name in super::param_str(args, "--case").split(",") {
+                    match name {
+                        "lower" => case.lower = true,
+                        "upper" => case.upper = true,
+                        "title" => case.title = true,
+                        _ => {
+                            eprintln!("ERROR: unknown case {name:?}");
+                            std::process::exit(1);
+                        }
+                    }
+                }
+            }
+            "-e" | "--ext" => {
+                let ext = super::param_str(args, "--ext");
+                exts.insert(hash::murmur_hash64a(ext.as_bytes(), 0));
+            }
+            "--max-candidates" => {
+                let param = super::param_str(args, "--max-candidates");
+                let Ok(max) = param.parse() else {
+                    eprintln!("ERROR: expected a number of candidates, got {param:?}");
+                    std::process::exit(1);
+                };
+                max_candidates = max;
+            }
+            "--dict" => dictionary.push(PathBuf::from(super::param(args, "--dict"))),
+            "--oodle" => oodle = Some(PathBuf::from(super::param(args, "--oodle"))),
+            "-o" | "--output" => output = PathBuf::from(super::param(args, "--output")),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            opt if opt.starts_with("-") => {
+                eprintln!("ERROR: unknown option {opt}");
+                std::process::exit(1);
+            }
+            _ => patterns.push(arg.into_string().map_err(|_| "invalid UTF-8 in template")?),
+        }
+    }
+
+    if patterns.is_empty() {
+        print_help();
+        std::process::exit(1);
+    }
+
+    let mut wordlists = HashMap::new();
+    for param in &wordlist_paths {
+        let (name, path) = match param.split_once('=') {
+            Some((name, path)) => (name.to_string(), Path::new(path)),
+            None => {
+                let path = Path::new(param);
+                let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or(param);
+                (stem.to_string(), path)
+            }
+        };
+        let Ok(text) = fs::read_to_string(path) else {
+            return Err(format!("failed to read wordlist \"{}\"", path.display()).into());
+        };
+        let words = text.lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+        wordlists.insert(name, words);
+    }
+    let templates = patterns.iter()
+        .map(|pattern| Template::parse(pattern, &wordlists, case))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // (ext, name) of every file to name
+    let mut files = if let Some(input) = input {
+        let (input, darktide_path) = super::bundle_dir(Some(input));
+        let oodle = match crate::load_oodle(oodle, &input, darktide_path.as_ref()) {
+            Ok(oodle) => oodle,
+            Err(e) => {
+                eprintln!("{e}");
+                std::process::exit(1);
+            }
+        };
+        let mut catalog = Catalog::new();
+        catalog.refresh(&input, &oodle, crate::num_threads())?;
+        catalog.bundles().iter()
+            .flat_map(|bundle| bundle.files.iter().map(|file| (file.ext, file.name)))
+            .collect::<HashSet<_>>()
+    } else {
+        let path = hashes_path.unwrap_or_else(|| PathBuf::from("hashes.bin"));
+        let Ok(bin) = fs::read(&path) else {
+            return Err(format!("failed to read \"{}\", run with --dump-hashes first", path.display()).into());
+        };
+        bin.chunks_exact(16)
+            .map(|pair| (
+                u64::from_le_bytes(pair[..8].try_into().unwrap()),
+                u64::from_le_bytes(pair[8..].try_into().unwrap()),
+            ))
+            .collect::<HashSet<_>>()
+    };
+    if !exts.is_empty() {
+        files.retain(|(ext, _)| exts.contains(ext));
+    }
+
+    let dictionary = super::load_dictionary(&dictionary)?;
+    let targets = files.iter()
+        .map(|(_, name)| *name)
+        .filter(|name| !dictionary.contains_key(&(*name).into()))
+        .collect::<HashSet<_>>();
+
+    // `Template::len` saturates
+    let count = |len: u64| match len {
+        u64::MAX => "too many".to_string(),
+        len => len.to_string(),
+    };
+    for (pattern, template) in patterns.iter().zip(&templates) {
+        println!("{:>20}  {pattern}", count(template.len()));
+    }
+    let total = templates.iter().map(Template::len).fold(0, u64::saturating_add);
+    println!("{} unknown names, {} candidates", targets.len(), count(total));
+    if total > max_candidates {
+        return Err(format!("more than {max_candidates} candidates, narrow the templates or raise --max-candidates").into());
+    }
+
+    let start = Instant::now();
+    let found = crack::crack(&templates, &targets, crate::num_threads());
+    let elapsed = start.elapsed().as_secs_f64();
+
+    // names found by an earlier run are not appended again
+    let existing = fs::read_to_string(&output).unwrap_or_default();
+    let existing = existing.lines().collect::<HashSet<_>>();
+    let mut names = found.values()
+        .map(String::as_str)
+        .filter(|name| !existing.contains(name))
+        .collect::<Vec<_>>();
+    names.sort_unstable();
+    if !names.is_empty() {
+        let mut fd = OpenOptions::new().create(true).append(true).open(&output)?;
+        for name in &names {
+            writeln!(fd, "{name}")?;
+        }
+    }
+
+    // ext -> (files, known before, found now)
+    let mut coverage = BTreeMap::<String, (usize, usize, usize)>::new();
+    for (ext, name) in &files {
+        let ext = match hash::extension_name(*ext) {
+            Some(ext) => ext.to_string(),
+            None => format!("{ext:016x}"),
+        };
+        let entry = coverage.entry(ext).or_default();
+        entry.0 += 1;
+        if dictionary.contains_key(&(*name).into()) {
+            entry.1 += 1;
+        } else if found.contains_key(name) {
+            entry.2 += 1;
+        }
+    }
+    println!("{:<28}{:>10}{:>10}{:>10}{:>10}", "EXT", "FILES", "KNOWN", "FOUND", "COVERAGE");
+    for (ext, (num_files, known, found)) in &coverage {
+        let percent = (known + found) as f64 * 100.0 / *num_files as f64;
+        println!("{ext:<28}{num_files:>10}{known:>10}{found:>10}{percent:>9.1}%");
+    }
+
+    println!("{} names found in {elapsed:.2}s ({:.1}M/s)",
+        found.len(),
+        total as f64 / elapsed.max(1e-9) / 1e6);
+    if !names.is_empty() {
+        println!("{} new names appended to \"{}\"", names.len(), output.display());
+    }
+    Ok(())
+}
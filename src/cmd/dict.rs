@@ -0,0 +1,87 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use limn::dictionary;
+use limn::dictionary::Dictionary;
+
+use super::CmdResult;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe dict compile [OPTIONS]");
+    println!();
+    println!("Compile text dictionaries into `dictionary.bin`. It is loaded instead of");
+    println!("`dictionary.txt` and `learned.txt` until either changes. One compiled from");
+    println!("other dictionaries is only used with `--dict dictionary.bin`.");
+    println!();
+    println!("OPTIONS:");
+    println!("        --dict <PATH>         Text dictionary. Default is `dictionary.txt` and `learned.txt`.");
+    println!("    -o, --output <PATH>       Compiled dictionary. Default is `dictionary.bin`.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    match args.next().as_ref().and_then(|arg| arg.to_str()) {
+        Some("compile") => (),
+        Some("--help") => {
+            print_help();
+            return Ok(());
+        }
+        _ => {
+            print_help();
+            std::process::exit(1);
+        }
+    }
+
+    let mut sources = Vec::new();
+    let mut output = PathBuf::from(dictionary::COMPILED_FILE);
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "--dict" => sources.push(PathBuf::from(super::param(args, "--dict"))),
+            "-o" | "--output" => output = PathBuf::from(super::param(args, "--output")),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            _ => {
+                eprintln!("ERROR: unknown option {arg:?}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let start = Instant::now();
+    let mut dict = Dictionary::new();
+    if sources.is_empty() {
+        sources.extend(super::DEFAULT_DICTIONARIES.iter()
+            .map(PathBuf::from)
+            .filter(|path| path.exists()));
+    }
+    for path in &sources {
+        if dict.add_file(path).is_err() {
+            return Err(format!("failed to load dictionary \"{}\"", path.display()).into());
+        }
+    }
+    if dict.is_empty() {
+        return Err("no dictionary names to compile".into());
+    }
+
+    dict.save(&output)?;
+    println!("compiled {} names to \"{}\" in {:.2}s",
+        dict.len(),
+        output.display(),
+        start.elapsed().as_secs_f64());
+
+    // strings keys with these short hashes list every name
+    let collisions = dict.collisions();
+    if !collisions.is_empty() {
+        println!("{} short hashes are shared by several names:", collisions.len());
+    }
+    for (short, hashes) in collisions {
+        let names = hashes.iter()
+            .filter_map(|hash| dict.get(hash))
+            .collect::<Vec<_>>();
+        println!("{:08x}  {}", u32::from(short), names.join(", "));
+    }
+    Ok(())
+}
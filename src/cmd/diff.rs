@@ -0,0 +1,169 @@
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use limn::ExtractBuilder;
+use limn::bundle;
+use limn::bundle::BundleFd;
+use limn::diff::ChangeKind;
+use limn::diff::Snapshot;
+use limn::file;
+use limn::file::Pool;
+use limn::hash;
+use limn::read::ChunkReader;
+
+use super::CmdResult;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe diff [OPTIONS] <OLD_DIR> <NEW_DIR>");
+    println!();
+    println!("Compare files in two bundle directories by extension and name.");
+    println!();
+    println!("OPTIONS:");
+    println!("    -o, --output <PATH>       Extract added and modified files from NEW_DIR.");
+    println!("        --dict <PATH>         Load dictionary. Default is `dictionary.txt`.");
+    println!("        --oodle <PATH>        Load the Oodle library from PATH.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    let mut dirs = Vec::new();
+    let mut output = None;
+    let mut dictionary = Vec::new();
+    let mut oodle = None;
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "-o" | "--output" => output = Some(PathBuf::from(super::param(args, "--output"))),
+            "--dict" => dictionary.push(PathBuf::from(super::param(args, "--dict"))),
+            "--oodle" => oodle = Some(PathBuf::from(super::param(args, "--oodle"))),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            opt if opt.starts_with("-") => {
+                eprintln!("ERROR: unknown option {opt}");
+                std::process::exit(1);
+            }
+            _ => dirs.push(PathBuf::from(arg)),
+        }
+    }
+
+    let [old_dir, new_dir] = <[PathBuf; 2]>::try_from(dirs).unwrap_or_else(|_| {
+        print_help();
+        std::process::exit(1);
+    });
+
+    let dictionary = super::load_dictionary(&dictionary)?;
+    let oodle = match crate::load_oodle(oodle, &new_dir, None) {
+        Ok(oodle) => oodle,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let start = Instant::now();
+    let num_threads = crate::num_threads();
+    let old = Snapshot::scan(&old_dir, &oodle, num_threads)?;
+    let new = Snapshot::scan(&new_dir, &oodle, num_threads)?;
+    let changes = limn::diff::diff(&old, &new);
+
+    let name_of = |name: u64| match dictionary.get(&name.into()) {
+        Some(name) => name.to_string(),
+        None => format!("{name:016x}"),
+    };
+    let bundles_of = |bundles: &[u64]| bundles.iter()
+        .map(|bundle| format!("{bundle:016x}"))
+        .collect::<Vec<_>>()
+        .join(",");
+
+    let mut counts = [0; 4];
+    for group in changes.chunk_by(|a, b| a.ext == b.ext) {
+        let ext = group[0].ext;
+        match hash::extension_name(ext) {
+            Some(ext) => println!("{ext}"),
+            None => println!("{ext:016x}"),
+        }
+
+        for change in group {
+            counts[change.kind as usize] += 1;
+            let name = name_of(change.name);
+            match (change.kind, change.old, change.new) {
+                (ChangeKind::Added, _, Some(new)) => println!("  A {name} in {}", bundles_of(&new.bundles)),
+                (ChangeKind::Removed, Some(old), _) => println!("  R {name} from {}", bundles_of(&old.bundles)),
+                (ChangeKind::Modified, Some(old), Some(new)) if old.bundles != new.bundles => {
+                    println!("  M {name} {} -> {}", bundles_of(&old.bundles), bundles_of(&new.bundles));
+                }
+                (ChangeKind::Modified, _, _) => println!("  M {name}"),
+                (ChangeKind::Moved, Some(old), Some(new)) => {
+                    println!("  > {name} {} -> {}", bundles_of(&old.bundles), bundles_of(&new.bundles));
+                }
+                _ => unreachable!(),
+            }
+        }
+    }
+    println!("{} added, {} removed, {} modified, {} moved",
+        counts[ChangeKind::Added as usize],
+        counts[ChangeKind::Removed as usize],
+        counts[ChangeKind::Modified as usize],
+        counts[ChangeKind::Moved as usize]);
+
+    if let Some(output) = output {
+        // extract each changed file from the first bundle holding it
+        let mut by_bundle = HashMap::<u64, HashSet<(u64, u64)>>::new();
+        for change in &changes {
+            if let (ChangeKind::Added | ChangeKind::Modified, Some(new)) = (change.kind, change.new) {
+                by_bundle.entry(new.bundles[0])
+                    .or_default()
+                    .insert((change.ext, change.name));
+            }
+        }
+
+        let mut builder = ExtractBuilder::new();
+        builder.input(&new_dir)
+            .output(Some(&output))
+            .oodle(oodle)
+            .set_dictionary(dictionary)
+            .skip_unknown(false);
+        let options = builder.build()?;
+
+        let mut pool = Pool::new();
+        let mut buffer_reader = vec![0_u8; 0x80000];
+        let mut scratch = Vec::new();
+        let mut num_files = 0;
+        let mut num_failed = 0;
+        for (path, bundle_hash) in bundle::list_bundles(&new_dir)? {
+            let Some(targets) = by_bundle.get(&bundle_hash) else {
+                continue;
+            };
+
+            let mut rdr = ChunkReader::new(&mut buffer_reader, File::open(&path)?);
+            let mut bundle = BundleFd::new(Some(bundle_hash), &mut rdr)?;
+            let mut files = bundle.files(options.decompressor(), &mut scratch)?;
+            while let Some(mut entry) = files.next_file()? {
+                if !targets.contains(&(entry.ext, entry.name)) {
+                    entry.skip()?;
+                    continue;
+                }
+
+                match file::extract(entry, &mut pool, &options) {
+                    Ok(_) => num_files += 1,
+                    Err(e) => {
+                        eprintln!("{e}");
+                        num_failed += 1;
+                    }
+                }
+            }
+        }
+        println!("extracted {num_files} files to \"{}\"", output.display());
+        if num_failed > 0 {
+            return Err(format!("{num_failed} files failed to extract").into());
+        }
+    }
+
+    println!("{:.2}s", start.elapsed().as_secs_f64());
+    Ok(())
+}
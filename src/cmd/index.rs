@@ -0,0 +1,70 @@
+use std::ffi::OsString;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use limn::catalog::Catalog;
+
+use super::CmdResult;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe index [OPTIONS]");
+    println!();
+    println!("Record every file in every bundle to the catalog. Only bundles that changed");
+    println!("since the last run are read again.");
+    println!();
+    println!("OPTIONS:");
+    println!("        --catalog <PATH>      Catalog file. Default is `catalog.bin`.");
+    println!("        --oodle <PATH>        Load the Oodle library from PATH.");
+    println!("    -i, --input <PATH>        Directory of bundles.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    let mut input = None;
+    let mut catalog_path = PathBuf::from(super::DEFAULT_CATALOG);
+    let mut oodle = None;
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "-i" | "--input" => input = Some(PathBuf::from(super::param(args, "--input"))),
+            "--catalog" => catalog_path = PathBuf::from(super::param(args, "--catalog")),
+            "--oodle" => oodle = Some(PathBuf::from(super::param(args, "--oodle"))),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            _ => {
+                eprintln!("ERROR: unknown option {arg:?}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (input, darktide_path) = super::bundle_dir(input);
+    let oodle = match crate::load_oodle(oodle, &input, darktide_path.as_ref()) {
+        Ok(oodle) => oodle,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let mut catalog = if catalog_path.exists() {
+        Catalog::load(&catalog_path).unwrap_or_else(|e| {
+            eprintln!("WARN: rebuilding catalog \"{}\": {e}", catalog_path.display());
+            Catalog::new()
+        })
+    } else {
+        Catalog::new()
+    };
+
+    let start = Instant::now();
+    let refresh = catalog.refresh(&input, &oodle, crate::num_threads())?;
+    catalog.save(&catalog_path)?;
+
+    let num_files = catalog.bundles().iter().map(|b| b.files.len()).sum::<usize>();
+    println!("{} bundles with {num_files} files", catalog.bundles().len());
+    println!("{} added, {} updated, {} removed, {} unchanged",
+        refresh.added, refresh.updated, refresh.removed, refresh.unchanged);
+    println!("{:.2}s", start.elapsed().as_secs_f64());
+    Ok(())
+}
@@ -0,0 +1,97 @@
+use std::collections::BTreeMap;
+use std::ffi::OsString;
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Instant;
+
+use limn::format::Format;
+use limn::harvest;
+use limn::infer;
+
+use super::CmdResult;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe infer [OPTIONS]");
+    println!();
+    println!("Guess unknown names from the known names of packages and the files they list.");
+    println!("Names are appended to `learned.txt` and where each came from to `inferred.jsonl`.");
+    println!();
+    println!("OPTIONS:");
+    println!("        --dict <PATH>         Load dictionary. Default is `dictionary.txt` and `learned.txt`.");
+    println!("        --oodle <PATH>        Load the Oodle library from PATH.");
+    println!("    -i, --input <PATH>        Directory of bundles.");
+    println!("    -o, --output <PATH>       Names found. Default is `learned.txt`.");
+    println!("        --sources <PATH>      Where names came from. Default is `inferred.jsonl`.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    let mut input = None;
+    let mut dictionary = Vec::new();
+    let mut oodle = None;
+    let mut output = PathBuf::from(harvest::LEARNED_FILE);
+    let mut sources = PathBuf::from("inferred.jsonl");
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "-i" | "--input" => input = Some(PathBuf::from(super::param(args, "--input"))),
+            "--dict" => dictionary.push(PathBuf::from(super::param(args, "--dict"))),
+            "--oodle" => oodle = Some(PathBuf::from(super::param(args, "--oodle"))),
+            "-o" | "--output" => output = PathBuf::from(super::param(args, "--output")),
+            "--sources" => sources = PathBuf::from(super::param(args, "--sources")),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            _ => {
+                eprintln!("ERROR: unknown option {arg:?}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (input, darktide_path) = super::bundle_dir(input);
+    let oodle = match crate::load_oodle(oodle, &input, darktide_path.as_ref()) {
+        Ok(oodle) => oodle,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+    let dictionary = super::load_dictionary(&dictionary)?;
+
+    let start = Instant::now();
+    let packages = infer::scan_packages(&input, &oodle, crate::num_threads())?;
+    let found = infer::infer(&packages, &dictionary);
+
+    if !found.is_empty() {
+        let mut names = String::new();
+        let mut lines = Vec::new();
+        for inferred in &found {
+            names.push_str(&inferred.name);
+            names.push('\n');
+            Format::Json.write(&mut lines, &inferred.to_value())?;
+            lines.push(b'\n');
+        }
+        OpenOptions::new().create(true).append(true).open(&output)?
+            .write_all(names.as_bytes())?;
+        OpenOptions::new().create(true).append(true).open(&sources)?
+            .write_all(&lines)?;
+    }
+
+    let mut rules = BTreeMap::<&str, usize>::new();
+    for inferred in &found {
+        *rules.entry(&inferred.rule).or_default() += 1;
+    }
+    for (rule, count) in &rules {
+        println!("{count:>8}  {rule}");
+    }
+    println!("{} names found from {} packages in {:.2}s",
+        found.len(),
+        packages.len(),
+        start.elapsed().as_secs_f64());
+    if !found.is_empty() {
+        println!("appended to \"{}\" and \"{}\"", output.display(), sources.display());
+    }
+    Ok(())
+}
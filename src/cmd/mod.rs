@@ -0,0 +1,135 @@
+use std::ffi::OsStr;
+use std::ffi::OsString;
+use std::path::Path;
+use std::path::PathBuf;
+
+use limn::dictionary;
+use limn::dictionary::CompiledDictionary;
+use limn::dictionary::Dictionary;
+use limn::dictionary::Source;
+
+mod crack;
+mod dict;
+mod diff;
+mod index;
+mod infer;
+mod verify;
+mod which;
+
+pub type CmdResult = Result<(), Box<dyn std::error::Error>>;
+type Run = fn(&mut dyn Iterator<Item = OsString>) -> CmdResult;
+
+// name, description and entry point of each subcommand
+pub const COMMANDS: &[(&str, &str, Run)] = &[
+    ("crack", "Find names of unknown files from templates and wordlists.", crack::run),
+    ("dict", "Compile dictionaries for faster loading.", dict::run),
+    ("diff", "Compare files in two bundle directories.", diff::run),
+    ("index", "Update the catalog of files in every bundle.", index::run),
+    ("infer", "Guess unknown names from package contents.", infer::run),
+    ("verify", "Check bundles are well-formed.", verify::run),
+    ("which", "List bundles containing a file.", which::run),
+    ("find", "Same as which.", which::run),
+];
+
+pub fn find(name: &OsStr) -> Option<Run> {
+    COMMANDS.iter()
+        .find(|(cmd, _, _)| OsStr::new(cmd) == name)
+        .map(|(_, _, run)| *run)
+}
+
+pub const DEFAULT_CATALOG: &str = "catalog.bin";
+
+// dictionaries loaded when none are given
+pub const DEFAULT_DICTIONARIES: &[&str] = &["dictionary.txt", limn::harvest::LEARNED_FILE];
+
+pub fn param(args: &mut dyn Iterator<Item = OsString>, opt: &str) -> OsString {
+    let Some(param) = args.next() else {
+        eprintln!("ERROR: missing parameter to {}", opt);
+        std::process::exit(1);
+    };
+    param
+}
+
+pub fn param_str(args: &mut dyn Iterator<Item = OsString>, opt: &str) -> String {
+    let Ok(param) = param(args, opt).into_string() else {
+        eprintln!("ERROR: invalid UTF-8 in parameter to {}", opt);
+        std::process::exit(1);
+    };
+    param
+}
+
+// `dictionary.bin` if it was compiled from the default dictionaries as they
+// are now
+pub fn compiled_dictionary() -> Option<CompiledDictionary> {
+    let path = Path::new(dictionary::COMPILED_FILE);
+    if !path.exists() {
+        return None;
+    }
+    let compiled = match CompiledDictionary::open(path) {
+        Ok(compiled) => compiled,
+        Err(e) => {
+            eprintln!("WARN: failed to load \"{}\": {e}", path.display());
+            return None;
+        }
+    };
+
+    let mut sources = DEFAULT_DICTIONARIES.iter()
+        .filter_map(|source| Source::of(Path::new(source)).ok())
+        .collect::<Vec<_>>();
+    let mut compiled_from = compiled.sources().to_vec();
+    sources.sort_unstable();
+    compiled_from.sort_unstable();
+    if sources != compiled_from {
+        eprintln!("WARN: \"{}\" was not compiled from the current {}, run `limn dict compile`",
+            path.display(),
+            DEFAULT_DICTIONARIES.join(" and "));
+        return None;
+    }
+    Some(compiled)
+}
+
+// text or compiled dictionary files, `dictionary.bin` or `dictionary.txt`
+// and `learned.txt` if none are given
+pub fn load_dictionary(paths: &[PathBuf]) -> Result<Dictionary, String> {
+    let mut dictionary = Dictionary::new();
+    if paths.is_empty() {
+        if let Some(compiled) = compiled_dictionary() {
+            dictionary.add_compiled(compiled);
+            return Ok(dictionary);
+        }
+        for path in DEFAULT_DICTIONARIES {
+            let path = Path::new(path);
+            if path.exists() && dictionary.add_file(path).is_err() {
+                return Err(format!("failed to load dictionary \"{}\"", path.display()));
+            }
+        }
+    }
+    for path in paths {
+        let loaded = if dictionary::is_compiled(path) {
+            CompiledDictionary::open(path)
+                .map(|compiled| dictionary.add_compiled(compiled))
+                .map_err(|e| e.to_string())
+        } else {
+            dictionary.add_file(path).map_err(|e| e.to_string())
+        };
+        if let Err(e) = loaded {
+            return Err(format!("failed to load dictionary \"{}\": {e}", path.display()));
+        }
+    }
+    Ok(dictionary)
+}
+
+// bundle directory and Darktide install when `input` is not given
+pub fn bundle_dir(input: Option<PathBuf>) -> (PathBuf, Option<PathBuf>) {
+    let darktide_path = steam_find::get_steam_app(1361210).map(|app| app.path);
+    let input = input.unwrap_or_else(|| {
+        match &darktide_path {
+            Ok(path) => path.join("bundle"),
+            Err(e) => {
+                eprintln!("Darktide steam installation was not found:\n{e:?}");
+                std::process::exit(1);
+            }
+        }
+    });
+    (input, darktide_path.ok())
+}
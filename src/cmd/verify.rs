@@ -0,0 +1,124 @@
+use std::ffi::OsString;
+use std::fs::File;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::thread;
+use std::time::Instant;
+
+use limn::Decompressor;
+use limn::bundle;
+use limn::bundle::BundleFd;
+use limn::read::ChunkReader;
+
+use super::CmdResult;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe verify [OPTIONS]");
+    println!();
+    println!("Check bundles are well-formed without extracting them. Every problem is");
+    println!("listed instead of stopping at the first.");
+    println!();
+    println!("OPTIONS:");
+    println!("    -i, --input <PATH>        Bundle or directory of bundles.");
+    println!("        --no-resources        Skip checking `data/**` resource files exist.");
+    println!("        --oodle <PATH>        Load the Oodle library from PATH.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    let mut input = None;
+    let mut resources = true;
+    let mut oodle = None;
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "-i" | "--input" => input = Some(PathBuf::from(super::param(args, "--input"))),
+            "--no-resources" => resources = false,
+            "--oodle" => oodle = Some(PathBuf::from(super::param(args, "--oodle"))),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            _ => {
+                eprintln!("ERROR: unknown option {arg:?}");
+                std::process::exit(1);
+            }
+        }
+    }
+
+    let (input, darktide_path) = super::bundle_dir(input);
+    let oodle = match crate::load_oodle(oodle, &input, darktide_path.as_ref()) {
+        Ok(oodle) => oodle,
+        Err(e) => {
+            eprintln!("{e}");
+            std::process::exit(1);
+        }
+    };
+
+    let (bundles, data_dir) = if input.is_dir() {
+        (bundle::list_bundles(&input)?, input.clone())
+    } else {
+        let hash = bundle::bundle_hash_from(&input).unwrap_or(0);
+        let dir = input.parent().map(|p| p.to_path_buf()).unwrap_or_default();
+        (vec![(input.clone(), hash)], dir)
+    };
+    let data_dir = resources.then_some(data_dir);
+
+    let start = Instant::now();
+    let next = AtomicUsize::new(0);
+    let problems = Mutex::new(Vec::new());
+    thread::scope(|s| {
+        for _ in 0..crate::num_threads() {
+            s.spawn(|| {
+                let mut buffer_reader = vec![0_u8; 0x80000];
+                let mut scratch = Vec::new();
+                while let Some((path, hash)) = bundles.get(next.fetch_add(1, Ordering::AcqRel)) {
+                    let found = verify_bundle(
+                        path,
+                        *hash,
+                        &oodle,
+                        &mut buffer_reader,
+                        &mut scratch,
+                        data_dir.as_deref(),
+                    );
+                    problems.lock().unwrap().extend(found);
+                }
+            });
+        }
+    });
+
+    let mut problems = problems.into_inner().unwrap();
+    problems.sort_by_key(|e| e.bundle());
+    for e in &problems {
+        println!("{e}");
+    }
+    println!("{} bundles, {} problems", bundles.len(), problems.len());
+    println!("{:.2}s", start.elapsed().as_secs_f64());
+
+    if !problems.is_empty() {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+fn verify_bundle(
+    path: &std::path::Path,
+    hash: u64,
+    decompressor: &dyn Decompressor,
+    buffer_reader: &mut [u8],
+    scratch: &mut Vec<u8>,
+    data_dir: Option<&std::path::Path>,
+) -> Vec<limn::Error> {
+    let res = File::open(path)
+        .map_err(limn::Error::from)
+        .and_then(|fd| {
+            let mut rdr = ChunkReader::new(buffer_reader, fd);
+            BundleFd::new(Some(hash), &mut rdr)?
+                .verify(decompressor, scratch, data_dir)
+        });
+    match res {
+        Ok(problems) => problems,
+        Err(e) => vec![e.with_bundle(Some(hash))],
+    }
+}
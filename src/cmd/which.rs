@@ -0,0 +1,117 @@
+use std::collections::HashSet;
+use std::ffi::OsString;
+use std::path::PathBuf;
+
+use limn::catalog::Catalog;
+use limn::file::uses_resource_file;
+use limn::glob::Glob;
+use limn::hash;
+
+use super::CmdResult;
+
+fn print_help() {
+    println!("USAGE:");
+    println!("limn.exe which [OPTIONS] <NAME>");
+    println!();
+    println!("List bundles containing a file. Reads the catalog written by `limn index`.");
+    println!();
+    println!("ARGS:");
+    println!("    <NAME>  Resource name, 16 digit hex hash or glob against dictionary names.");
+    println!("            A known extension at the end of the name is used as --ext.");
+    println!();
+    println!("OPTIONS:");
+    println!("    -e, --ext <EXT>           Only match files with extension.");
+    println!("        --catalog <PATH>      Catalog file. Default is `catalog.bin`.");
+    println!("        --dict <PATH>         Load dictionary. Default is `dictionary.txt`.");
+}
+
+pub fn run(args: &mut dyn Iterator<Item = OsString>) -> CmdResult {
+    let mut name = None;
+    let mut ext = None;
+    let mut catalog_path = PathBuf::from(super::DEFAULT_CATALOG);
+    let mut dictionary = Vec::new();
+    while let Some(arg) = args.next() {
+        match arg.to_str().unwrap_or("") {
+            "-e" | "--ext" => ext = Some(super::param_str(args, "--ext")),
+            "--catalog" => catalog_path = PathBuf::from(super::param(args, "--catalog")),
+            "--dict" => dictionary.push(PathBuf::from(super::param(args, "--dict"))),
+            "--help" => {
+                print_help();
+                return Ok(());
+            }
+            opt if opt.starts_with("-") => {
+                eprintln!("ERROR: unknown option {opt}");
+                std::process::exit(1);
+            }
+            _ => name = Some(arg.into_string().map_err(|_| "invalid UTF-8 in name")?),
+        }
+    }
+
+    let Some(mut name) = name else {
+        print_help();
+        std::process::exit(1);
+    };
+
+    if ext.is_none()
+        && let Some((stem, suffix)) = name.rsplit_once('.')
+        && hash::extension_name(hash::murmur_hash64a(suffix.as_bytes(), 0)).is_some()
+    {
+        ext = Some(suffix.to_string());
+        name = stem.to_string();
+    }
+    let ext = ext.map(|ext| hash::murmur_hash64a(ext.as_bytes(), 0));
+
+    let dictionary = super::load_dictionary(&dictionary)?;
+    let names = if name.len() == 16
+        && let Ok(hash) = u64::from_str_radix(&name, 16)
+    {
+        HashSet::from([hash])
+    } else if Glob::is_glob(&name) {
+        let glob = Glob::new(&name);
+        dictionary.iter()
+            .filter(|(_, name)| glob.is_match(name))
+            .map(|(hash, _)| hash)
+            .collect()
+    } else {
+        HashSet::from([hash::murmur_hash64a(name.as_bytes(), 0)])
+    };
+
+    if !catalog_path.exists() {
+        eprintln!("ERROR: catalog \"{}\" not found, run `limn index` first", catalog_path.display());
+        std::process::exit(1);
+    }
+    let catalog = Catalog::load(&catalog_path)?;
+
+    let mut found = 0;
+    for bundle in catalog.bundles() {
+        for file in &bundle.files {
+            if !names.contains(&file.name) || ext.is_some_and(|ext| ext != file.ext) {
+                continue;
+            }
+            found += 1;
+
+            let name = match dictionary.get(&file.name.into()) {
+                Some(name) => name.to_string(),
+                None => format!("{:016x}", file.name),
+            };
+            let ext = match hash::extension_name(file.ext) {
+                Some(ext) => ext.to_string(),
+                None => format!("{:016x}", file.ext),
+            };
+            print!("{:016x} {name}.{ext}", bundle.hash);
+            for variant in &file.variants {
+                print!(" [body {} tail {}]", variant.body_size, variant.tail_size);
+            }
+            if uses_resource_file(file.ext, &file.variants) {
+                print!(" data/**");
+            }
+            println!();
+        }
+    }
+
+    if found == 0 {
+        eprintln!("no bundle contains {name}");
+        std::process::exit(1);
+    }
+    Ok(())
+}
@@ -0,0 +1,307 @@
+//! Find names for unknown hashes by hashing generated candidates.
+//!
+//! A [`Template`] is a path with placeholders in braces:
+//!
+//! - `{words}` is every line of the wordlist named `words`
+//! - `{a|b|c}` is each of the alternatives
+//! - `{0..99}` is each number in the range, `{00..99}` pads to the width of
+//!   the first number
+//!
+//! `{{` and `}}` are literal braces. Every combination of placeholder values
+//! is a candidate, so `content/{dirs}/{names}_{01..20}` tries
+//! `dirs * names * 20` names.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
+use std::sync::Mutex;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::thread;
+
+use crate::hash::murmur_hash64a;
+
+/// Spelling variants of wordlist words.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Case {
+    pub lower: bool,
+    pub upper: bool,
+    /// First letter upper case.
+    pub title: bool,
+}
+
+impl Case {
+    fn apply(&self, words: &[String]) -> Vec<String> {
+        if *self == Self::default() {
+            return words.to_vec();
+        }
+
+        let mut out = Vec::with_capacity(words.len());
+        let mut seen = HashSet::new();
+        for word in words {
+            let mut variants = Vec::with_capacity(3);
+            if self.lower {
+                variants.push(word.to_lowercase());
+            }
+            if self.upper {
+                variants.push(word.to_uppercase());
+            }
+            if self.title {
+                let mut chars = word.chars();
+                if let Some(first) = chars.next() {
+                    variants.push(first.to_uppercase().chain(chars).collect());
+                }
+            }
+            for variant in variants {
+                if seen.insert(variant.clone()) {
+                    out.push(variant);
+                }
+            }
+        }
+        out
+    }
+}
+
+#[derive(Debug)]
+pub struct TemplateError {
+    pub template: String,
+    pub msg: String,
+}
+
+impl fmt::Display for TemplateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "invalid template {:?}: {}", self.template, self.msg)
+    }
+}
+
+impl std::error::Error for TemplateError {}
+
+#[derive(Clone, Debug)]
+enum Part {
+    Literal(String),
+    Choice(Vec<String>),
+}
+
+/// Path pattern expanded into candidate names.
+#[derive(Clone, Debug)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+impl Template {
+    /// Parse `pattern`, looking up `{name}` placeholders in `wordlists`.
+    ///
+    /// `case` applies to wordlist words, alternatives are used as written.
+    pub fn parse(
+        pattern: &str,
+        wordlists: &HashMap<String, Vec<String>>,
+        case: Case,
+    ) -> Result<Self, TemplateError> {
+        let error = |msg: String| TemplateError {
+            template: pattern.to_string(),
+            msg,
+        };
+
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = pattern;
+        while let Some(c) = rest.chars().next() {
+            rest = &rest[c.len_utf8()..];
+            match c {
+                '{' if rest.starts_with('{') => {
+                    rest = &rest[1..];
+                    literal.push('{');
+                }
+                '}' if rest.starts_with('}') => {
+                    rest = &rest[1..];
+                    literal.push('}');
+                }
+                '}' => return Err(error("unmatched `}`".to_string())),
+                '{' => {
+                    let Some((inner, after)) = rest.split_once('}') else {
+                        return Err(error("unmatched `{`".to_string()));
+                    };
+                    rest = after;
+
+                    let choice = if let Some((start, end)) = inner.split_once("..") {
+                        range(start, end).ok_or_else(|| error(format!("invalid range {{{inner}}}")))?
+                    } else if inner.contains('|') {
+                        inner.split('|').map(str::to_string).collect()
+                    } else if let Some(words) = wordlists.get(inner) {
+                        case.apply(words)
+                    } else {
+                        return Err(error(format!("unknown wordlist {inner:?}")));
+                    };
+
+                    if !literal.is_empty() {
+                        parts.push(Part::Literal(std::mem::take(&mut literal)));
+                    }
+                    parts.push(Part::Choice(choice));
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+        Ok(Self { parts })
+    }
+
+    /// Number of candidates, saturating at `u64::MAX`.
+    pub fn len(&self) -> u64 {
+        self.parts.iter()
+            .map(|part| match part {
+                Part::Literal(_) => 1,
+                Part::Choice(choice) => choice.len() as u64,
+            })
+            .fold(1, u64::saturating_mul)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // choice index of every part for candidate `index`, last part varies
+    // fastest
+    fn digits(&self, mut index: u64, digits: &mut Vec<usize>) {
+        digits.clear();
+        digits.resize(self.parts.len(), 0);
+        for (digit, part) in digits.iter_mut().zip(&self.parts).rev() {
+            if let Part::Choice(choice) = part {
+                let len = choice.len() as u64;
+                *digit = (index % len) as usize;
+                index /= len;
+            }
+        }
+    }
+
+    // advance to the next candidate, false after the last one
+    fn increment(&self, digits: &mut [usize]) -> bool {
+        for (digit, part) in digits.iter_mut().zip(&self.parts).rev() {
+            if let Part::Choice(choice) = part {
+                *digit += 1;
+                if *digit < choice.len() {
+                    return true;
+                }
+                *digit = 0;
+            }
+        }
+        false
+    }
+
+    fn write(&self, digits: &[usize], out: &mut String) {
+        out.clear();
+        for (digit, part) in digits.iter().zip(&self.parts) {
+            match part {
+                Part::Literal(s) => out.push_str(s),
+                Part::Choice(choice) => out.push_str(&choice[*digit]),
+            }
+        }
+    }
+
+    /// Call `f` with every candidate.
+    pub fn for_each(&self, mut f: impl FnMut(&str)) {
+        self.for_range(0, self.len(), &mut f);
+    }
+
+    fn for_range(&self, start: u64, end: u64, f: &mut dyn FnMut(&str)) {
+        if start >= end {
+            return;
+        }
+        let mut digits = Vec::new();
+        let mut candidate = String::new();
+        self.digits(start, &mut digits);
+        for _ in start..end {
+            self.write(&digits, &mut candidate);
+            f(&candidate);
+            if !self.increment(&mut digits) {
+                break;
+            }
+        }
+    }
+}
+
+fn range(start: &str, end: &str) -> Option<Vec<String>> {
+    let first = start.parse::<u64>().ok()?;
+    let last = end.parse::<u64>().ok()?;
+    if first > last || last - first >= 10_000_000 {
+        return None;
+    }
+    let width = if start.len() > 1 && start.starts_with('0') {
+        start.len()
+    } else {
+        0
+    };
+    Some((first..=last).map(|n| format!("{n:0width$}")).collect())
+}
+
+// candidates handed to a thread at once
+const CHUNK: u64 = 0x10000;
+
+/// Hash every candidate of `templates` on `num_threads` threads and return
+/// the names hashing to one of `targets`.
+pub fn crack(
+    templates: &[Template],
+    targets: &HashSet<u64>,
+    num_threads: usize,
+) -> HashMap<u64, String> {
+    let found = Mutex::new(HashMap::new());
+    for template in templates {
+        let len = template.len();
+        let next = AtomicU64::new(0);
+        thread::scope(|s| {
+            for _ in 0..num_threads.max(1) {
+                s.spawn(|| {
+                    let mut hits = Vec::new();
+                    loop {
+                        let start = next.fetch_add(CHUNK, Ordering::AcqRel);
+                        if start >= len {
+                            break;
+                        }
+                        let end = start.saturating_add(CHUNK).min(len);
+                        template.for_range(start, end, &mut |candidate| {
+                            let hash = murmur_hash64a(candidate.as_bytes(), 0);
+                            if targets.contains(&hash) {
+                                hits.push((hash, candidate.to_string()));
+                            }
+                        });
+                    }
+                    found.lock().unwrap().extend(hits);
+                });
+            }
+        });
+    }
+    found.into_inner().unwrap()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn templates() {
+        let wordlists = HashMap::from([
+            ("w".to_string(), vec!["Foo".to_string(), "bar".to_string()]),
+        ]);
+        let case = Case {
+            lower: true,
+            title: true,
+            ..Case::default()
+        };
+        let template = Template::parse("a/{w}_{08..10}{.lua|}{{x}}", &wordlists, case).unwrap();
+        assert_eq!(template.len(), 4 * 3 * 2);
+        let mut all = Vec::new();
+        template.for_each(|s| all.push(s.to_string()));
+        assert_eq!(&all[..3], ["a/foo_08.lua{x}", "a/foo_08{x}", "a/foo_09.lua{x}"]);
+        assert_eq!(all.last().unwrap(), "a/Bar_10{x}");
+        assert_eq!(all.len(), 24);
+
+        assert!(Template::parse("{missing}", &wordlists, case).is_err());
+        assert!(Template::parse("{5..1}", &wordlists, case).is_err());
+        assert!(Template::parse("a}", &wordlists, case).is_err());
+
+        let targets = HashSet::from([murmur_hash64a(b"a/Bar_09{x}", 0)]);
+        let found = crack(&[template], &targets, 2);
+        assert_eq!(found.into_values().collect::<Vec<_>>(), ["a/Bar_09{x}"]);
+    }
+}
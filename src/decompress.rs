@@ -0,0 +1,49 @@
+use std::panic::RefUnwindSafe;
+
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::error::Result;
+
+/// Decoder for the compressed chunks in bundles and texture streams.
+pub trait Decompressor: Send + Sync + RefUnwindSafe {
+    /// Decompress `input` into all of `out` and return the number of bytes
+    /// written.
+    ///
+    /// `scratch` is at least [`scratch_size`](Self::scratch_size) bytes.
+    fn decompress(&self, input: &[u8], out: &mut [u8], scratch: &mut [u8]) -> Result<usize>;
+
+    /// Scratch memory needed by one [`decompress`](Self::decompress) call.
+    fn scratch_size(&self) -> usize;
+}
+
+/// Treats input as already decompressed.
+///
+/// Useful for bundles that only contain stored chunks.
+pub struct Passthrough;
+
+impl Decompressor for Passthrough {
+    fn decompress(&self, input: &[u8], out: &mut [u8], _scratch: &mut [u8]) -> Result<usize> {
+        if input.len() != out.len() {
+            return Err(Error::new(ErrorKind::Decompress));
+        }
+        out.copy_from_slice(input);
+        Ok(out.len())
+    }
+
+    fn scratch_size(&self) -> usize {
+        0
+    }
+}
+
+// used when no decompressor was given, fails once something is compressed
+pub(crate) struct Missing;
+
+impl Decompressor for Missing {
+    fn decompress(&self, _input: &[u8], _out: &mut [u8], _scratch: &mut [u8]) -> Result<usize> {
+        Err(Error::new(ErrorKind::MissingOption("decompressor")))
+    }
+
+    fn scratch_size(&self) -> usize {
+        0
+    }
+}
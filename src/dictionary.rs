@@ -0,0 +1,503 @@
+//! Name lookup for hashes.
+//!
+//! Text dictionaries have one name per line, or `@<hash>=<name>` for names
+//! that do not hash to their own hash. Parsing a large dictionary takes a
+//! while on every run, so it can be compiled with [`Dictionary::save`] and
+//! memory-mapped by [`CompiledDictionary::open`]. The text dictionaries it was
+//! compiled from are recorded so a stale or unrelated compiled dictionary
+//! is not used in their place.
+//!
+//! Compiled layout, little endian:
+//!
+//! ```text
+//! magic        b"LIMNDICT"
+//! version      u32
+//! num_names    u32
+//! num_short    u32
+//! collisions   u32  short hashes shared by several names
+//! num_sources  u32
+//! sources      [(mtime: u64, len: u32, path: [u8; len]); num_sources]
+//! names        [(hash: u64, offset: u32, len: u32); num_names]  sorted by hash
+//! short        [(short: u32, name: u32); num_short]             sorted by (short, name)
+//! pool         name bytes, offsets are from the start of the pool
+//! ```
+
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::time::UNIX_EPOCH;
+use byteorder::WriteBytesExt;
+use byteorder::LE;
+use memmap2::Mmap;
+
+use crate::error::ensure;
+use crate::error::Error;
+use crate::error::Result;
+use crate::hash;
+use crate::hash::MurmurHash;
+use crate::hash::MurmurHash32;
+
+/// Compiled dictionary loaded instead of `dictionary.txt` when newer.
+pub const COMPILED_FILE: &str = "dictionary.bin";
+
+const MAGIC: [u8; 8] = *b"LIMNDICT";
+// 3: source dictionaries in the header
+// 4: number of short hash collisions in the header
+const VERSION: u32 = 4;
+const HEADER_SIZE: usize = 28;
+const NAME_SIZE: usize = 16;
+const SHORT_SIZE: usize = 8;
+
+/// Whether `path` starts like a compiled dictionary.
+pub fn is_compiled(path: &Path) -> bool {
+    let mut magic = [0; 8];
+    File::open(path)
+        .and_then(|mut fd| io::Read::read_exact(&mut fd, &mut magic))
+        .is_ok_and(|()| magic == MAGIC)
+}
+
+/// Text dictionary file a compiled dictionary was built from.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Source {
+    pub path: String,
+    /// Modified time in nanoseconds since the unix epoch.
+    pub mtime: u64,
+}
+
+impl Source {
+    pub fn of(path: &Path) -> io::Result<Self> {
+        let modified = fs::metadata(path)?.modified()?;
+        Ok(Self {
+            path: path.to_string_lossy().into_owned(),
+            mtime: modified.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_nanos() as u64),
+        })
+    }
+}
+
+/// Names from text dictionaries and compiled dictionaries.
+///
+/// Text entries take precedence over compiled ones.
+#[derive(Default)]
+pub struct Dictionary {
+    names: HashMap<MurmurHash, String>,
+    short: HashMap<MurmurHash32, Vec<MurmurHash>>,
+    compiled: Vec<CompiledDictionary>,
+    sources: Vec<Source>,
+    // short hashes shared by several text names
+    num_collisions: usize,
+}
+
+impl Dictionary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add lines of a text dictionary.
+    pub fn add<T: Into<String>>(&mut self, keys: impl Iterator<Item = T>) {
+        for key in keys {
+            let key = key.into();
+            let (hash, name) = hash::dictionary_entry(&key);
+            let key = if name.len() == key.len() {
+                key
+            } else {
+                name.to_string()
+            };
+            let short = self.short.entry(hash.clone_short()).or_default();
+            if !short.contains(&hash) {
+                short.push(hash.clone());
+                if short.len() == 2 {
+                    self.num_collisions += 1;
+                }
+            }
+            self.names.insert(hash, key);
+        }
+    }
+
+    /// Add a text dictionary file, recorded as a source by [`save`](Self::save).
+    pub fn add_file(&mut self, path: &Path) -> io::Result<()> {
+        // a change while reading leaves an older mtime, which is stale later
+        let source = Source::of(path)?;
+        let text = fs::read_to_string(path)?;
+        self.add(text.lines());
+        self.sources.push(source);
+        Ok(())
+    }
+
+    pub fn add_compiled(&mut self, compiled: CompiledDictionary) {
+        self.compiled.push(compiled);
+    }
+
+    pub fn get(&self, hash: &MurmurHash) -> Option<&str> {
+        if let Some(name) = self.names.get(hash) {
+            return Some(name);
+        }
+        self.compiled.iter().find_map(|compiled| compiled.get(hash.0))
+    }
+
+    pub fn contains_key(&self, hash: &MurmurHash) -> bool {
+        self.get(hash).is_some()
+    }
+
+    /// Full hashes of known names with the upper 32 bits `short`, sorted.
+    pub fn get_short(&self, short: &MurmurHash32) -> Vec<MurmurHash> {
+        let mut hashes = self.compiled.iter()
+            .flat_map(|compiled| compiled.get_short(short.0))
+            .map(MurmurHash)
+            .collect::<Vec<_>>();
+        if let Some(text) = self.short.get(short) {
+            hashes.extend(text.iter().cloned());
+        }
+        hashes.sort_unstable_by_key(|hash| hash.0);
+        hashes.dedup();
+        hashes
+    }
+
+    /// Short hashes shared by more than one name, sorted.
+    pub fn collisions(&self) -> Vec<(MurmurHash32, Vec<MurmurHash>)> {
+        let mut shorts = BTreeSet::new();
+        for compiled in &self.compiled {
+            for i in 1..compiled.num_short {
+                let short = compiled.short_at(i).0;
+                if compiled.short_at(i - 1).0 == short {
+                    shorts.insert(short);
+                }
+            }
+        }
+        // a text name can collide with a compiled one
+        for (short, hashes) in &self.short {
+            if hashes.len() > 1 || self.compiled.iter().any(|c| !c.get_short(short.0).is_empty()) {
+                shorts.insert(short.0);
+            }
+        }
+        shorts.into_iter()
+            .map(MurmurHash32)
+            .map(|short| {
+                let hashes = self.get_short(&short);
+                (short, hashes)
+            })
+            .filter(|(_, hashes)| hashes.len() > 1)
+            .collect()
+    }
+
+    /// Number of short hashes shared by several names within each text or
+    /// compiled dictionary, without looking through the compiled names.
+    ///
+    /// A text name colliding with a compiled one is only found by
+    /// [`collisions`](Self::collisions).
+    pub fn num_collisions(&self) -> usize {
+        self.num_collisions + self.compiled.iter().map(|compiled| compiled.num_collisions).sum::<usize>()
+    }
+
+    /// Number of names, counting names in several dictionaries once each.
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.names.is_empty() && self.compiled.iter().all(CompiledDictionary::is_empty)
+    }
+
+    /// Every (hash, name), each hash once with the name [`get`](Self::get)
+    /// returns.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        let text = self.names.iter().map(|(hash, name)| (hash.0, name.as_str()));
+        let compiled = self.compiled.iter()
+            .enumerate()
+            .flat_map(move |(i, compiled)| compiled.iter().filter(move |(hash, _)| {
+                !self.names.contains_key(&MurmurHash(*hash))
+                    && self.compiled[..i].iter().all(|earlier| earlier.get(*hash).is_none())
+            }));
+        text.chain(compiled)
+    }
+
+    fn entries(&self) -> BTreeMap<u64, &str> {
+        self.iter().collect()
+    }
+
+    /// Write every name in the compiled format.
+    pub fn write(&self, out: &mut dyn Write) -> Result<()> {
+        let entries = self.entries();
+        let mut short = entries.keys()
+            .enumerate()
+            .map(|(i, hash)| (MurmurHash(*hash).clone_short().0, i as u32))
+            .collect::<Vec<_>>();
+        short.sort_unstable();
+        let num_collisions = short.chunk_by(|a, b| a.0 == b.0)
+            .filter(|names| names.len() > 1)
+            .count();
+
+        out.write_all(&MAGIC)?;
+        out.write_u32::<LE>(VERSION)?;
+        out.write_u32::<LE>(entries.len() as u32)?;
+        out.write_u32::<LE>(short.len() as u32)?;
+        out.write_u32::<LE>(num_collisions as u32)?;
+        out.write_u32::<LE>(self.sources.len() as u32)?;
+        for source in &self.sources {
+            out.write_u64::<LE>(source.mtime)?;
+            out.write_u32::<LE>(source.path.len() as u32)?;
+            out.write_all(source.path.as_bytes())?;
+        }
+        let mut offset = 0_usize;
+        for (hash, name) in &entries {
+            ensure!(offset + name.len() <= u32::MAX as usize, "dictionary larger than 4 GiB");
+            out.write_u64::<LE>(*hash)?;
+            out.write_u32::<LE>(offset as u32)?;
+            out.write_u32::<LE>(name.len() as u32)?;
+            offset += name.len();
+        }
+        for (short_hash, name) in &short {
+            out.write_u32::<LE>(*short_hash)?;
+            out.write_u32::<LE>(*name)?;
+        }
+        for name in entries.values() {
+            out.write_all(name.as_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        // write next to the dictionary first so an interrupted save keeps the old one
+        let tmp = path.with_extension("tmp");
+        let mut out = io::BufWriter::new(File::create(&tmp)?);
+        self.write(&mut out)?;
+        out.into_inner().map_err(|e| e.into_error())?;
+        fs::rename(&tmp, path)?;
+        Ok(())
+    }
+}
+
+enum Data {
+    Map(Mmap),
+    Vec(Vec<u8>),
+}
+
+/// Dictionary written by [`Dictionary::save`], searched in place.
+pub struct CompiledDictionary {
+    data: Data,
+    sources: Vec<Source>,
+    // offset of the names table
+    tables: usize,
+    num_names: usize,
+    num_short: usize,
+    num_collisions: usize,
+}
+
+impl CompiledDictionary {
+    /// Memory-map the compiled dictionary at `path`.
+    pub fn open(path: &Path) -> Result<Self> {
+        let fd = File::open(path)?;
+        // SAFETY: the map is only read and every offset is bounds checked.
+        // Truncating the file while it is mapped is not supported.
+        let map = unsafe { Mmap::map(&fd)? };
+        Self::new(Data::Map(map))
+    }
+
+    pub fn from_bytes(data: Vec<u8>) -> Result<Self> {
+        Self::new(Data::Vec(data))
+    }
+
+    fn new(data: Data) -> Result<Self> {
+        let bytes = match &data {
+            Data::Map(map) => &map[..],
+            Data::Vec(vec) => &vec[..],
+        };
+        ensure!(bytes.len() >= HEADER_SIZE && bytes[..8] == MAGIC, "not a compiled dictionary");
+        let version = read_u32(bytes, 8);
+        ensure!(version == VERSION, "unsupported dictionary version {version}");
+        let num_names = read_u32(bytes, 12) as usize;
+        let num_short = read_u32(bytes, 16) as usize;
+        let num_collisions = read_u32(bytes, 20) as usize;
+        let num_sources = read_u32(bytes, 24);
+
+        let mut tables = HEADER_SIZE;
+        let mut sources = Vec::new();
+        for _ in 0..num_sources {
+            ensure!(tables + 12 <= bytes.len(), "compiled dictionary is truncated");
+            let mtime = u64::from_le_bytes(bytes[tables..tables + 8].try_into().unwrap());
+            let len = read_u32(bytes, tables + 8) as usize;
+            tables += 12;
+            let Some(path) = bytes.get(tables..tables + len) else {
+                return Err(Error::layout("compiled dictionary is truncated"));
+            };
+            sources.push(Source {
+                path: String::from_utf8_lossy(path).into_owned(),
+                mtime,
+            });
+            tables += len;
+        }
+        ensure!(tables + num_names * NAME_SIZE + num_short * SHORT_SIZE <= bytes.len(),
+            "compiled dictionary is truncated");
+        Ok(Self {
+            data,
+            sources,
+            tables,
+            num_names,
+            num_short,
+            num_collisions,
+        })
+    }
+
+    fn bytes(&self) -> &[u8] {
+        match &self.data {
+            Data::Map(map) => map,
+            Data::Vec(vec) => vec,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.num_names
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.num_names == 0
+    }
+
+    /// Text dictionaries this was compiled from.
+    pub fn sources(&self) -> &[Source] {
+        &self.sources
+    }
+
+    fn pool(&self) -> usize {
+        self.tables + self.num_names * NAME_SIZE + self.num_short * SHORT_SIZE
+    }
+
+    fn hash_at(&self, i: usize) -> u64 {
+        let start = self.tables + i * NAME_SIZE;
+        u64::from_le_bytes(self.bytes()[start..start + 8].try_into().unwrap())
+    }
+
+    fn name_at(&self, i: usize) -> Option<&str> {
+        let bytes = self.bytes();
+        let start = self.tables + i * NAME_SIZE;
+        let offset = self.pool() + read_u32(bytes, start + 8) as usize;
+        let len = read_u32(bytes, start + 12) as usize;
+        std::str::from_utf8(bytes.get(offset..offset + len)?).ok()
+    }
+
+    fn short_at(&self, i: usize) -> (u32, usize) {
+        let bytes = self.bytes();
+        let start = self.tables + self.num_names * NAME_SIZE + i * SHORT_SIZE;
+        (read_u32(bytes, start), read_u32(bytes, start + 4) as usize)
+    }
+
+    pub fn get(&self, hash: u64) -> Option<&str> {
+        let i = search(self.num_names, hash, |i| self.hash_at(i))?;
+        self.name_at(i)
+    }
+
+    /// Full hashes of names with the upper 32 bits `short`.
+    pub fn get_short(&self, short: u32) -> Vec<u64> {
+        let Some(mut i) = search(self.num_short, short, |i| self.short_at(i).0) else {
+            return Vec::new();
+        };
+        // names sharing the short hash are next to each other
+        while i > 0 && self.short_at(i - 1).0 == short {
+            i -= 1;
+        }
+        let mut hashes = Vec::new();
+        while i < self.num_short && self.short_at(i).0 == short {
+            let (_, name) = self.short_at(i);
+            if name < self.num_names {
+                hashes.push(self.hash_at(name));
+            }
+            i += 1;
+        }
+        hashes
+    }
+
+    /// Every (hash, name) in hash order.
+    pub fn iter(&self) -> impl Iterator<Item = (u64, &str)> {
+        (0..self.num_names).filter_map(|i| Some((self.hash_at(i), self.name_at(i)?)))
+    }
+}
+
+fn read_u32(bytes: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(bytes[offset..offset + 4].try_into().unwrap())
+}
+
+// index of `key` in a sorted table of `len` keys
+fn search<K: Ord>(len: usize, key: K, key_at: impl Fn(usize) -> K) -> Option<usize> {
+    let (mut low, mut high) = (0, len);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        match key_at(mid).cmp(&key) {
+            std::cmp::Ordering::Less => low = mid + 1,
+            std::cmp::Ordering::Greater => high = mid,
+            std::cmp::Ordering::Equal => return Some(mid),
+        }
+    }
+    None
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn compiled() {
+        let mut text = Dictionary::new();
+        text.add([
+            "content/ui/foo",
+            "scripts/bar",
+            "@0123456789abcdef=renamed",
+            "@0123456700000000=collides",
+        ].into_iter());
+
+        let mut bin = Vec::new();
+        text.write(&mut bin).unwrap();
+        let compiled = CompiledDictionary::from_bytes(bin).unwrap();
+        assert_eq!(compiled.len(), 4);
+        assert_eq!(compiled.get(murmur(b"scripts/bar")), Some("scripts/bar"));
+        assert_eq!(compiled.get(0x0123456789abcdef), Some("renamed"));
+        assert_eq!(compiled.get(murmur(b"missing")), None);
+        assert_eq!(compiled.get_short(0x01234567), [0x0123456700000000, 0x0123456789abcdef]);
+        assert_eq!(compiled.num_collisions, 1);
+        let foo = murmur(b"content/ui/foo");
+        assert_eq!(compiled.get_short((foo >> 32) as u32), [foo]);
+        assert_eq!(compiled.get_short(0), []);
+
+        // text names are found before compiled ones
+        let mut dict = Dictionary::new();
+        dict.add_compiled(compiled);
+        dict.add([format!("@{foo:016x}=content/ui/other")].into_iter());
+        assert_eq!(dict.get(&MurmurHash(foo)), Some("content/ui/other"));
+        assert_eq!(dict.get(&MurmurHash(0x0123456789abcdef)), Some("renamed"));
+        assert_eq!(dict.len(), 4);
+
+        // collisions within and across dictionaries
+        dict.add(["@01234567ffffffff=text"].into_iter());
+        // only counted within each dictionary
+        assert_eq!(dict.num_collisions(), 1);
+        let collisions = dict.collisions();
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].0, MurmurHash32(0x01234567));
+        assert_eq!(collisions[0].1, [
+            MurmurHash(0x0123456700000000),
+            MurmurHash(0x0123456789abcdef),
+            MurmurHash(0x01234567ffffffff),
+        ]);
+
+        assert!(CompiledDictionary::from_bytes(b"LIMNDICT".to_vec()).is_err());
+
+        // text files are recorded as sources
+        let path = std::env::temp_dir().join(format!("limn-dict-{}.txt", std::process::id()));
+        fs::write(&path, "content/ui/foo\n").unwrap();
+        let mut text = Dictionary::new();
+        text.add_file(&path).unwrap();
+        let mut bin = Vec::new();
+        text.write(&mut bin).unwrap();
+        let compiled = CompiledDictionary::from_bytes(bin).unwrap();
+        assert_eq!(compiled.sources(), [Source::of(&path).unwrap()]);
+        assert_eq!(compiled.get(foo), Some("content/ui/foo"));
+        fs::remove_file(&path).unwrap();
+    }
+
+    fn murmur(key: &[u8]) -> u64 {
+        hash::murmur_hash64a(key, 0)
+    }
+}
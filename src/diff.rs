@@ -0,0 +1,195 @@
+//! Compare the files of two bundle directories.
+//!
+//! Files are keyed by extension and name so a file moving between bundles
+//! is not reported as removed and added.
+
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+
+use crate::bundle;
+use crate::bundle::BundleFd;
+use crate::decompress::Decompressor;
+use crate::error::ensure;
+use crate::error::Error;
+use crate::error::Result;
+use crate::hash::Digest;
+
+/// Where a file lives and a digest of its contents.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SnapshotFile {
+    /// Sorted bundle hashes.
+    pub bundles: Vec<u64>,
+    /// Size and digest of the copy in the first of `bundles`.
+    pub size: u64,
+    pub digest: u64,
+}
+
+/// Every file in a bundle directory.
+#[derive(Debug, Default)]
+pub struct Snapshot {
+    files: BTreeMap<(u64, u64), SnapshotFile>,
+}
+
+impl Snapshot {
+    /// Read every bundle in `dir` on `num_threads` threads.
+    pub fn scan(
+        dir: &Path,
+        decompressor: &dyn Decompressor,
+        num_threads: usize,
+    ) -> Result<Self> {
+        let bundles = bundle::list_bundles(dir)?;
+        let read = bundle::read_parallel(&bundles, num_threads, |i, bundle, scratch| {
+            digest_bundle(bundles[i].1, bundle, decompressor, scratch)
+        })?;
+
+        // copies in several bundles keep the size and digest of the copy in
+        // the lowest bundle hash, not whichever thread finished first
+        let mut files = read.into_iter().flatten().collect::<Vec<_>>();
+        files.sort_unstable_by_key(|read| (read.ext, read.name, read.bundle));
+        let mut snapshot = Self::default();
+        for read in files {
+            let file = snapshot.files.entry((read.ext, read.name)).or_insert(SnapshotFile {
+                bundles: Vec::new(),
+                size: read.size,
+                digest: read.digest,
+            });
+            file.bundles.push(read.bundle);
+        }
+        Ok(snapshot)
+    }
+
+    pub fn get(&self, ext: u64, name: u64) -> Option<&SnapshotFile> {
+        self.files.get(&(ext, name))
+    }
+
+    pub fn files(&self) -> impl Iterator<Item = ((u64, u64), &SnapshotFile)> {
+        self.files.iter().map(|(key, file)| (*key, file))
+    }
+}
+
+struct Digested {
+    ext: u64,
+    name: u64,
+    bundle: u64,
+    size: u64,
+    digest: u64,
+}
+
+fn digest_bundle(
+    hash: u64,
+    bundle: &mut BundleFd<'_>,
+    decompressor: &dyn Decompressor,
+    scratch: &mut Vec<u8>,
+) -> Result<Vec<Digested>> {
+    let index = bundle.index()?.collect::<Vec<_>>();
+    ensure!(index.len() == bundle.num_files as usize, "bundle index ends after {} files", index.len());
+
+    let mut out = Vec::with_capacity(index.len());
+    let mut files = bundle.files(decompressor, scratch)?;
+    while let Some(mut file) = files.next_file()? {
+        let mut digest = Digest::new();
+        let size = io::copy(&mut file, &mut digest)
+            .map_err(|e| Error::from(e).with_entry(file.ext, file.name).with_bundle(Some(hash)))?;
+        out.push(Digested {
+            ext: file.ext,
+            name: file.name,
+            bundle: hash,
+            size,
+            digest: digest.finish(),
+        });
+    }
+    ensure!(out.len() == index.len(), "bundle has {} files instead of {}", out.len(), index.len());
+    Ok(out)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    /// Content changed. The file may also have moved.
+    Modified,
+    /// Same content in different bundles.
+    Moved,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Change<'a> {
+    pub ext: u64,
+    pub name: u64,
+    pub kind: ChangeKind,
+    pub old: Option<&'a SnapshotFile>,
+    pub new: Option<&'a SnapshotFile>,
+}
+
+/// Files that differ between two snapshots, ordered by extension and name.
+pub fn diff<'a>(old: &'a Snapshot, new: &'a Snapshot) -> Vec<Change<'a>> {
+    let mut changes = Vec::new();
+    for (&(ext, name), old_file) in &old.files {
+        let new_file = new.files.get(&(ext, name));
+        let kind = match new_file {
+            None => ChangeKind::Removed,
+            Some(new_file) if new_file.digest != old_file.digest
+                || new_file.size != old_file.size => ChangeKind::Modified,
+            Some(new_file) if new_file.bundles != old_file.bundles => ChangeKind::Moved,
+            Some(_) => continue,
+        };
+        changes.push(Change {
+            ext,
+            name,
+            kind,
+            old: Some(old_file),
+            new: new_file,
+        });
+    }
+
+    for (&(ext, name), new_file) in &new.files {
+        if !old.files.contains_key(&(ext, name)) {
+            changes.push(Change {
+                ext,
+                name,
+                kind: ChangeKind::Added,
+                old: None,
+                new: Some(new_file),
+            });
+        }
+    }
+
+    changes.sort_by_key(|change| (change.ext, change.name));
+    changes
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TestBundles;
+    use crate::decompress::Passthrough;
+
+    #[test]
+    fn diff_dirs() {
+        let old_dir = TestBundles::new("diff-old");
+        let new_dir = TestBundles::new("diff-new");
+        old_dir.write(1, &[(1, b"same"), (2, b"old"), (3, b"moved"), (4, b"removed")]);
+        new_dir.write(1, &[(1, b"same"), (2, b"new"), (5, b"added")]);
+        new_dir.write(2, &[(3, b"moved"), (6, b"copy")]);
+        new_dir.write(3, &[(6, b"other copy")]);
+
+        let old = Snapshot::scan(&old_dir.dir, &Passthrough, 2).unwrap();
+        let new = Snapshot::scan(&new_dir.dir, &Passthrough, 2).unwrap();
+        let changes = diff(&old, &new).into_iter()
+            .map(|change| (change.name, change.kind))
+            .collect::<Vec<_>>();
+        assert_eq!(changes, [
+            (2, ChangeKind::Modified),
+            (3, ChangeKind::Moved),
+            (4, ChangeKind::Removed),
+            (5, ChangeKind::Added),
+            (6, ChangeKind::Added),
+        ]);
+
+        // copies with different contents use the one in the lowest bundle
+        let copy = new.get(0xa14e8dfa2cd117e2, 6).unwrap();
+        assert_eq!(copy.bundles, [2, 3]);
+        assert_eq!(copy.size, 4);
+    }
+}
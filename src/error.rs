@@ -0,0 +1,158 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use crate::hash::extension_name;
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+#[derive(Debug)]
+pub enum ErrorKind {
+    Io(io::Error),
+    Decompress,
+    UnknownVersion(u16),
+    UnexpectedLayout(Cow<'static, str>),
+    MissingResource(PathBuf),
+    MissingOption(&'static str),
+    /// The output format cannot hold the shape of a converted file.
+    UnsupportedFormat(&'static str),
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Decompress => f.write_str("failed to decompress data"),
+            Self::UnknownVersion(version) => write!(f, "unknown bundle version {version}"),
+            Self::UnexpectedLayout(msg) => write!(f, "unexpected layout: {msg}"),
+            Self::MissingResource(path) => write!(f, "missing resource file {}", path.display()),
+            Self::MissingOption(option) => write!(f, "missing {option}"),
+            Self::UnsupportedFormat(format) => write!(f, "file cannot be written as {format}"),
+        }
+    }
+}
+
+/// Error with the location in a bundle where it happened.
+///
+/// Context is attached while the error travels up so the innermost (most
+/// precise) bundle, entry and offset are kept.
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    bundle: Option<u64>,
+    entry: Option<(u64, u64)>,
+    offset: Option<u64>,
+}
+
+impl Error {
+    pub fn new(kind: ErrorKind) -> Self {
+        Self {
+            kind,
+            bundle: None,
+            entry: None,
+            offset: None,
+        }
+    }
+
+    pub(crate) fn layout(msg: impl Into<Cow<'static, str>>) -> Self {
+        Self::new(ErrorKind::UnexpectedLayout(msg.into()))
+    }
+
+    pub fn kind(&self) -> &ErrorKind {
+        &self.kind
+    }
+
+    /// Hash of the bundle being read.
+    pub fn bundle(&self) -> Option<u64> {
+        self.bundle
+    }
+
+    /// Extension and name hash of the bundle entry being read.
+    pub fn entry(&self) -> Option<(u64, u64)> {
+        self.entry
+    }
+
+    /// Byte offset into the decompressed bundle stream.
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+
+    pub fn with_bundle(mut self, bundle: Option<u64>) -> Self {
+        if self.bundle.is_none() {
+            self.bundle = bundle;
+        }
+        self
+    }
+
+    pub fn with_entry(mut self, ext: u64, name: u64) -> Self {
+        if self.entry.is_none() {
+            self.entry = Some((ext, name));
+        }
+        self
+    }
+
+    pub fn with_offset(mut self, offset: u64) -> Self {
+        if self.offset.is_none() {
+            self.offset = Some(offset);
+        }
+        self
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(bundle) = self.bundle {
+            write!(f, "{bundle:016x} ")?;
+        }
+        if let Some((ext, name)) = self.entry {
+            match extension_name(ext) {
+                Some(ext) => write!(f, "{name:016x}.{ext} ")?,
+                None => write!(f, "{name:016x}.{ext:016x} ")?,
+            }
+        }
+        if let Some(offset) = self.offset {
+            write!(f, "@{offset} ")?;
+        }
+        if self.bundle.is_some() || self.entry.is_some() || self.offset.is_some() {
+            f.write_str("- ")?;
+        }
+        write!(f, "{}", self.kind)
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match &self.kind {
+            ErrorKind::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        // errors from `Read` impls over bundle data are tunneled through io::Error
+        if e.get_ref().is_some_and(|inner| inner.is::<Error>()) {
+            *e.into_inner().unwrap().downcast::<Error>().unwrap()
+        } else {
+            Self::new(ErrorKind::Io(e))
+        }
+    }
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Self {
+        Self::new(kind)
+    }
+}
+
+// return `UnexpectedLayout` instead of panicking on malformed files
+macro_rules! ensure {
+    ($cond:expr, $($arg:tt)*) => {
+        if !$cond {
+            return Err(crate::error::Error::layout(format!($($arg)*)));
+        }
+    }
+}
+pub(crate) use ensure;
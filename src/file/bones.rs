@@ -1,59 +1,84 @@
 use super::*;
 
-pub(crate) struct BonesParser;
+/// Skeleton bone names.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Bones {
+    pub lods: Vec<u32>,
+    /// 32-bit murmur hash of each bone name.
+    pub bone_hashes: Vec<u32>,
+    pub names: Vec<String>,
+}
 
-impl Extractor for BonesParser {
-    fn extract(
-        &self,
-        entry: &mut Entry<'_, '_>,
-        file_path: &Path,
-        mut shared: &mut [u8],
-        mut shared_flex: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64> {
-        let variants = entry.variants();
-        assert_eq!(1, variants.len());
-        shared_flex.clear();
+/// Parse a `bones` file.
+pub fn parse_bones(entry: &mut Entry<'_, '_>) -> Result<Bones> {
+    let variants = entry.variants();
+    ensure!(variants.len() == 1, "expected 1 variant, found {}", variants.len());
 
-        let num_bones = entry.read_u32::<LE>().unwrap();
-        let num_lods = entry.read_u32::<LE>().unwrap();
-        for _ in 0..num_bones {
-            let _short_hash = entry.read_u32::<LE>().unwrap();
-        }
+    let num_bones = entry.read_u32::<LE>()?;
+    let num_lods = entry.read_u32::<LE>()?;
+    let mut bone_hashes = Vec::with_capacity(num_bones.min(0x10000) as usize);
+    for _ in 0..num_bones {
+        bone_hashes.push(entry.read_u32::<LE>()?);
+    }
 
-        write!(&mut shared_flex, "{{\"lod\":[").unwrap();
-        for i in 0..num_lods {
-            let lod = entry.read_u32::<LE>().unwrap();
-            if i > 0 {
-                write!(&mut shared_flex, ",").unwrap();
+    let mut lods = Vec::with_capacity(num_lods.min(0x10000) as usize);
+    for _ in 0..num_lods {
+        lods.push(entry.read_u32::<LE>()?);
+    }
+
+    let mut names = Vec::with_capacity(bone_hashes.len());
+    let mut name = Vec::new();
+    for _ in 0..num_bones {
+        loop {
+            let b = entry.read_u8()?;
+            if b == 0 {
+                break;
             }
-            write!(&mut shared_flex, "{lod}").unwrap();
+            name.push(b);
         }
-        write!(&mut shared_flex, "],\"bones\":[").unwrap();
-        let mut len = 0;
-        for i in 0..num_bones {
-            loop {
-                let b = entry.read_u8().unwrap();
-                if b == 0 {
-                    break;
-                } else {
-                    shared[len] = b;
-                    len += 1;
-                }
-            }
 
-            if i > 0 {
-                write!(&mut shared_flex, ",").unwrap();
-            }
-            write!(&mut shared_flex, "\"{}\"", std::str::from_utf8(&shared[..len]).unwrap()).unwrap();
-            len = 0;
+        let Ok(bone) = String::from_utf8(mem::take(&mut name)) else {
+            return Err(Error::layout("bone name is not UTF-8"));
+        };
+        names.push(bone);
+    }
+    ensure!(entry.read_u8().is_err(), "trailing data after bone names");
+
+    Ok(Bones {
+        lods,
+        bone_hashes,
+        names,
+    })
+}
+
+fn to_value(bones: &Bones) -> Value {
+    Value::Object(vec![
+        ("lod".to_string(), Value::Array(bones.lods.iter().map(|&lod| lod.into()).collect())),
+        ("bones".to_string(), Value::Array(bones.names.iter().map(|name| name.as_str().into()).collect())),
+    ])
+}
+
+pub(crate) struct BonesParser;
+
+impl Extractor for BonesParser {
+    fn name(&self) -> &str {
+        "bones"
+    }
+
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+        let (entry, file_path, shared, shared_flex, options) = cx.parts();
+        let bones = parse_bones(entry)?;
+        if let Some(harvest) = options.harvest() {
+            harvest.add(bones.names.iter().map(String::as_str));
         }
-        write!(&mut shared_flex, "]}}").unwrap();
-        assert!(entry.read_u8().is_err());
+        shared_flex.clear();
+        options.format().write(shared_flex, &to_value(&bones))?;
 
+        let mut shared = &mut shared[..];
+        let ext = write_help!(&mut shared, "bones.{}", options.format().extension());
         let parent = file_path.parent().unwrap();
         let stem = file_path.file_stem().unwrap().to_str().unwrap();
-        let path = path_concat(parent, &mut shared, stem, Some("bones.json"));
-        options.write(path, &shared_flex)
+        let path = path_concat(parent, &mut shared, stem, Some(ext))?;
+        options.write(path, shared_flex)
     }
 }
@@ -3,40 +3,44 @@ use super::*;
 pub(crate) struct LuaParser;
 
 impl Extractor for LuaParser {
-    fn extract(
-        &self,
-        mut entry: &mut Entry<'_, '_>,
-        _file_path: &Path,
-        shared: &mut [u8],
-        shared_flex: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64> {
+    fn name(&self) -> &str {
+        "lua"
+    }
+
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+        let root = cx.root();
+        let (mut entry, _file_path, shared, shared_flex, options) = cx.parts();
         let variants = entry.variants();
         shared_flex.clear();
 
-        assert_eq!(1, variants.len());
+        ensure!(variants.len() == 1, "expected 1 variant, found {}", variants.len());
 
         let body_size = variants[0].body_size;
-        let _ = entry.read_u32::<LE>();
-        let mut file_len = entry.read_u32::<LE>().unwrap();
-        let _ = entry.read_u32::<LE>();
+        let _ = entry.read_u32::<LE>()?;
+        let mut file_len = entry.read_u32::<LE>()?;
+        let _ = entry.read_u32::<LE>()?;
 
-        let mut header = entry.read_u32::<LE>().unwrap();
+        let mut header = entry.read_u32::<LE>()?;
         let has_source = if header == 2 {
-            let _ = entry.read_u32::<LE>();
-            let _ = entry.read_u32::<LE>();
-            header = entry.read_u32::<LE>().unwrap();
+            let _ = entry.read_u32::<LE>()?;
+            let _ = entry.read_u32::<LE>()?;
+            header = entry.read_u32::<LE>()?;
             true
         } else {
             file_len = body_size;
             false
         };
         let file_len = file_len as u64;
-        assert!(header == 38423579 || header == 2186495515, "{:016x}.lua has unexpected header {header:08x}", entry.name);
+        ensure!(header == 38423579 || header == 2186495515, "unexpected lua header {header:08x}");
 
-        assert_eq!(entry.read_u8().unwrap(), 0);
-        let path_len = leb128::read::unsigned(&mut entry).unwrap();
-        assert_eq!(entry.read_u8().unwrap(), b'@');
+        ensure!(entry.read_u8()? == 0, "unexpected lua flags");
+        let path_len = match leb128::read::unsigned(&mut entry) {
+            Ok(len) => len,
+            Err(leb128::read::Error::IoError(e)) => return Err(e.into()),
+            Err(leb128::read::Error::Overflow) => return Err(Error::layout("lua chunk name length overflow")),
+        };
+        ensure!(entry.read_u8()? == b'@', "lua chunk name does not start with '@'");
+        ensure!(path_len > 0 && path_len as usize <= shared.len(), "lua chunk name has length {path_len}");
         let len = path_len as usize - 1;
 
         // always write valid LuaJIT header
@@ -45,24 +49,161 @@ impl Extractor for LuaParser {
         leb128::write::unsigned(&mut *shared_flex, path_len).unwrap();
         shared_flex.write_u8(b'@').unwrap();
 
-        let slice;
-        (slice, _) = shared.split_at_mut(len);
+        let (slice, mut shared) = shared.split_at_mut(len);
         for b in slice.iter_mut() {
-            let c = entry.read_u8().unwrap();
+            let c = entry.read_u8()?;
             *b = c;
             shared_flex.write_u8(c).unwrap();
         }
-        let lua_path = std::str::from_utf8(&slice).unwrap();
+        let Ok(lua_path) = std::str::from_utf8(slice) else {
+            return Err(Error::layout("lua chunk name is not UTF-8"));
+        };
+
+        let header_len = shared_flex.len();
+        let Some(code_len) = file_len.checked_sub(header_len as u64) else {
+            return Err(Error::layout(format!("lua file length {file_len} is shorter than its header")));
+        };
+        io::copy(&mut entry.take(code_len), &mut *shared_flex)?;
+        if let Some(harvest) = options.harvest() {
+            harvest.add([lua_path]);
+            harvest.add(string_constants(&shared_flex[header_len..]));
+        }
 
         if has_source && options.config.contains("extract-lua-source") {
-            io::copy(&mut entry.take(file_len - shared_flex.len() as u64), &mut io::sink()).unwrap();
-
             shared_flex.clear();
-            io::copy(&mut entry, &mut *shared_flex).unwrap();
-        } else {
-            io::copy(&mut entry.take(file_len - shared_flex.len() as u64), &mut *shared_flex).unwrap();
+            io::copy(&mut entry, &mut *shared_flex)?;
+        }
+
+        // the chunk name is the script's path
+        let path = path_concat(root, &mut shared, lua_path, None)?;
+        options.write(path, shared_flex)
+    }
+}
+
+fn uleb(data: &mut &[u8]) -> Option<u64> {
+    leb128::read::unsigned(data).ok()
+}
+
+fn take<'a>(data: &mut &'a [u8], len: u64) -> Option<&'a [u8]> {
+    let len = usize::try_from(len).ok()?;
+    if len > data.len() {
+        return None;
+    }
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+    Some(head)
+}
+
+// string constants of the function prototypes following the chunk name in
+// unstripped LuaJIT bytecode, see `lj_bcwrite.c`
+//
+// stops at the first prototype that does not parse
+fn string_constants(mut data: &[u8]) -> Vec<&str> {
+    let mut strings = Vec::new();
+    while let Some(len) = uleb(&mut data)
+        && len > 0
+        && let Some(proto) = take(&mut data, len)
+    {
+        if proto_strings(proto, &mut strings).is_none() {
+            break;
         }
+    }
+    strings
+}
+
+fn proto_strings<'a>(mut proto: &'a [u8], strings: &mut Vec<&'a str>) -> Option<()> {
+    let data = &mut proto;
+    // flags, number of params, frame size and number of upvalues
+    let [_, _, _, num_uv] = take(data, 4)?.try_into().ok()?;
+    let num_kgc = uleb(data)?;
+    let _num_kn = uleb(data)?;
+    let num_bc = uleb(data)?;
+    let size_dbg = uleb(data)?;
+    if size_dbg > 0 {
+        let _first_line = uleb(data)?;
+        let _num_line = uleb(data)?;
+    }
+    take(data, num_bc.checked_mul(4)?)?;
+    take(data, num_uv as u64 * 2)?;
+
+    let mut string = |data: &mut &'a [u8], tp: u64| -> Option<()> {
+        if let Ok(s) = std::str::from_utf8(take(data, tp - 5)?) {
+            strings.push(s);
+        }
+        Some(())
+    };
+    for _ in 0..num_kgc {
+        match uleb(data)? {
+            // child prototype
+            0 => (),
+            // table
+            1 => {
+                let num_array = uleb(data)?;
+                let num_hash = uleb(data)?;
+                for _ in 0..num_array.checked_add(num_hash.checked_mul(2)?)? {
+                    match uleb(data)? {
+                        // nil, false, true
+                        0..=2 => (),
+                        // integer
+                        3 => {
+                            uleb(data)?;
+                        }
+                        // number
+                        4 => {
+                            uleb(data)?;
+                            uleb(data)?;
+                        }
+                        tp => string(data, tp)?,
+                    }
+                }
+            }
+            // signed and unsigned 64-bit integer
+            2 | 3 => {
+                uleb(data)?;
+                uleb(data)?;
+            }
+            // complex number
+            4 => {
+                for _ in 0..4 {
+                    uleb(data)?;
+                }
+            }
+            tp => string(data, tp)?,
+        }
+    }
+    Some(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn constants() {
+        let mut proto = vec![
+            // flags, params, frame size, upvalues
+            0, 0, 2, 1,
+            // constants, numbers, bytecode, debug size
+            3, 0, 1, 0,
+            // bytecode and upvalue
+            0x4b, 0, 1, 0, 0, 0,
+        ];
+        proto.push(5 + 14);
+        proto.extend_from_slice(b"content/ui/foo");
+        // table with a string value and a string key
+        proto.extend_from_slice(&[1, 1, 1, 5 + 3]);
+        proto.extend_from_slice(b"bar");
+        proto.extend_from_slice(&[5 + 3]);
+        proto.extend_from_slice(b"key");
+        proto.extend_from_slice(&[3, 7]);
+        // child prototype
+        proto.push(0);
 
-        options.write(lua_path.as_ref(), &shared_flex)
+        let mut data = vec![proto.len() as u8];
+        data.extend_from_slice(&proto);
+        data.push(0);
+        assert_eq!(string_constants(&data), ["content/ui/foo", "bar", "key"]);
+        // truncated prototypes are ignored
+        assert!(string_constants(&data[..data.len() - 4]).is_empty());
     }
 }
@@ -3,24 +3,22 @@ use super::*;
 pub(crate) struct MaterialParser;
 
 impl Extractor for MaterialParser {
-    fn extract(
-        &self,
-        entry: &mut Entry<'_, '_>,
-        file_path: &Path,
-        shared: &mut [u8],
-        _shared_flex: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64> {
+    fn name(&self) -> &str {
+        "material"
+    }
+
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+        let (entry, file_path, shared, _shared_flex, options) = cx.parts();
         let variants = entry.variants();
-        assert_eq!(1, variants.len());
+        ensure!(variants.len() == 1, "expected 1 variant, found {}", variants.len());
         let prime = &variants[0];
-        assert_eq!(prime.body_size, 30);
-        assert_eq!(prime.tail_size, 0);
+        ensure!(prime.body_size == 30, "unexpected material body size {}", prime.body_size);
+        ensure!(prime.tail_size == 0, "unexpected material tail size {}", prime.tail_size);
 
         let mut data_res = {
             let (data_path, scope_shared) = shared.split_at_mut(prime.body_size as usize);
-            entry.read_exact(data_path).unwrap();
-            file_from_data_path(scope_shared, &options.target, data_path).unwrap()
+            entry.read_exact(data_path)?;
+            file_from_data_path(scope_shared, options, data_path)?
         };
 
         options.open(file_path, |out| {
@@ -8,10 +8,24 @@ use std::mem;
 use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
+use std::panic::RefUnwindSafe;
 use crate::bundle::Entry;
-use crate::oodle::Oodle;
+use crate::bundle::Variant;
+use crate::error::ensure;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::error::Result;
+use crate::decompress::Decompressor;
+use crate::format::Format;
+use crate::format::Value;
+use crate::harvest::Harvest;
+use crate::manifest;
+use crate::manifest::DigestWriter;
+use crate::manifest::Manifest;
+use crate::manifest::ManifestRecord;
+use crate::hash::Digest;
 use crate::hash::MurmurHash;
-use crate::hash::MurmurHash32;
+use crate::dictionary::Dictionary;
 use crate::hash::FILE_EXTENSION;
 use byteorder::ReadBytesExt;
 use byteorder::WriteBytesExt;
@@ -24,6 +38,15 @@ mod material;
 mod package;
 mod strings;
 mod texture;
+pub use bones::Bones;
+pub use bones::parse_bones;
+pub use package::Package;
+pub use package::parse_package;
+pub use strings::Strings;
+pub use strings::parse_strings;
+pub use texture::HighRes;
+pub use texture::Texture;
+pub use texture::parse_texture;
 
 macro_rules! write_help {
     ($dst:expr, $($arg:tt)*) => {{
@@ -33,38 +56,131 @@ macro_rules! write_help {
 }
 pub(crate) use write_help;
 
-trait Extractor {
-    fn extract(
+/// Converts one kind of bundle file.
+///
+/// Register with [`ExtractBuilder::register_extractor`](crate::ExtractBuilder::register_extractor).
+pub trait Extractor: Send + Sync + RefUnwindSafe {
+    /// Write the converted file and return the number of bytes written.
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64>;
+
+    /// Converter name recorded in the manifest.
+    fn name(&self) -> &str;
+}
+
+/// File being extracted and where it goes.
+pub struct ExtractContext<'a, 'e, 'b: 'e> {
+    entry: &'a mut Entry<'e, 'b>,
+    root: &'a Path,
+    path: &'a Path,
+    shared: &'a mut [u8],
+    shared2: &'a mut Vec<u8>,
+    options: &'a ExtractOptions,
+}
+
+impl<'a, 'e, 'b: 'e> ExtractContext<'a, 'e, 'b> {
+    pub fn entry(&mut self) -> &mut Entry<'e, 'b> {
+        self.entry
+    }
+
+    /// Output path of the file, including the bundle file extension.
+    pub fn path(&self) -> &Path {
+        self.path
+    }
+
+    /// Directory output paths start with, `.` or the bundle's directory
+    /// with [`per_bundle`](crate::ExtractBuilder::per_bundle).
+    pub fn root(&self) -> &'a Path {
+        self.root
+    }
+
+    pub fn options(&self) -> &ExtractOptions {
+        self.options
+    }
+
+    /// Buffer reused between files.
+    pub fn scratch(&mut self) -> &mut Vec<u8> {
+        self.shared2
+    }
+
+    pub fn open(
         &self,
-        entry: &mut Entry<'_, '_>,
-        file_path: &Path,
-        shared: &mut [u8],
-        shared2: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64>;
+        path: &Path,
+        scope: impl FnMut(&mut dyn io::Write) -> io::Result<u64>,
+    ) -> Result<u64> {
+        self.options.open(path, scope)
+    }
+
+    pub fn write(&self, path: &Path, buffer: &[u8]) -> Result<u64> {
+        self.options.write(path, buffer)
+    }
+
+    // built-in extractors carve up a fixed size buffer for paths and
+    // small reads next to the growable one
+    #[allow(clippy::type_complexity)]
+    pub(crate) fn parts(&mut self) -> (&mut Entry<'e, 'b>, &Path, &mut [u8], &mut Vec<u8>, &ExtractOptions) {
+        (self.entry, self.path, self.shared, self.shared2, self.options)
+    }
+}
+
+// (extension, converter) of extractors registered by default
+pub(crate) fn builtin_extractors() -> [(&'static str, Box<dyn Extractor>); 6] {
+    [
+        ("bones", Box::new(bones::BonesParser)),
+        ("lua", Box::new(lua::LuaParser)),
+        ("material", Box::new(material::MaterialParser)),
+        ("package", Box::new(package::PackageParser)),
+        ("strings", Box::new(strings::StringsParser)),
+        ("texture", Box::new(texture::TextureParser)),
+    ]
 }
 
 pub struct ExtractOptions {
     pub(crate) target: PathBuf,
     pub(crate) out: Box<dyn FileOpen>,
-    pub(crate) oodle: Oodle,
-    pub(crate) dictionary: HashMap<MurmurHash, String>,
-    pub(crate) dictionary_short: HashMap<MurmurHash32, MurmurHash>,
+    pub(crate) decompressor: Box<dyn Decompressor>,
+    pub(crate) extractors: HashMap<u64, Box<dyn Extractor>>,
+    pub(crate) dictionary: Dictionary,
     pub(crate) config: HashSet<String>,
+    pub(crate) format: Format,
+    pub(crate) manifest: Option<Manifest>,
+    pub(crate) per_bundle: bool,
+    pub(crate) harvest: Option<Harvest>,
     pub(crate) skip_extract: bool,
     pub(crate) skip_unknown: bool,
     pub(crate) as_blob: bool,
 }
 
 impl ExtractOptions {
-    pub fn oodle(&self) -> &Oodle {
-        &self.oodle
+    pub fn decompressor(&self) -> &dyn Decompressor {
+        &*self.decompressor
     }
 
     pub fn contains_key(&self, key: &MurmurHash) -> bool {
         self.dictionary.contains_key(key)
     }
 
+    pub fn lookup(&self, key: &MurmurHash) -> Option<&str> {
+        self.dictionary.get(key)
+    }
+
+    pub fn dictionary(&self) -> &Dictionary {
+        &self.dictionary
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    /// Whether files are written under a directory named after their bundle.
+    pub fn per_bundle(&self) -> bool {
+        self.per_bundle
+    }
+
+    /// Candidate names collected so far, if harvesting is enabled.
+    pub fn harvest(&self) -> Option<&Harvest> {
+        self.harvest.as_ref()
+    }
+
     pub fn skip_extract(&self) -> bool {
         self.skip_extract
     }
@@ -73,40 +189,112 @@ impl ExtractOptions {
         self.skip_unknown
     }
 
+    /// Files written so far, if the manifest is enabled.
+    pub fn manifest(&self) -> Option<&Manifest> {
+        self.manifest.as_ref()
+    }
+
     pub fn open(
         &self,
         path: &Path,
         mut scope: impl FnMut(&mut dyn io::Write) -> io::Result<u64>,
-    ) -> io::Result<u64> {
-        self.out.open(path, &mut scope)
+    ) -> Result<u64> {
+        if self.manifest.is_none() {
+            return Ok(self.out.open(path, &mut scope)?);
+        }
+
+        let mut digest = Digest::new();
+        let mut size = 0;
+        let wrote = self.out.open(path, &mut |out| {
+            let mut out = DigestWriter {
+                inner: out,
+                digest: Digest::new(),
+                size: 0,
+            };
+            let wrote = scope(&mut out)?;
+            (digest, size) = (out.digest, out.size);
+            Ok(wrote)
+        })?;
+        manifest::record_written(path, size, digest.finish());
+        Ok(wrote)
     }
 
     pub fn write(
         &self,
         path: &Path,
         buffer: &[u8],
-    ) -> io::Result<u64> {
+    ) -> Result<u64> {
         self.open(path, |out| {
-            out.write_all(&buffer)?;
+            out.write_all(buffer)?;
             Ok(buffer.len() as u64)
         })
     }
+
+    /// Write `manifest.jsonl` when the manifest is enabled and complete
+    /// archive outputs.
+    ///
+    /// Call once after every file was extracted.
+    pub fn finish(&self) -> Result<()> {
+        if let Some(manifest) = &self.manifest {
+            let mut buffer = Vec::new();
+            manifest.write(&mut buffer);
+            let path = Path::new(".").join(manifest::FILE_NAME);
+            self.out.open(&path, &mut |out| {
+                out.write_all(&buffer)?;
+                Ok(buffer.len() as u64)
+            })?;
+        }
+        self.out.finish()?;
+        Ok(())
+    }
 }
 
 pub fn extract(
     mut entry: Entry<'_, '_>,
     pool: &mut Pool,
     options: &ExtractOptions,
-) -> io::Result<u64> {
-    let extractor: Option<&'static dyn Extractor> = 'res: {Some(match entry.ext {
-        0x18dead01056b72e9 => &bones::BonesParser,
-        0xa14e8dfa2cd117e2 => &lua::LuaParser,
-        0xeac0b497876adedf => &material::MaterialParser,
-        0xad9c6d9ed1e5e77a => &package::PackageParser,
-        0x0d972bab10b40fd3 => &strings::StringsParser,
-        0xcd4238c6a0c69e32 => &texture::TextureParser,
-        _ => break 'res None,
-    })};
+) -> Result<u64> {
+    let Some(manifest) = &options.manifest else {
+        return extract_(&mut entry, pool, options).map_err(|e| e
+            .with_entry(entry.ext, entry.name)
+            .with_offset(entry.position())
+            .with_bundle(entry.bundle));
+    };
+
+    manifest::take_written();
+    let res = extract_(&mut entry, pool, options);
+    let written = manifest::take_written();
+    if res.is_ok() {
+        let converter = match options.extractors.get(&entry.ext) {
+            Some(extractor) if !options.as_blob => extractor.name(),
+            _ => "raw",
+        };
+        for (path, size, digest) in written {
+            manifest.push(ManifestRecord {
+                path,
+                ext: entry.ext,
+                name: entry.name,
+                resolved: options.lookup(&MurmurHash::from(entry.name)).map(str::to_string),
+                bundle: entry.bundle,
+                variants: entry.variants().to_vec(),
+                converter: converter.to_string(),
+                size,
+                digest,
+            });
+        }
+    }
+    res.map_err(|e| e
+        .with_entry(entry.ext, entry.name)
+        .with_offset(entry.position())
+        .with_bundle(entry.bundle))
+}
+
+fn extract_(
+    entry: &mut Entry<'_, '_>,
+    pool: &mut Pool,
+    options: &ExtractOptions,
+) -> Result<u64> {
+    let extractor = options.extractors.get(&entry.ext);
 
     let Pool {
         shared,
@@ -117,6 +305,14 @@ pub fn extract(
     }
     let mut shared = &mut shared[..];
 
+    let root = match entry.bundle {
+        Some(bundle) if options.per_bundle => match options.dictionary.get(&MurmurHash::from(bundle)) {
+            Some(s) => path_concat(Path::new("."), &mut shared, s, None)?,
+            None => Path::new(write_help!(&mut shared, "./{bundle:016x}")),
+        },
+        _ => Path::new("."),
+    };
+
     let file_name = match options.dictionary.get(&MurmurHash::from(entry.name)) {
         Some(s) => s,
         None => write_help!(&mut shared, "{:016x}", entry.name),
@@ -128,7 +324,7 @@ pub fn extract(
     };
 
     if options.as_blob || extractor.is_none() {
-        let path = path_concat(&Path::new("."), &mut shared, file_name, Some(ext_name));
+        let path = path_concat(root, &mut shared, file_name, Some(ext_name))?;
 
         shared2.clear();
         shared2.reserve(0x1000);
@@ -148,13 +344,20 @@ pub fn extract(
 
         options.open(path, |out| {
             out.write_all(&shared2)?;
-            io::copy(&mut entry, out).map(|copied| copied + shared2.len() as u64)
+            io::copy(&mut *entry, out).map(|copied| copied + shared2.len() as u64)
         })
     } else {
-        let out = path_concat(&Path::new("."), &mut shared, file_name, Some(ext_name));
+        let out = path_concat(root, &mut shared, file_name, Some(ext_name))?;
 
         let extractor = extractor.unwrap();
-        extractor.extract(&mut entry, out, shared, shared2, options)
+        extractor.extract(&mut ExtractContext {
+            entry,
+            root,
+            path: out,
+            shared,
+            shared2,
+            options,
+        })
     }
 }
 
@@ -212,32 +415,67 @@ fn write_slice<'a>(
     Ok(slice)
 }
 
-fn no_escape(path: &Path) -> bool {
-    for part in path.components() {
-        match part {
-            //Component::RootDir => return false,
-            Component::ParentDir => return false,
-            _ => (),
-        }
-    }
-    true
-}
-
+// `root/path.ext` written to `buffer`
+//
+// `path` comes from bundle data or the dictionary and has to stay under `root`
 fn path_concat<'a>(
     root: &Path,
     buffer: &mut &'a mut [u8],
     path: &str,
     ext: Option<&str>,
-) -> &'a Path {
+) -> Result<&'a Path> {
+    ensure!(!path.is_empty() && Path::new(path).components().all(|c| matches!(c, Component::Normal(_))),
+        "path {path:?} is not relative or leaves its directory");
     let root = root.to_str().unwrap();
     let path = if let Some(ext) = ext {
         write_help!(buffer, "{root}/{path}.{ext}")
     } else {
         write_help!(buffer, "{root}/{path}")
     };
-    let path = Path::new(path);
-    assert!(no_escape(path), "{}", path.display());
-    path
+    Ok(Path::new(path))
+}
+
+/// Whether some of a file's data is stored in a `data/**` resource file
+/// instead of the bundle.
+pub fn uses_resource_file(ext: u64, variants: &[Variant]) -> bool {
+    match ext {
+        // material body is the resource path
+        0xeac0b497876adedf => true,
+        // texture is either fully in the resource file or has its larger
+        // mipmaps there
+        0xcd4238c6a0c69e32 => variants.first()
+            .is_some_and(|v| v.unknown1 == 1 || v.tail_size > 0),
+        _ => false,
+    }
+}
+
+/// Path of the `data/**` resource file a file refers to.
+///
+/// `data` is the full contents of the file.
+pub fn resource_path<'a>(ext: u64, variants: &[Variant], data: &'a [u8]) -> Result<Option<&'a str>> {
+    if !uses_resource_file(ext, variants) {
+        return Ok(None);
+    }
+
+    let prime = &variants[0];
+    let path = if ext == 0xcd4238c6a0c69e32 && prime.unknown1 == 0 {
+        // path to larger mipmaps is at the end of the texture
+        let tail = prime.tail_size as usize;
+        ensure!(tail <= data.len(), "texture tail size {tail} is larger than the file");
+        &data[data.len() - tail..]
+    } else {
+        let body = prime.body_size as usize;
+        ensure!(body <= data.len(), "body size {body} is larger than the file");
+        &data[..body]
+    };
+
+    let path = path.split(|b| *b == 0).next().unwrap();
+    let Some(path) = data_path_from(path) else {
+        return Err(Error::layout("resource path is not UTF-8"));
+    };
+    ensure!(Path::new(path).components().all(|c| matches!(c, Component::Normal(_))),
+        "resource path {path:?} escapes the bundle directory");
+    Ok(Some(path))
 }
 
 fn data_path_from(buffer: &[u8]) -> Option<&str> {
@@ -249,19 +487,213 @@ fn data_path_from(buffer: &[u8]) -> Option<&str> {
 
 fn file_from_data_path(
     mut shared: &mut [u8],
-    target: &Path,
+    options: &ExtractOptions,
     path: &[u8],
-) -> io::Result<File> {
+) -> Result<File> {
     let path = path.split(|b| *b == 0).next().unwrap();
-    let path = data_path_from(&*path).unwrap();
-    let path = path_concat(target, &mut shared, path, None);
-    assert!(path.starts_with(target));
+    let Some(path) = data_path_from(path) else {
+        return Err(Error::layout("resource path is not UTF-8"));
+    };
+    if let Some(harvest) = &options.harvest {
+        harvest.add([path]);
+    }
+    let target = &options.target;
+    let path = path_concat(target, &mut shared, path, None)?;
+    ensure!(path.starts_with(target), "resource path {} escapes bundle directory", path.display());
     let Ok(fd) = File::open(path) else {
-        if cfg!(debug_assertions) {
-            panic!("failed to load {}", path.display());
-        }
-        return Err(io::Error::new(io::ErrorKind::NotFound,
-            "failed to find texture resource file under data/*/*"));
+        return Err(ErrorKind::MissingResource(path.to_path_buf()).into());
     };
     Ok(fd)
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+    use crate::ExtractBuilder;
+    use crate::bundle;
+    use crate::bundle::BundleFd;
+    use crate::bundle::BundleWriter;
+    use crate::bundle::TestBundles;
+    use crate::decompress::Passthrough;
+    use crate::filter::NameFilter;
+    use crate::hash;
+
+    struct Upper;
+
+    impl Extractor for Upper {
+        fn name(&self) -> &str {
+            "upper"
+        }
+
+        fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+            let path = cx.path().with_extension("txt");
+            let mut data = mem::take(cx.scratch());
+            data.clear();
+            cx.entry().read_to_end(&mut data)?;
+            data.make_ascii_uppercase();
+            let wrote = cx.write(&path, &data);
+            *cx.scratch() = data;
+            wrote
+        }
+    }
+
+    #[test]
+    fn register_extractor() {
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"body", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut builder = ExtractBuilder::new();
+        let out = written.clone();
+        builder.input(".")
+            .output_custom(move |path, data| out.lock().unwrap().push((path.to_string(), data.to_vec())))
+            .decompressor(Box::new(Passthrough))
+            .register_extractor("lua", Box::new(Upper));
+        let options = builder.build().unwrap();
+
+        let mut rdr = io::Cursor::new(buf);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = bundle.files(options.decompressor(), &mut scratch).unwrap();
+        let file = files.next_file().unwrap().unwrap();
+        extract(file, &mut Pool::new(), &options).unwrap();
+
+        let written = written.lock().unwrap();
+        assert_eq!(written.len(), 1);
+        assert!(written[0].0.ends_with("0000000000000001.txt"), "{}", written[0].0);
+        assert_eq!(written[0].1, b"BODY");
+    }
+
+    #[test]
+    fn per_bundle() {
+        // lua bytecode named after its script, stripped of everything else
+        let mut lua = Vec::new();
+        for n in [0_u32, 0, 0, 38423579] {
+            lua.extend(n.to_le_bytes());
+        }
+        lua.extend(b"\0\x10@scripts/foo.lua");
+
+        let test_bundles = TestBundles::new("per-bundle");
+        let inventory = hash::murmur_hash64a(b"packages/inventory", 0);
+        test_bundles.write(inventory, &[(1, &lua)]);
+        test_bundles.write(2, &[(1, &lua)]);
+        let dir = &test_bundles.dir;
+
+        let written = Arc::new(Mutex::new(Vec::new()));
+        let mut builder = ExtractBuilder::new();
+        let out = written.clone();
+        builder.input(dir)
+            .output_custom(move |path, _| out.lock().unwrap().push(path.to_string()))
+            .decompressor(Box::new(Passthrough))
+            .dictionary(["packages/inventory"].into_iter())
+            .per_bundle(true);
+        let options = builder.build().unwrap();
+
+        // `--bundle` matches globs against the package name
+        let mut filter = NameFilter::new();
+        filter.bundle("packages/inv*");
+        let mut bundles = bundle::list_bundles(dir).unwrap();
+        bundles.sort_unstable_by_key(|(_, hash)| *hash);
+        let matched = bundles.iter()
+            .map(|(_, hash)| *hash)
+            .filter(|hash| filter.is_match(*hash, options.lookup(&(*hash).into())))
+            .collect::<Vec<_>>();
+        assert_eq!(matched, [inventory]);
+        let mut by_hash = NameFilter::new();
+        by_hash.bundle("0000000000000002");
+        assert!(by_hash.is_match(2, None));
+        assert!(!by_hash.is_match(inventory, Some("packages/inventory")));
+
+        // every bundle's copy of a script goes to its own directory
+        let mut scratch = Vec::new();
+        for (path, hash) in bundles {
+            let mut rdr = File::open(path).unwrap();
+            let mut bundle = BundleFd::new(Some(hash), &mut rdr).unwrap();
+            let mut files = bundle.files(options.decompressor(), &mut scratch).unwrap();
+            while let Some(file) = files.next_file().unwrap() {
+                extract(file, &mut Pool::new(), &options).unwrap();
+            }
+        }
+
+        let written = written.lock().unwrap();
+        assert_eq!(*written, [
+            "./0000000000000002/scripts/foo.lua",
+            "./packages/inventory/scripts/foo.lua",
+        ]);
+    }
+
+    #[test]
+    fn escaping_paths() {
+        let mut buf = [0; 0x100];
+        for path in ["../x", "data/../../x", "/abs", ""] {
+            assert!(path_concat(Path::new("."), &mut &mut buf[..], path, None).is_err(), "{path:?}");
+        }
+        let path = path_concat(Path::new("."), &mut &mut buf[..], "data/ab/cd", Some("lua")).unwrap();
+        assert_eq!(path, Path::new("./data/ab/cd.lua"));
+
+        // a dictionary name leaving the output is an error, not a panic
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0x1234, 1, 0)
+            .variant(0, 0, b"body", b"");
+        let mut bundle_data = Vec::new();
+        writer.write(&mut bundle_data).unwrap();
+        let mut builder = ExtractBuilder::new();
+        builder.input(".")
+            .output_custom(|_, _| panic!("nothing is written"))
+            .decompressor(Box::new(Passthrough))
+            .dictionary(["@0000000000000001=../escape"].into_iter());
+        let options = builder.build().unwrap();
+        let mut rdr = io::Cursor::new(bundle_data);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = bundle.files(options.decompressor(), &mut scratch).unwrap();
+        let file = files.next_file().unwrap().unwrap();
+        assert!(extract(file, &mut Pool::new(), &options).is_err());
+    }
+
+    #[test]
+    fn parse_typed() {
+        let mut package = Vec::new();
+        package.extend(43_u32.to_le_bytes());
+        package.extend(1_u32.to_le_bytes());
+        package.extend(0xa14e8dfa2cd117e2_u64.to_le_bytes());
+        package.extend(5_u64.to_le_bytes());
+        package.push(1);
+
+        let mut bones = Vec::new();
+        for n in [2_u32, 1, 0xaa, 0xbb, 3] {
+            bones.extend(n.to_le_bytes());
+        }
+        bones.extend(b"root\0hand\0");
+
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xad9c6d9ed1e5e77a, 1, 0)
+            .variant(0, 0, &package, b"");
+        writer.add_file(0x18dead01056b72e9, 2, 0)
+            .variant(0, 0, &bones, b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+
+        let mut rdr = io::Cursor::new(buf);
+        let mut bundle = BundleFd::new(None, &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = bundle.files(&Passthrough, &mut scratch).unwrap();
+        {
+            let mut file = files.next_file().unwrap().unwrap();
+            assert_eq!(parse_package(&mut file).unwrap(), Package {
+                entries: vec![(0xa14e8dfa2cd117e2, 5)],
+            });
+        }
+        let mut file = files.next_file().unwrap().unwrap();
+        assert_eq!(parse_bones(&mut file).unwrap(), Bones {
+            lods: vec![3],
+            bone_hashes: vec![0xaa, 0xbb],
+            names: vec!["root".to_string(), "hand".to_string()],
+        });
+    }
+}
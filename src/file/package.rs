@@ -2,54 +2,66 @@ use crate::hash::FILE_EXTENSION;
 use crate::hash::MurmurHash;
 use super::*;
 
+/// Files loaded together with a package.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Package {
+    /// (extension hash, name hash) of each file.
+    pub entries: Vec<(u64, u64)>,
+}
+
+/// Parse a `package` file.
+pub fn parse_package(entry: &mut Entry<'_, '_>) -> Result<Package> {
+    let variants = entry.variants();
+    ensure!(variants.len() == 1, "expected 1 variant, found {}", variants.len());
+
+    let version = entry.read_u32::<LE>()?;
+    ensure!(version == 43, "unknown package version {version}");
+    let num_files = entry.read_u32::<LE>()?;
+
+    let mut entries = Vec::with_capacity(num_files.min(0x10000) as usize);
+    for _ in 0..num_files {
+        let ext_hash = entry.read_u64::<LE>()?;
+        let name_hash = entry.read_u64::<LE>()?;
+        entries.push((ext_hash, name_hash));
+    }
+    ensure!(entry.read_u8()? == 1, "unexpected package trailer");
+    ensure!(entry.read_u8().is_err(), "trailing data after package entries");
+
+    Ok(Package { entries })
+}
+
+fn to_value(package: &Package, options: &ExtractOptions) -> Value {
+    Value::Array(package.entries.iter().map(|&(ext_hash, name_hash)| {
+        let mut fields = vec![("name_hash".to_string(), format!("{name_hash:016x}").into())];
+        if let Some(name) = options.dictionary.get(&MurmurHash(name_hash)) {
+            fields.push(("name".to_string(), name.into()));
+        }
+        match FILE_EXTENSION.binary_search_by(|(probe, _)| probe.cmp(&ext_hash)) {
+            Ok(i) => fields.push(("ext".to_string(), FILE_EXTENSION[i].1.into())),
+            Err(_) => fields.push(("ext_hash".to_string(), format!("{ext_hash:016x}").into())),
+        }
+        Value::Object(fields)
+    }).collect())
+}
+
 pub(crate) struct PackageParser;
 
 impl Extractor for PackageParser {
-    fn extract(
-        &self,
-        entry: &mut Entry<'_, '_>,
-        file_path: &Path,
-        mut shared: &mut [u8],
-        mut shared_flex: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64> {
-        let variants = entry.variants();
-        assert_eq!(1, variants.len());
-        shared_flex.clear();
+    fn name(&self) -> &str {
+        "package"
+    }
 
-        assert_eq!(43, entry.read_u32::<LE>().unwrap());
-        let num_files = entry.read_u32::<LE>().unwrap();
-
-        write!(&mut shared_flex, "[").unwrap();
-        for i in 0..num_files {
-            let ext_hash = entry.read_u64::<LE>().unwrap();
-            let name_hash = entry.read_u64::<LE>().unwrap();
-            let ext = FILE_EXTENSION
-                .binary_search_by(|(probe, _)| probe.cmp(&ext_hash))
-                .map(|i| FILE_EXTENSION[i].1)
-                .ok();
-            let name = options.dictionary.get(&MurmurHash(name_hash));
-
-            if i > 0 {
-                write!(&mut shared_flex, ",").unwrap();
-            }
-            write!(&mut shared_flex, "{{\"name_hash\":\"{name_hash:016x}\",").unwrap();
-            if let Some(name) = name {
-                write!(&mut shared_flex, "\"name\":\"{name}\",").unwrap();
-            }
-            if let Some(ext) = ext {
-                write!(&mut shared_flex, "\"ext\":\"{ext}\"}}").unwrap();
-            } else {
-                write!(&mut shared_flex, "{{\"ext_hash\":\"{ext_hash:016x}\",").unwrap();
-            }
-        }
-        write!(&mut shared_flex, "]").unwrap();
-        assert_eq!(1, entry.read_u8().unwrap());
-        assert!(entry.read_u8().is_err());
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+        let (entry, file_path, shared, shared_flex, options) = cx.parts();
+        let package = parse_package(entry)?;
+        shared_flex.clear();
+        options.format().write(shared_flex, &to_value(&package, options))?;
 
+        let mut shared = &mut shared[..];
+        let ext = write_help!(&mut shared, "package.{}", options.format().extension());
         let parent = file_path.parent().unwrap();
         let stem = file_path.file_stem().unwrap().to_str().unwrap();
-        let path = path_concat(parent, &mut shared, stem, Some("package.json"));
-        options.write(path, &shared_flex)
+        let path = path_concat(parent, &mut shared, stem, Some(ext))?;
+        options.write(path, shared_flex)
     }
 }
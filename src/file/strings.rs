@@ -65,113 +65,100 @@ impl fmt::Display for Language {
     }
 }
 
+/// Localized strings keyed by the 32-bit murmur hash of their names.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Strings {
+    /// (language code, (short hash, string)) of each variant.
+    pub variants: Vec<(u32, Vec<(u32, String)>)>,
+}
+
+/// Parse a `strings` file.
+///
+/// Strings are cut at their first nul and invalid UTF-8 is replaced.
+pub fn parse_strings(entry: &mut Entry<'_, '_>) -> Result<Strings> {
+    let mut variants = Vec::with_capacity(entry.variants().len());
+    let mut variant_i = 0;
+    while let Some(variant) = entry.variants().get(variant_i) {
+        variant_i += 1;
+        let kind = variant.kind;
+        let variant_size = variant.body_size;
+
+        let _unk = entry.read_u32::<LE>()?;
+        //assert_eq!(_unk, 0x3e85f3ae);
+        let num_items = entry.read_u32::<LE>()?;
+        let mut offset = 8;
+        // (short hash, string length)
+        let mut hashes = Vec::with_capacity(num_items.min(0x10000) as usize);
+        let mut last = None;
+        for _ in 0..num_items {
+            let short_hash = entry.read_u32::<LE>()?;
+            let string_offset = entry.read_u32::<LE>()?;
+            if let Some((last_hash, last_offset)) = last {
+                ensure!(string_offset >= last_offset && string_offset <= variant_size,
+                    "string offset {string_offset} is out of order");
+                hashes.push((last_hash, string_offset - last_offset));
+            }
+            last = Some((short_hash, string_offset));
+            offset += 8;
+        }
+        if let Some((last_hash, last_offset)) = last {
+            ensure!(variant_size >= last_offset, "string offset {last_offset} is past the end");
+            hashes.push((last_hash, variant_size - last_offset));
+        }
+
+        let mut strings = Vec::with_capacity(hashes.len());
+        let mut buffer = Vec::new();
+        for (short_hash, string_len) in hashes {
+            let string_len = string_len as usize;
+            ensure!(string_len >= 2, "string with length {string_len} is too short");
+            buffer.resize(string_len, 0);
+            entry.read_exact(&mut buffer)?;
+            ensure!(buffer[string_len - 1] == 0, "string is not nul terminated");
+
+            // characters with a nul before the end have
+            // trailing "[Narrative]" or "[Dev]" text
+            let string = buffer[..string_len - 2].split(|b| *b == 0).next().unwrap();
+            strings.push((short_hash, String::from_utf8_lossy(string).into_owned()));
+            offset += string_len;
+        }
+
+        ensure!(offset == variant_size as usize, "strings end at {offset} instead of {variant_size}");
+        variants.push((kind, strings));
+    }
+
+    Ok(Strings { variants })
+}
+
+fn to_value(strings: Vec<(u32, String)>, options: &ExtractOptions) -> Value {
+    Value::Object(strings.into_iter().filter_map(|(short_hash, string)| {
+        let names = options.dictionary.get_short(&short_hash.into()).iter()
+            .filter_map(|hash| options.lookup(hash))
+            .collect::<Vec<_>>();
+        let key = match names.as_slice() {
+            [name] => name.to_string(),
+            [] if !options.skip_unknown => format!("{short_hash:08x}"),
+            [] => return None,
+            // only the short hash is stored, so keep every candidate
+            names => names.join("|"),
+        };
+        Some((key, string.into()))
+    }).collect())
+}
+
 pub(crate) struct StringsParser;
 
 impl Extractor for StringsParser {
-    fn extract(
-        &self,
-        entry: &mut Entry<'_, '_>,
-        file_path: &Path,
-        shared: &mut [u8],
-        shared_flex: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64> {
+    fn name(&self) -> &str {
+        "strings"
+    }
+
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+        let (entry, file_path, shared, shared_flex, options) = cx.parts();
         let mut wrote = 0;
-        let mut variant_i = 0;
-        while let Some(variant) = entry.variants().get(variant_i) {
+        for (kind, strings) in parse_strings(entry)?.variants {
             let mut shared = &mut shared[..];
-            variant_i += 1;
-            let kind = variant.kind;
-            let variant_size = variant.body_size;
-
-            let _unk = entry.read_u32::<LE>()?;
-            //assert_eq!(_unk, 0x3e85f3ae);
-            let num_items = entry.read_u32::<LE>()?;
-            let mut offset = 8;
-            let size_needed = num_items as usize * 8;
-            assert!(shared.len() > (size_needed + 0x1000), "{}, {size_needed}", shared.len());
-            let (hashes, buffer) = shared.split_at_mut(size_needed);
-            let mut hashes_into = &mut hashes[..];
-            let mut last = None;
-            for _ in 0..num_items {
-                let short_hash = entry.read_u32::<LE>()?;
-                let string_offset = entry.read_u32::<LE>()?;
-                if let Some((last_hash, last_offset)) = last {
-                    hashes_into.write_u32::<LE>(last_hash)?;
-                    // store length
-                    hashes_into.write_u32::<LE>(string_offset - last_offset)?;
-                }
-                last = Some((short_hash, string_offset));
-                offset += 8;
-            }
-            if let Some((last_hash, last_offset)) = last {
-                hashes_into.write_u32::<LE>(last_hash)?;
-                hashes_into.write_u32::<LE>(variant_size - last_offset)?;
-            }
-
-            let mut hashes = &hashes[..];
             shared_flex.clear();
-            let mut is_trailing = false;
-            write!(shared_flex, "{{")?;
-            for _ in 0..num_items {
-                let short_hash = hashes.read_u32::<LE>()?;
-                let string_len = hashes.read_u32::<LE>()? as usize;
-                let do_print = if let Some(hash) = options.dictionary_short.get(&short_hash.into()) {
-                    let key = options.dictionary.get(hash).unwrap();
-                    if is_trailing {
-                        write!(shared_flex, ",")?;
-                    }
-                    is_trailing = true;
-                    write!(shared_flex, "{key:?}:\"")?;
-                    true
-                } else if !options.skip_unknown {
-                    if is_trailing {
-                        write!(shared_flex, ",")?;
-                    }
-                    is_trailing = true;
-                    write!(shared_flex, "\"{short_hash:08x}\":\"")?;
-                    true
-                } else {
-                    false
-                };
-
-                assert!(buffer.len() >= string_len);
-                entry.read_exact(&mut buffer[..string_len])?;
-                assert_eq!(0, buffer[string_len - 1]);
-                if do_print {
-                    shared_flex.reserve(string_len * 2);
-                    for c in std::str::from_utf8(&buffer[..string_len - 2]).unwrap().chars() {
-                        match c {
-                            '\0' => {
-                                // characters with a nul before the end have
-                                // trailing "[Narrative]" or "[Dev]" text
-
-                                break;
-                            }
-                            '\t'
-                            | '\n'
-                            | '\r'
-                            | '"' => {
-                                write!(shared_flex, "\\{}", match c {
-                                    '\t' => 't',
-                                    '\n' => 'n',
-                                    '\r' => 'r',
-                                    '"'  => '"',
-                                    _ => unreachable!(),
-                                })?;
-                            }
-                            _ => {
-                                write!(shared_flex, "{c}")?;
-                            }
-                        }
-                    }
-                    write!(shared_flex, "\"")?;
-                }
-                offset += string_len;
-            }
-
-            assert_eq!(offset, variant_size as usize);
-            write!(shared_flex, "}}")?;
+            options.format().write(shared_flex, &to_value(strings, options))?;
 
             let lang = if let Some(lang) = Language::from_code(kind) {
                 write_help!(&mut shared, "{lang}")
@@ -182,11 +169,45 @@ impl Extractor for StringsParser {
             let stem = file_path.file_stem().unwrap().to_str().unwrap();
             let file = write_help!(&mut shared, "{stem}.{lang}");
             let parent = file_path.parent().unwrap();
-            let path = path_concat(parent, &mut shared, file, Some("json"));
+            let path = path_concat(parent, &mut shared, file, Some(options.format().extension()))?;
 
-            wrote += options.write(path, &shared_flex)?;
+            wrote += options.write(path, shared_flex)?;
         }
 
         Ok(wrote)
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::ExtractBuilder;
+
+    #[test]
+    fn colliding_keys() {
+        // both hash to the short hash 2be5d8fe
+        let mut builder = ExtractBuilder::new();
+        builder.input(".")
+            .output(None::<&str>)
+            .dictionary(["loc_test_89084", "loc_test_126676", "loc_single"].into_iter());
+        let options = builder.build().unwrap();
+        assert_eq!(options.dictionary.num_collisions(), 1);
+
+        let single = (crate::hash::murmur_hash64a(b"loc_single", 0) >> 32) as u32;
+        let value = to_value(vec![
+            (0x2be5d8fe, "ambiguous".to_string()),
+            (single, "single".to_string()),
+            (0x12345678, "unknown".to_string()),
+        ], &options);
+        let Value::Object(fields) = value else {
+            panic!("not an object");
+        };
+        let fields = fields.iter()
+            .map(|(key, value)| (key.as_str(), value))
+            .collect::<Vec<_>>();
+        assert_eq!(fields, [
+            ("loc_test_126676|loc_test_89084", &Value::from("ambiguous")),
+            ("loc_single", &Value::from("single")),
+        ]);
+    }
+}
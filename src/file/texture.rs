@@ -11,183 +11,264 @@ use crate::read::ChunkReader;
 use super::*;
 
 const DDSD_MIPMAPCOUNT: u32 = 0x20000;
+// 16384x16384 at 4 bytes per pixel with every mipmap
+const MAX_DDS_SIZE: usize = 148 + 16384 * 16384 * 4 / 3 * 4;
+
+/// Texture header and the mipmaps stored in the bundle.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Texture {
+    /// DDS file of the mipmaps stored with the texture.
+    pub dds: Vec<u8>,
+    pub num_mipmaps: u32,
+    /// Size of the largest mipmap.
+    pub width: u32,
+    pub height: u32,
+    /// Larger mipmaps stored in a `data/**` resource file.
+    pub high_res: Option<HighRes>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HighRes {
+    /// Resource path relative to the bundle directory.
+    pub path: String,
+    /// Compressed size of each mipmap chunk.
+    pub chunks: Vec<u32>,
+}
+
+/// Parse a `texture` file.
+///
+/// Textures stored in a resource file are read from the bundle directory of
+/// `options`.
+pub fn parse_texture(entry: &mut Entry<'_, '_>, options: &ExtractOptions) -> Result<Texture> {
+    parse(entry, options, &mut vec![0; 0x11000], &mut Vec::new())
+}
+
+fn parse(
+    entry: &mut Entry<'_, '_>,
+    options: &ExtractOptions,
+    mut shared: &mut [u8],
+    memory_pool: &mut Vec<u8>,
+) -> Result<Texture> {
+    let variants = entry.variants();
+    ensure!(variants.len() == 1, "expected 1 variant, found {}", variants.len());
+    let prime = &variants[0];
+    let body_size = prime.body_size;
+    let tail_size = prime.tail_size;
+    ensure!(tail_size <= 31, "unexpected texture tail size {tail_size}");
+
+    let has_high_res = prime.unknown1 == 0 && tail_size > 0;
+    let unknown1 = prime.unknown1;
+    let data_size;
+    let mut either_rdr = match unknown1 {
+        0 => {
+            data_size = body_size as u64;
+            Ok(entry)
+        }
+        1 => {
+            ensure!(tail_size == 0, "unexpected texture tail size {tail_size}");
+            ensure!(body_size <= 31, "unexpected texture body size {body_size}");
+
+            let mut data_path = [0_u8; 31];
+            entry.read_exact(&mut data_path[..body_size as usize])?;
+            let file = file_from_data_path(shared, options, &data_path)?;
+            data_size = file.metadata()?.len();
+            let slice;
+            (slice, shared) = shared.split_at_mut(0x10000);
+            Err(ChunkReader::new(slice, file))
+        }
+        unk => return Err(Error::layout(format!("unexpected Entry.unknown1 {unk}"))),
+    };
+    let _ = shared;
+
+    let rdr: &mut dyn Read = match &mut either_rdr {
+        Ok(f) => f,
+        Err(f) => f,
+    };
+
+    let kind = rdr.read_u32::<LE>()?;
+    ensure!(kind == 1 || kind == 0, "unexpected texture type {kind}");
+    if kind == 0 {
+        //for _ in 0..8 {
+        //    rdr.read_u8().unwrap();
+        //}
+        //let mut fd = File::create(out_path).unwrap();
+        //io::copy(rdr, &mut fd)
+        return Err(Error::layout("unknown texture file kind"));
+    }
+
+    let deflate_size = rdr.read_u32::<LE>()? as usize;
+    let inflate_size = rdr.read_u32::<LE>()? as usize;
+    ensure!(inflate_size >= 148, "DDS header size {inflate_size} is too small");
+    ensure!(deflate_size as u64 <= data_size, "texture data size {deflate_size} is larger than the file");
+    ensure!(inflate_size <= MAX_DDS_SIZE, "DDS size {inflate_size} is too large");
+
+    let ([in_buf, scratch], _) = split_vec(memory_pool,
+        [deflate_size, options.decompressor().scratch_size()]);
+    let mut dds = vec![0; inflate_size];
+    rdr.read_exact(in_buf)?;
+    options.decompressor().decompress(in_buf, &mut dds, scratch)?;
+
+    let fourcc = u32::from_le_bytes(<[u8; 4]>::try_from(&dds[84..88]).unwrap());
+
+    let magic = rdr.read_u32::<LE>()?;
+    ensure!(magic == 67, "unexpected texture magic {magic}");
+    rdr.read_u32::<LE>()?;
+    let num_mipmaps = rdr.read_u32::<LE>()?;
+    let width = rdr.read_u32::<LE>()?;
+    let height = rdr.read_u32::<LE>()?;
+    let mut skip = [0; 128];
+
+    rdr.read_exact(&mut skip)?;
+    let _image_size = u32::from_le_bytes(<[u8; 4]>::try_from(&skip[60..64]).unwrap());
+
+    let meta_size = rdr.read_u32::<LE>()?;
+    let Ok(meta_size) = u16::try_from(meta_size) else {
+        return Err(Error::layout(format!("texture meta size {meta_size} is too large")));
+    };
+
+    let high_res = if meta_size == 0 {
+        let _unknown = rdr.read_u32::<LE>()?;
+        ensure!(rdr.read_u8().is_err(), "trailing data after texture");
+        None
+    } else {
+        ensure!(has_high_res, "texture has mipmap chunks but no resource file");
+        ensure!(0x44583130_u32.swap_bytes() == fourcc, "unexpected DDS fourcc {fourcc:08x}");
+
+        check_dxt10(&dds[128..148])?;
+
+        let num_chunks = rdr.read_u32::<LE>()?;
+        ensure!(8 + num_chunks as u64 * 4 == meta_size as u64, "texture meta size {meta_size} does not fit {num_chunks} chunks");
+        let num_chunks = num_chunks as u16;
+        ensure!(rdr.read_u16::<LE>()? == 0, "unexpected texture chunk header");
+        ensure!(rdr.read_u16::<LE>()? == num_chunks, "unexpected texture chunk count");
+        let mut last = 0;
+        let mut chunks = Vec::with_capacity(num_chunks as usize);
+        for _ in 0..num_chunks {
+            let next = rdr.read_u32::<LE>()?;
+            ensure!(next > last, "texture chunk offsets are not increasing");
+            chunks.push(next - last);
+            last = next;
+        }
+        let _unknown = rdr.read_u32::<LE>()?;
+
+        let mut data_path = [0; 31];
+        rdr.read_exact(&mut data_path[..tail_size as usize])?;
+        ensure!(rdr.read_u8().is_err(), "trailing data after texture");
+
+        let path = data_path[..tail_size as usize].split(|b| *b == 0).next().unwrap();
+        let Some(path) = data_path_from(path) else {
+            return Err(Error::layout("resource path is not UTF-8"));
+        };
+        Some(HighRes {
+            path: path.to_string(),
+            chunks,
+        })
+    };
+
+    Ok(Texture {
+        dds,
+        num_mipmaps,
+        width,
+        height,
+        high_res,
+    })
+}
 
 pub(crate) struct TextureParser;
 
 impl Extractor for TextureParser {
-    fn extract(
-        &self,
-        entry: &mut Entry<'_, '_>,
-        file_path: &Path,
-        mut shared: &mut [u8],
-        memory_pool: &mut Vec<u8>,
-        options: &ExtractOptions,
-    ) -> io::Result<u64> {
-        let variants = entry.variants();
-        assert_eq!(1, variants.len());
-        let prime = &variants[0];
-        let body_size = prime.body_size;
-        let tail_size = prime.tail_size;
-        assert!(tail_size <= 31);
-
-        let has_high_res = prime.unknown1 == 0 && tail_size > 0;
-        let unknown1 = prime.unknown1;
-        let mut either_rdr = match unknown1 {
-            0 => Ok(entry),
-            1 => {
-                assert_eq!(0, tail_size);
-
-                let mut data_path = [0_u8; 31];
-                entry.read(&mut data_path[..body_size as usize]).unwrap();
-                let file = file_from_data_path(shared, &options.target, &data_path).unwrap();
-                let slice;
-                (slice, shared) = shared.split_at_mut(0x10000);
-                Err(ChunkReader::new(slice, file))
-            }
-            unk => panic!("unexpected Entry.unknown1 {unk}"),
+    fn name(&self) -> &str {
+        "texture"
+    }
+
+    fn extract(&self, cx: &mut ExtractContext<'_, '_, '_>) -> Result<u64> {
+        let (entry, file_path, mut shared, memory_pool, options) = cx.parts();
+        let Texture {
+            mut dds,
+            num_mipmaps: _,
+            width: largest_width,
+            height: largest_height,
+            high_res,
+        } = parse(entry, options, shared, memory_pool)?;
+
+        let parent = file_path.parent().unwrap_or(Path::new("."));
+        let file_name = file_path.file_stem().unwrap().to_str().unwrap();
+        let out_path = path_concat(parent, &mut shared, file_name, Some("dds"))?;
+
+        let Some(HighRes { path: data_path, chunks }) = high_res else {
+            return options.write(out_path, &dds);
         };
 
-        let rdr: &mut dyn Read = match &mut either_rdr {
-            Ok(f) => f,
-            Err(f) => f,
+        let base_width = u32::from_le_bytes(<[u8; 4]>::try_from(&dds[16..20]).unwrap());
+        let base_pitch = u32::from_le_bytes(<[u8; 4]>::try_from(&dds[20..24]).unwrap());
+
+        // assume all textures by this point are block compressed
+        ensure!(base_width > 0, "DDS header has zero width");
+        let Some(block_size) = base_pitch.checked_mul(4).map(|pitch| pitch / base_width) else {
+            return Err(Error::layout(format!("DDS header pitch {base_pitch} is too large")));
         };
 
-        let kind = rdr.read_u32::<LE>().unwrap();
-        assert!(kind == 1 || kind == 0, "unexpected texture type {kind}");
-
-        if kind == 1 {
-            let deflate_size = rdr.read_u32::<LE>().unwrap() as usize;
-            let inflate_size = rdr.read_u32::<LE>().unwrap() as usize;
-            assert!(inflate_size >= 148, "{inflate_size}");
-
-            let ([in_buf, out_buf, scratch], _) = split_vec(memory_pool,
-                [deflate_size, inflate_size, 0x100000]);
-            rdr.read_exact(in_buf).unwrap();
-            let size = options.oodle.decompress(in_buf, out_buf, scratch).unwrap();
-            assert_eq!(size, out_buf.len() as u64);
-
-            let fourcc = u32::from_le_bytes(<[u8; 4]>::try_from(&out_buf[84..88]).unwrap());
-
-            assert_eq!(67, rdr.read_u32::<LE>().unwrap());
-            rdr.read_u32::<LE>().unwrap();
-            let _num_mipmaps = rdr.read_u32::<LE>().unwrap();
-            let largest_width = rdr.read_u32::<LE>().unwrap();
-            let largest_height = rdr.read_u32::<LE>().unwrap();
-            let mut skip = [0; 128];
-
-            rdr.read_exact(&mut skip).unwrap();
-            let _image_size = u32::from_le_bytes(<[u8; 4]>::try_from(&skip[60..64]).unwrap());
-
-            let meta_size = u16::try_from(rdr.read_u32::<LE>().unwrap()).unwrap();
-
-            let parent = file_path.parent().unwrap_or(Path::new("."));
-            let file_name = file_path.file_stem().unwrap().to_str().unwrap();
-            let out_path = path_concat(parent, &mut shared, file_name, Some("dds"));
-
-            if meta_size == 0 {
-                let _unknown = rdr.read_u32::<LE>().unwrap();
-                assert!(rdr.read_u8().is_err());
-
-                options.write(out_path, &out_buf)
-            } else {
-                assert!(has_high_res);
-                assert_eq!(0x44583130_u32.swap_bytes(), fourcc);
-
-                check_dxt10(&out_buf[128..148]);
-
-                let num_chunks = u16::try_from(rdr.read_u32::<LE>().unwrap()).unwrap();
-                assert_eq!(8 + num_chunks * 4, meta_size);
-                assert_eq!(0, rdr.read_u16::<LE>().unwrap());
-                assert_eq!(num_chunks, rdr.read_u16::<LE>().unwrap());
-                let mut last = 0;
-                let mut chunks = Vec::with_capacity(num_chunks as usize);
-                for _ in 0..num_chunks {
-                    let next = rdr.read_u32::<LE>().unwrap();
-                    assert!(next > last);
-                    chunks.push(next - last);
-                    last = next;
-                }
-                let _unknown = rdr.read_u32::<LE>().unwrap();
-
-                let mut data_path = [0; 31];
-                rdr.read_exact(&mut data_path[..tail_size as usize]).unwrap();
-                assert!(rdr.read_u8().is_err());
-
-                let base_width = u32::from_le_bytes(<[u8; 4]>::try_from(&out_buf[16..20]).unwrap());
-                let base_pitch = u32::from_le_bytes(<[u8; 4]>::try_from(&out_buf[20..24]).unwrap());
-
-                // assume all textures by this point are block compressed
-                let block_size = 4 * base_pitch / base_width;
-
-                let pitch = largest_width / 4 * block_size;
-                let mut flags = u32::from_le_bytes(<[u8; 4]>::try_from(&out_buf[8..12]).unwrap());
-
-                // disable flag DDSD_MIPMAPCOUNT for output
-                // since only the largest mipmap is extracted
-                flags &= !DDSD_MIPMAPCOUNT;
-
-                // patch DDS header to use with largest mipmap
-                out_buf[8..12].copy_from_slice(&flags.to_le_bytes());
-                out_buf[12..16].copy_from_slice(&largest_height.to_le_bytes());
-                out_buf[16..20].copy_from_slice(&largest_width.to_le_bytes());
-                out_buf[20..24].copy_from_slice(&pitch.to_le_bytes());
-                out_buf[28..32].copy_from_slice(&0_u32.to_le_bytes());
-                //out_buf[140..144].copy_from_slice(&0_u32.to_le_bytes());
-
-                let chunk_width_pixel = if block_size == 8 {
-                    128
-                } else if block_size == 16 {
-                    64
-                } else {
-                    unreachable!()
-                };
-                let chunk_width = largest_width / chunk_width_pixel / 4;
-                let chunk_height = largest_height / 64 / 4;
-                let num_chunks = chunk_width * chunk_height;
-                assert!(chunks.len() >= num_chunks as usize);
-
-                let data_fd = file_from_data_path(shared, &options.target, &data_path).unwrap();
-                let slice;
-                (slice, shared) = shared.split_at_mut(0x10000);
-                let mut data_rdr = ChunkReader::new(slice, data_fd);
-
-                shared[..148].copy_from_slice(&out_buf[..148]);
-                options.open(out_path, |out| {
-                    out.write_all(&shared[..148])?;
-                    Ok(148 + sort_write_texture_chunks(
-                        memory_pool,
-                        &options.oodle,
-                        &mut data_rdr,
-                        &chunks[..num_chunks as usize],
-                        chunk_width,
-                        chunk_width_pixel,
-                        block_size,
-                        pitch,
-                        out,
-                    ))
-                })
-            }
-        } else if kind == 0 {
-            //for _ in 0..8 {
-            //    rdr.read_u8().unwrap();
-            //}
-            //let mut fd = File::create(out_path).unwrap();
-            //io::copy(rdr, &mut fd)
-            Err(io::Error::new(io::ErrorKind::InvalidData, "unknown texture file kind"))
+        let Some(pitch) = (largest_width / 4).checked_mul(block_size) else {
+            return Err(Error::layout(format!("texture width {largest_width} is too large")));
+        };
+        let mut flags = u32::from_le_bytes(<[u8; 4]>::try_from(&dds[8..12]).unwrap());
+
+        // disable flag DDSD_MIPMAPCOUNT for output
+        // since only the largest mipmap is extracted
+        flags &= !DDSD_MIPMAPCOUNT;
+
+        // patch DDS header to use with largest mipmap
+        dds[8..12].copy_from_slice(&flags.to_le_bytes());
+        dds[12..16].copy_from_slice(&largest_height.to_le_bytes());
+        dds[16..20].copy_from_slice(&largest_width.to_le_bytes());
+        dds[20..24].copy_from_slice(&pitch.to_le_bytes());
+        dds[28..32].copy_from_slice(&0_u32.to_le_bytes());
+        //dds[140..144].copy_from_slice(&0_u32.to_le_bytes());
+
+        let chunk_width_pixel = if block_size == 8 {
+            128
+        } else if block_size == 16 {
+            64
         } else {
-            unreachable!()
-        }
+            return Err(Error::layout(format!("unexpected block size {block_size}")));
+        };
+        let chunk_width = largest_width / chunk_width_pixel / 4;
+        let chunk_height = largest_height / 64 / 4;
+        let num_chunks = chunk_width * chunk_height;
+        ensure!(chunks.len() >= num_chunks as usize, "texture has {} chunks instead of {num_chunks}", chunks.len());
+
+        let data_fd = file_from_data_path(shared, options, data_path.as_bytes())?;
+        let slice;
+        (slice, shared) = shared.split_at_mut(0x10000);
+        let _ = shared;
+        let mut data_rdr = ChunkReader::new(slice, data_fd);
+
+        options.open(out_path, |out| {
+            out.write_all(&dds[..148])?;
+            Ok(148 + sort_write_texture_chunks(
+                memory_pool,
+                options.decompressor(),
+                &mut data_rdr,
+                &chunks[..num_chunks as usize],
+                chunk_width,
+                chunk_width_pixel,
+                block_size,
+                pitch,
+                out,
+            ).map_err(io::Error::other)?)
+        })
     }
 }
 
-fn check_dxt10(mut dxt10: &[u8]) {
-    let _encoding_kind = dxt10.read_u32::<LE>().unwrap();
-    let dimension = dxt10.read_u32::<LE>().unwrap();
-    let _misc_flags = dxt10.read_u32::<LE>().unwrap();
-    let array_size = dxt10.read_u32::<LE>().unwrap();
-    let _misc_flags2 = dxt10.read_u32::<LE>().unwrap();
-    assert_eq!(3, dimension);
-    assert_eq!(1, array_size);
+fn check_dxt10(mut dxt10: &[u8]) -> Result<()> {
+    let _encoding_kind = dxt10.read_u32::<LE>()?;
+    let dimension = dxt10.read_u32::<LE>()?;
+    let _misc_flags = dxt10.read_u32::<LE>()?;
+    let array_size = dxt10.read_u32::<LE>()?;
+    let _misc_flags2 = dxt10.read_u32::<LE>()?;
+    ensure!(dimension == 3, "unexpected DXT10 dimension {dimension}");
+    ensure!(array_size == 1, "unexpected DXT10 array size {array_size}");
+    Ok(())
 }
 
 // Write out DDS texture while sorting chunks to restore texture dimensions.
@@ -206,7 +287,7 @@ fn check_dxt10(mut dxt10: &[u8]) {
 // ```
 fn sort_write_texture_chunks(
     memory_pool: &mut Vec<u8>,
-    oodle: &Oodle,
+    decompressor: &dyn Decompressor,
     data_rdr: &mut ChunkReader<File>,
     chunks: &[u32],
     chunk_width: u32,
@@ -214,26 +295,28 @@ fn sort_write_texture_chunks(
     block_size: u32,
     pitch: u32,
     out_fd: &mut dyn io::Write,
-) -> u64 {
-    let window_size = (pitch * 64) as usize;
+) -> Result<u64> {
+    let Some(window_size) = pitch.checked_mul(64) else {
+        return Err(Error::layout(format!("texture pitch {pitch} is too large")));
+    };
+    let window_size = window_size as usize;
     let ([in_buf, out_buf, scratch, window], _) = split_vec(memory_pool,
-        [0x11000, 0x10000, 0x100000, window_size]);
+        [0x11000, 0x10000, decompressor.scratch_size(), window_size]);
 
     let mut wrote = 0;
     for (i, &chunk) in chunks.iter().enumerate() {
+        ensure!(chunk as usize <= in_buf.len(), "texture chunk size {chunk:#x} is too large");
         let in_buf = &mut in_buf[..chunk as usize];
-        data_rdr.read_exact(in_buf).unwrap();
-        let size = oodle.decompress(in_buf, out_buf, scratch).unwrap();
-        assert_eq!(size, out_buf.len() as u64);
-        assert_eq!(size, 0x10000);
+        data_rdr.read_exact(in_buf)?;
+        let size = decompressor.decompress(in_buf, out_buf, scratch)?;
 
         let i = i as u32;
         if i > 0 && i % chunk_width == 0 {
-            out_fd.write_all(&window).unwrap();
+            out_fd.write_all(window)?;
             wrote += window.len();
         }
 
-        assert_eq!((pitch / chunk_width) as u64, size / 64);
+        ensure!((pitch / chunk_width) as usize == size / 64, "texture chunk does not match pitch {pitch}");
 
         let row_size = (chunk_width_pixel * block_size) as usize;
         let chunk_x = (i % chunk_width) * chunk_width_pixel * block_size;
@@ -243,8 +326,8 @@ fn sort_write_texture_chunks(
             window[start as usize..start as usize + row_size].copy_from_slice(row);
         }
     }
-    out_fd.write_all(&window).unwrap();
+    out_fd.write_all(window)?;
     wrote += window.len();
 
-    wrote as u64
+    Ok(wrote as u64)
 }
@@ -0,0 +1,112 @@
+//! Select files by name.
+//!
+//! Globs and regexes match names from the dictionary, so files without a
+//! known name only match by hash. A glob without `/` matches the part of the
+//! name after the last `/`, so `*weapon*` finds files in any directory.
+
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use crate::glob::Glob;
+use crate::hash::murmur_hash64a;
+
+/// Matches a file if any of its patterns or hashes match.
+#[derive(Clone, Debug, Default)]
+pub struct NameFilter {
+    globs: Vec<(Glob, bool)>,
+    regexes: Vec<Regex>,
+    hashes: HashSet<u64>,
+}
+
+impl NameFilter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn glob(&mut self, pattern: &str) -> &mut Self {
+        self.globs.push((Glob::new(pattern), pattern.contains('/')));
+        self
+    }
+
+    /// Add a regex searched for anywhere in the name.
+    pub fn regex(&mut self, pattern: &str) -> Result<&mut Self, regex::Error> {
+        self.regexes.push(Regex::new(pattern)?);
+        Ok(self)
+    }
+
+    pub fn hash(&mut self, name: u64) -> &mut Self {
+        self.hashes.insert(name);
+        self
+    }
+
+    /// Add a bundle given as a 16 digit hash, a glob or a package name.
+    ///
+    /// Bundle files are named after the hash of their package name.
+    pub fn bundle(&mut self, pattern: &str) -> &mut Self {
+        if pattern.len() == 16
+            && let Ok(hash) = u64::from_str_radix(pattern, 16)
+        {
+            self.hash(hash)
+        } else if Glob::is_glob(pattern) {
+            self.glob(pattern)
+        } else {
+            self.hash(murmur_hash64a(pattern.as_bytes(), 0))
+        }
+    }
+
+    /// Whether `name` was added with [`hash`](Self::hash).
+    pub fn contains_hash(&self, name: u64) -> bool {
+        self.hashes.contains(&name)
+    }
+
+    /// Whether no patterns or hashes were added, in which case nothing is
+    /// filtered.
+    pub fn is_empty(&self) -> bool {
+        self.globs.is_empty()
+            && self.regexes.is_empty()
+            && self.hashes.is_empty()
+    }
+
+    /// Whether a file with name hash `name` and dictionary name `resolved`
+    /// matches.
+    pub fn is_match(&self, name: u64, resolved: Option<&str>) -> bool {
+        if self.is_empty() || self.hashes.contains(&name) {
+            return true;
+        }
+
+        let Some(resolved) = resolved else {
+            return false;
+        };
+        let file_name = resolved.rsplit('/').next().unwrap_or(resolved);
+        self.globs.iter().any(|(glob, full)| glob.is_match(if *full { resolved } else { file_name }))
+            || self.regexes.iter().any(|regex| regex.is_match(resolved))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn names() {
+        let mut filter = NameFilter::new();
+        assert!(filter.is_match(1, None));
+
+        filter.glob("content/ui/**")
+            .glob("*weapon*")
+            .hash(0x10);
+        filter.regex("^scripts/.*_view$").unwrap();
+        assert!(filter.is_match(1, Some("content/ui/materials/icons/foo")));
+        assert!(filter.is_match(1, Some("content/characters/weapons/lasgun_weapon_p1")));
+        assert!(!filter.is_match(1, Some("content/weapon/lasgun")));
+        assert!(filter.is_match(1, Some("scripts/ui/views/inventory_view")));
+        assert!(!filter.is_match(1, Some("scripts/ui/views/inventory_view_settings")));
+        assert!(filter.is_match(0x10, None));
+        assert!(filter.contains_hash(0x10));
+        assert!(!filter.contains_hash(1));
+        assert!(!filter.is_match(1, None));
+
+        assert!(NameFilter::new().regex("(").is_err());
+    }
+}
@@ -0,0 +1,699 @@
+//! Structured output for converted files.
+//!
+//! Converters build a [`Value`] and write it in the [`Format`] picked by the
+//! user. Not every format fits every shape: TOML needs a table at the top
+//! and CSV needs rows of scalars.
+
+use std::fmt;
+use std::io::Write;
+
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::error::Result;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Value {
+    Null,
+    Bool(bool),
+    Int(i64),
+    Str(String),
+    Array(Vec<Value>),
+    /// Fields are written in insertion order.
+    Object(Vec<(String, Value)>),
+}
+
+impl Value {
+    fn is_scalar(&self) -> bool {
+        !matches!(self, Self::Array(_) | Self::Object(_))
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Self {
+        Self::Bool(b)
+    }
+}
+
+impl From<u32> for Value {
+    fn from(n: u32) -> Self {
+        Self::Int(n.into())
+    }
+}
+
+impl From<i64> for Value {
+    fn from(n: i64) -> Self {
+        Self::Int(n)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(s: &str) -> Self {
+        Self::Str(s.to_string())
+    }
+}
+
+impl From<String> for Value {
+    fn from(s: String) -> Self {
+        Self::Str(s)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Format {
+    #[default]
+    Json,
+    JsonPretty,
+    Yaml,
+    Toml,
+    Csv,
+}
+
+impl Format {
+    pub fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "json" => Self::Json,
+            "json-pretty" => Self::JsonPretty,
+            "yaml" => Self::Yaml,
+            "toml" => Self::Toml,
+            "csv" => Self::Csv,
+            _ => return None,
+        })
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Json => "json",
+            Self::JsonPretty => "json-pretty",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            Self::Csv => "csv",
+        }
+    }
+
+    /// File extension of the output.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Self::Json | Self::JsonPretty => "json",
+            Self::Yaml => "yaml",
+            Self::Toml => "toml",
+            Self::Csv => "csv",
+        }
+    }
+
+    /// Append `value` to `out`.
+    ///
+    /// Fails with [`ErrorKind::UnsupportedFormat`] if the format cannot hold
+    /// the shape of `value`.
+    pub fn write(self, out: &mut Vec<u8>, value: &Value) -> Result<()> {
+        match self {
+            Self::Json => write_json(out, value),
+            Self::JsonPretty => {
+                write_json_pretty(out, value, 0);
+                out.push(b'\n');
+            }
+            Self::Yaml => write_yaml(out, value),
+            Self::Toml => {
+                let Value::Object(fields) = value else {
+                    return Err(self.unsupported());
+                };
+                write_toml_table(out, fields, &mut Vec::new()).map_err(|()| self.unsupported())?;
+            }
+            Self::Csv => write_csv(out, value).map_err(|()| self.unsupported())?,
+        }
+        Ok(())
+    }
+
+    fn unsupported(self) -> Error {
+        Error::new(ErrorKind::UnsupportedFormat(self.name()))
+    }
+}
+
+impl fmt::Display for Format {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Append `s` as a quoted JSON string.
+///
+/// The escapes are also valid in YAML and TOML basic strings.
+pub fn write_json_string(out: &mut Vec<u8>, s: &str) {
+    out.push(b'"');
+    for c in s.chars() {
+        match c {
+            '"' => out.extend_from_slice(b"\\\""),
+            '\\' => out.extend_from_slice(b"\\\\"),
+            '\n' => out.extend_from_slice(b"\\n"),
+            '\r' => out.extend_from_slice(b"\\r"),
+            '\t' => out.extend_from_slice(b"\\t"),
+            '\u{8}' => out.extend_from_slice(b"\\b"),
+            '\u{c}' => out.extend_from_slice(b"\\f"),
+            c if c < ' ' || c == '\u{7f}' => write!(out, "\\u{:04x}", c as u32).unwrap(),
+            c => out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+        }
+    }
+    out.push(b'"');
+}
+
+fn write_json_scalar(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => out.extend_from_slice(b"null"),
+        Value::Bool(b) => write!(out, "{b}").unwrap(),
+        Value::Int(n) => write!(out, "{n}").unwrap(),
+        Value::Str(s) => write_json_string(out, s),
+        Value::Array(_) | Value::Object(_) => unreachable!(),
+    }
+}
+
+fn write_json(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_json(out, item);
+            }
+            out.push(b']');
+        }
+        Value::Object(fields) => {
+            out.push(b'{');
+            for (i, (key, field)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.push(b',');
+                }
+                write_json_string(out, key);
+                out.push(b':');
+                write_json(out, field);
+            }
+            out.push(b'}');
+        }
+        scalar => write_json_scalar(out, scalar),
+    }
+}
+
+fn indent(out: &mut Vec<u8>, depth: usize) {
+    out.extend(std::iter::repeat_n(b' ', depth * 2));
+}
+
+fn write_json_pretty(out: &mut Vec<u8>, value: &Value, depth: usize) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.extend_from_slice(b"[\n");
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.extend_from_slice(b",\n");
+                }
+                indent(out, depth + 1);
+                write_json_pretty(out, item, depth + 1);
+            }
+            out.push(b'\n');
+            indent(out, depth);
+            out.push(b']');
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            out.extend_from_slice(b"{\n");
+            for (i, (key, field)) in fields.iter().enumerate() {
+                if i > 0 {
+                    out.extend_from_slice(b",\n");
+                }
+                indent(out, depth + 1);
+                write_json_string(out, key);
+                out.extend_from_slice(b": ");
+                write_json_pretty(out, field, depth + 1);
+            }
+            out.push(b'\n');
+            indent(out, depth);
+            out.push(b'}');
+        }
+        value => write_json(out, value),
+    }
+}
+
+// keys made of these characters don't need quotes in YAML or TOML
+fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.bytes().all(|b| b.is_ascii_alphanumeric() || b == b'_' || b == b'-')
+}
+
+fn write_yaml_key(out: &mut Vec<u8>, key: &str) {
+    const RESERVED: &[&str] = &["true", "false", "null", "yes", "no", "on", "off", "y", "n", "~"];
+    let plain = is_bare_key(key)
+        && !key.starts_with(|c: char| c.is_ascii_digit() || c == '-')
+        && !RESERVED.iter().any(|word| word.eq_ignore_ascii_case(key));
+    if plain {
+        out.extend_from_slice(key.as_bytes());
+    } else {
+        write_json_string(out, key);
+    }
+}
+
+fn write_yaml(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Array(items) if !items.is_empty() => write_yaml_items(out, items, 0),
+        Value::Object(fields) if !fields.is_empty() => write_yaml_fields(out, fields, 0, false),
+        value => {
+            write_json(out, value);
+            out.push(b'\n');
+        }
+    }
+}
+
+// written after "key:" or "-"
+fn write_yaml_value(out: &mut Vec<u8>, value: &Value, depth: usize) {
+    match value {
+        Value::Array(items) if !items.is_empty() => {
+            out.push(b'\n');
+            write_yaml_items(out, items, depth);
+        }
+        Value::Object(fields) if !fields.is_empty() => {
+            out.push(b'\n');
+            write_yaml_fields(out, fields, depth, false);
+        }
+        value => {
+            out.push(b' ');
+            write_json(out, value);
+            out.push(b'\n');
+        }
+    }
+}
+
+fn write_yaml_items(out: &mut Vec<u8>, items: &[Value], depth: usize) {
+    for item in items {
+        indent(out, depth);
+        out.push(b'-');
+        match item {
+            // first field goes on the same line as the dash
+            Value::Object(fields) if !fields.is_empty() => {
+                out.push(b' ');
+                write_yaml_fields(out, fields, depth + 1, true);
+            }
+            item => write_yaml_value(out, item, depth + 1),
+        }
+    }
+}
+
+fn write_yaml_fields(out: &mut Vec<u8>, fields: &[(String, Value)], depth: usize, inline_first: bool) {
+    for (i, (key, field)) in fields.iter().enumerate() {
+        if i > 0 || !inline_first {
+            indent(out, depth);
+        }
+        write_yaml_key(out, key);
+        out.push(b':');
+        write_yaml_value(out, field, depth + 1);
+    }
+}
+
+fn write_toml_key(out: &mut Vec<u8>, key: &str) {
+    if is_bare_key(key) {
+        out.extend_from_slice(key.as_bytes());
+    } else {
+        write_json_string(out, key);
+    }
+}
+
+fn write_toml_path(out: &mut Vec<u8>, path: &[&str]) {
+    for (i, key) in path.iter().enumerate() {
+        if i > 0 {
+            out.push(b'.');
+        }
+        write_toml_key(out, key);
+    }
+}
+
+fn is_toml_table_array(value: &Value) -> bool {
+    matches!(value, Value::Array(items)
+        if !items.is_empty() && items.iter().all(|item| matches!(item, Value::Object(_))))
+}
+
+fn write_toml_inline(out: &mut Vec<u8>, value: &Value) -> Result<(), ()> {
+    match value {
+        Value::Null => return Err(()),
+        Value::Array(items) => {
+            out.push(b'[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.extend_from_slice(b", ");
+                }
+                write_toml_inline(out, item)?;
+            }
+            out.push(b']');
+        }
+        Value::Object(fields) => {
+            out.push(b'{');
+            for (i, (key, field)) in fields.iter().enumerate() {
+                out.extend_from_slice(if i > 0 { b", " } else { b" " });
+                write_toml_key(out, key);
+                out.extend_from_slice(b" = ");
+                write_toml_inline(out, field)?;
+            }
+            out.extend_from_slice(if fields.is_empty() { b"}" } else { b" }" });
+        }
+        scalar => write_json_scalar(out, scalar),
+    }
+    Ok(())
+}
+
+fn write_toml_table<'a>(
+    out: &mut Vec<u8>,
+    fields: &'a [(String, Value)],
+    path: &mut Vec<&'a str>,
+) -> Result<(), ()> {
+    // plain keys have to come before any sub-table
+    for (key, field) in fields {
+        if matches!(field, Value::Object(_)) || is_toml_table_array(field) {
+            continue;
+        }
+        write_toml_key(out, key);
+        out.extend_from_slice(b" = ");
+        write_toml_inline(out, field)?;
+        out.push(b'\n');
+    }
+
+    for (key, field) in fields {
+        path.push(key);
+        match field {
+            Value::Object(sub) => {
+                out.extend_from_slice(b"\n[");
+                write_toml_path(out, path);
+                out.extend_from_slice(b"]\n");
+                write_toml_table(out, sub, path)?;
+            }
+            Value::Array(items) if is_toml_table_array(field) => {
+                for item in items {
+                    let Value::Object(sub) = item else { unreachable!() };
+                    out.extend_from_slice(b"\n[[");
+                    write_toml_path(out, path);
+                    out.extend_from_slice(b"]]\n");
+                    write_toml_table(out, sub, path)?;
+                }
+            }
+            _ => {}
+        }
+        path.pop();
+    }
+    Ok(())
+}
+
+fn write_csv_field(out: &mut Vec<u8>, value: &Value) {
+    match value {
+        Value::Null => {}
+        Value::Str(s) if s.contains([',', '"', '\n', '\r']) => {
+            out.push(b'"');
+            out.extend_from_slice(s.replace('"', "\"\"").as_bytes());
+            out.push(b'"');
+        }
+        Value::Str(s) => out.extend_from_slice(s.as_bytes()),
+        scalar => write_json_scalar(out, scalar),
+    }
+}
+
+fn write_csv_row<'a>(out: &mut Vec<u8>, fields: impl Iterator<Item = &'a Value>) {
+    for (i, field) in fields.enumerate() {
+        if i > 0 {
+            out.push(b',');
+        }
+        write_csv_field(out, field);
+    }
+    out.push(b'\n');
+}
+
+// rows of objects with scalar fields, or a single object as key,value rows
+fn write_csv(out: &mut Vec<u8>, value: &Value) -> Result<(), ()> {
+    match value {
+        Value::Array(rows) => {
+            let mut header = Vec::<&str>::new();
+            for row in rows {
+                let Value::Object(fields) = row else {
+                    return Err(());
+                };
+                for (key, field) in fields {
+                    if !field.is_scalar() {
+                        return Err(());
+                    }
+                    if !header.contains(&key.as_str()) {
+                        header.push(key);
+                    }
+                }
+            }
+
+            let header_values = header.iter().map(|&key| Value::from(key)).collect::<Vec<_>>();
+            write_csv_row(out, header_values.iter());
+            for row in rows {
+                let Value::Object(fields) = row else { unreachable!() };
+                write_csv_row(out, header.iter().map(|&key| {
+                    fields.iter()
+                        .find(|(k, _)| k == key)
+                        .map(|(_, field)| field)
+                        .unwrap_or(&Value::Null)
+                }));
+            }
+        }
+        Value::Object(fields) => {
+            if !fields.iter().all(|(_, field)| field.is_scalar()) {
+                return Err(());
+            }
+            out.extend_from_slice(b"key,value\n");
+            for (key, field) in fields {
+                write_csv_row(out, [Value::from(key.as_str()), field.clone()].iter());
+            }
+        }
+        _ => return Err(()),
+    }
+    Ok(())
+}
+
+/// Parse a JSON document.
+///
+/// Only integer numbers are supported, which is all the files written by
+/// limn contain.
+pub fn parse_json(s: &str) -> Result<Value> {
+    let mut parser = JsonParser {
+        s: s.as_bytes(),
+        pos: 0,
+    };
+    let value = parser.value(0)?;
+    parser.skip_space();
+    if parser.pos != parser.s.len() {
+        return Err(parser.error("trailing data"));
+    }
+    Ok(value)
+}
+
+struct JsonParser<'a> {
+    s: &'a [u8],
+    pos: usize,
+}
+
+impl JsonParser<'_> {
+    fn error(&self, msg: &str) -> Error {
+        Error::layout(format!("invalid JSON at {}: {msg}", self.pos))
+    }
+
+    fn skip_space(&mut self) {
+        while self.s.get(self.pos).is_some_and(|b| b.is_ascii_whitespace()) {
+            self.pos += 1;
+        }
+    }
+
+    fn eat(&mut self, b: u8) -> bool {
+        self.skip_space();
+        if self.s.get(self.pos) == Some(&b) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, b: u8) -> Result<()> {
+        if self.eat(b) {
+            Ok(())
+        } else {
+            Err(self.error(&format!("expected '{}'", b as char)))
+        }
+    }
+
+    fn keyword(&mut self, word: &str, value: Value) -> Result<Value> {
+        if self.s[self.pos..].starts_with(word.as_bytes()) {
+            self.pos += word.len();
+            Ok(value)
+        } else {
+            Err(self.error("unexpected character"))
+        }
+    }
+
+    fn value(&mut self, depth: usize) -> Result<Value> {
+        if depth > 128 {
+            return Err(self.error("nested too deep"));
+        }
+
+        self.skip_space();
+        match self.s.get(self.pos) {
+            None => Err(self.error("unexpected end")),
+            Some(b'n') => self.keyword("null", Value::Null),
+            Some(b't') => self.keyword("true", Value::Bool(true)),
+            Some(b'f') => self.keyword("false", Value::Bool(false)),
+            Some(b'"') => self.string().map(Value::Str),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut items = Vec::new();
+                if !self.eat(b']') {
+                    loop {
+                        items.push(self.value(depth + 1)?);
+                        if self.eat(b']') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Value::Array(items))
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut fields = Vec::new();
+                if !self.eat(b'}') {
+                    loop {
+                        self.skip_space();
+                        if self.s.get(self.pos) != Some(&b'"') {
+                            return Err(self.error("expected key"));
+                        }
+                        let key = self.string()?;
+                        self.expect(b':')?;
+                        fields.push((key, self.value(depth + 1)?));
+                        if self.eat(b'}') {
+                            break;
+                        }
+                        self.expect(b',')?;
+                    }
+                }
+                Ok(Value::Object(fields))
+            }
+            Some(b'-' | b'0'..=b'9') => {
+                let start = self.pos;
+                self.pos += 1;
+                while self.s.get(self.pos).is_some_and(|b| b.is_ascii_digit()) {
+                    self.pos += 1;
+                }
+                std::str::from_utf8(&self.s[start..self.pos]).unwrap()
+                    .parse()
+                    .map(Value::Int)
+                    .map_err(|_| self.error("unsupported number"))
+            }
+            Some(_) => Err(self.error("unexpected character")),
+        }
+    }
+
+    // at the opening quote
+    fn string(&mut self) -> Result<String> {
+        self.pos += 1;
+        let mut out = Vec::new();
+        loop {
+            let Some(&b) = self.s.get(self.pos) else {
+                return Err(self.error("unterminated string"));
+            };
+            self.pos += 1;
+            match b {
+                b'"' => break,
+                b'\\' => {
+                    let Some(&escape) = self.s.get(self.pos) else {
+                        return Err(self.error("unterminated string"));
+                    };
+                    self.pos += 1;
+                    let c = match escape {
+                        b'"' => '"',
+                        b'\\' => '\\',
+                        b'/' => '/',
+                        b'b' => '\u{8}',
+                        b'f' => '\u{c}',
+                        b'n' => '\n',
+                        b'r' => '\r',
+                        b't' => '\t',
+                        b'u' => {
+                            let mut code = self.hex4()?;
+                            // surrogate pair
+                            if (0xd800..0xdc00).contains(&code) && self.s[self.pos..].starts_with(b"\\u") {
+                                self.pos += 2;
+                                let low = self.hex4()?;
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low.wrapping_sub(0xdc00) & 0x3ff);
+                            }
+                            char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER)
+                        }
+                        _ => return Err(self.error("invalid escape")),
+                    };
+                    out.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes());
+                }
+                b => out.push(b),
+            }
+        }
+        // input is a str and escapes produce whole characters
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    fn hex4(&mut self) -> Result<u32> {
+        let digits = self.s.get(self.pos..self.pos + 4)
+            .and_then(|digits| std::str::from_utf8(digits).ok())
+            .and_then(|digits| u32::from_str_radix(digits, 16).ok());
+        let Some(code) = digits else {
+            return Err(self.error("invalid unicode escape"));
+        };
+        self.pos += 4;
+        Ok(code)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn write(format: Format, value: &Value) -> String {
+        let mut out = Vec::new();
+        format.write(&mut out, value).unwrap();
+        String::from_utf8(out).unwrap()
+    }
+
+    #[test]
+    fn formats() {
+        let rows = Value::Array(vec![
+            Value::Object(vec![
+                ("name".to_string(), "a \"b\"\\c".into()),
+                ("ext".to_string(), "lua".into()),
+            ]),
+            Value::Object(vec![
+                ("name".to_string(), "x,\ny".into()),
+                ("size".to_string(), 3_u32.into()),
+            ]),
+        ]);
+        assert_eq!(write(Format::Json, &rows),
+            r#"[{"name":"a \"b\"\\c","ext":"lua"},{"name":"x,\ny","size":3}]"#);
+        assert_eq!(write(Format::JsonPretty, &Value::Array(vec![Value::Object(vec![])])),
+            "[\n  {}\n]\n");
+        assert_eq!(write(Format::Yaml, &rows),
+            "- name: \"a \\\"b\\\"\\\\c\"\n  ext: \"lua\"\n- name: \"x,\\ny\"\n  size: 3\n");
+        assert_eq!(write(Format::Csv, &rows),
+            "name,ext,size\n\"a \"\"b\"\"\\c\",lua,\n\"x,\ny\",,3\n");
+        assert!(Format::Toml.write(&mut Vec::new(), &rows).is_err());
+
+        let table = Value::Object(vec![
+            ("lod".to_string(), Value::Array(vec![1_u32.into()])),
+            ("a.b".to_string(), Value::Object(vec![("true".to_string(), true.into())])),
+        ]);
+        assert_eq!(write(Format::Toml, &table), "lod = [1]\n\n[\"a.b\"]\ntrue = true\n");
+        assert_eq!(write(Format::Yaml, &table), "lod:\n  - 1\n\"a.b\":\n  \"true\": true\n");
+        assert!(Format::Csv.write(&mut Vec::new(), &table).is_err());
+    }
+
+    #[test]
+    fn parse() {
+        let value = Value::Object(vec![
+            ("a\u{1}\"".to_string(), Value::Array(vec![Value::Null, true.into(), Value::Int(-3)])),
+            ("é".to_string(), "\\\n\u{1f600}".into()),
+        ]);
+        assert_eq!(parse_json(&write(Format::Json, &value)).unwrap(), value);
+        assert_eq!(parse_json(&write(Format::JsonPretty, &value)).unwrap(), value);
+        assert_eq!(parse_json(r#" "\ud83d\ude00" "#).unwrap(), Value::from("\u{1f600}"));
+        assert!(parse_json("[1,]").is_err());
+        assert!(parse_json("1.5").is_err());
+        assert!(parse_json("{} x").is_err());
+    }
+}
@@ -0,0 +1,120 @@
+//! Glob patterns for resource names.
+//!
+//! - `?` matches one character other than `/`
+//! - `*` matches any characters other than `/`
+//! - `**` matches any characters including `/`
+//!
+//! Other characters match themselves.
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Token {
+    Literal(char),
+    One,
+    Star,
+    DoubleStar,
+}
+
+#[derive(Clone, Debug)]
+pub struct Glob {
+    tokens: Vec<Token>,
+}
+
+impl Glob {
+    pub fn new(pattern: &str) -> Self {
+        let mut tokens = Vec::with_capacity(pattern.len());
+        let mut chars = pattern.chars().peekable();
+        while let Some(c) = chars.next() {
+            tokens.push(match c {
+                '?' => Token::One,
+                '*' if chars.peek() == Some(&'*') => {
+                    chars.next();
+                    // `***` is the same as `**`
+                    while chars.peek() == Some(&'*') {
+                        chars.next();
+                    }
+                    Token::DoubleStar
+                }
+                '*' => Token::Star,
+                c => Token::Literal(c),
+            });
+        }
+        Self { tokens }
+    }
+
+    /// Whether `pattern` uses any wildcards.
+    pub fn is_glob(pattern: &str) -> bool {
+        pattern.contains(['*', '?'])
+    }
+
+    pub fn is_match(&self, s: &str) -> bool {
+        // tokens reachable after the characters so far, linear in the length
+        // of `s` unlike backtracking
+        let mut states = vec![false; self.tokens.len() + 1];
+        let mut next = states.clone();
+        states[0] = true;
+        self.skip_stars(&mut states);
+        for c in s.chars() {
+            next.fill(false);
+            for (i, token) in self.tokens.iter().enumerate() {
+                if !states[i] {
+                    continue;
+                }
+                match token {
+                    Token::Literal(l) if *l == c => next[i + 1] = true,
+                    Token::One if c != '/' => next[i + 1] = true,
+                    Token::Star if c != '/' => next[i] = true,
+                    Token::DoubleStar => next[i] = true,
+                    _ => (),
+                }
+            }
+            self.skip_stars(&mut next);
+            if !next.contains(&true) {
+                return false;
+            }
+            std::mem::swap(&mut states, &mut next);
+        }
+        states[self.tokens.len()]
+    }
+
+    // stars also match nothing
+    fn skip_stars(&self, states: &mut [bool]) {
+        for (i, token) in self.tokens.iter().enumerate() {
+            if states[i] && matches!(token, Token::Star | Token::DoubleStar) {
+                states[i + 1] = true;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn wildcards() {
+        let glob = Glob::new("scripts/*/foo?.lua");
+        assert!(glob.is_match("scripts/ui/foo1.lua"));
+        assert!(!glob.is_match("scripts/ui/views/foo1.lua"));
+        assert!(!glob.is_match("scripts/ui/foo.lua"));
+
+        let glob = Glob::new("scripts/**/foo*");
+        assert!(glob.is_match("scripts/ui/views/foo_bar"));
+        assert!(glob.is_match("scripts//foo"));
+        assert!(!glob.is_match("content/ui/foo"));
+
+        assert!(Glob::new("**").is_match("a/b/c"));
+        assert!(!Glob::new("*").is_match("a/b"));
+        assert!(Glob::new("a").is_match("a"));
+        assert!(!Glob::new("a").is_match(""));
+        assert!(Glob::new("**").is_match(""));
+    }
+
+    #[test]
+    fn pathological() {
+        let glob = Glob::new("*a*a*a*a*a*a*a*a*a*a*b");
+        let s = "a".repeat(10000);
+        assert!(!glob.is_match(&s));
+        assert!(glob.is_match(&(s.clone() + "b")));
+        assert!(!Glob::new("**a**a**a**a**a**a**a**b").is_match(&s.replace("aa", "a/")));
+    }
+}
@@ -0,0 +1,95 @@
+//! Learn resource names from strings inside extracted files.
+//!
+//! Lua string constants and chunk names, `data/**` resource paths and bone
+//! names often spell out the names of other resources. Enable with
+//! [`ExtractBuilder::harvest`](crate::ExtractBuilder::harvest), then keep the
+//! candidates that hash to a name seen in a bundle index with
+//! [`Harvest::matching`].
+
+use std::collections::HashSet;
+use std::sync::Mutex;
+
+use crate::hash::MurmurHash;
+use crate::hash::extension_name;
+use crate::hash::murmur_hash64a;
+
+/// Dictionary of harvested names, loaded next to `dictionary.txt`.
+pub const LEARNED_FILE: &str = "learned.txt";
+
+/// Candidate names collected from every extraction thread.
+#[derive(Default)]
+pub struct Harvest {
+    candidates: Mutex<HashSet<String>>,
+}
+
+impl Harvest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add strings that could be resource names.
+    ///
+    /// Strings that cannot be a path, like ones with whitespace, are
+    /// dropped.
+    pub fn add<'a>(&self, candidates: impl IntoIterator<Item = &'a str>) {
+        let mut set = None;
+        for candidate in candidates {
+            let candidate = candidate.strip_prefix('@').unwrap_or(candidate);
+            if candidate.is_empty()
+                || candidate.len() > 0x200
+                || candidate.contains(|c: char| c.is_whitespace() || c.is_control())
+            {
+                continue;
+            }
+            set.get_or_insert_with(|| self.candidates.lock().unwrap())
+                .insert(candidate.to_string());
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.candidates.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Sorted candidates hashing to one of `names`.
+    ///
+    /// A candidate ending in a known extension is also tried without it.
+    pub fn matching(&self, names: &HashSet<u64>) -> Vec<String> {
+        let candidates = self.candidates.lock().unwrap();
+        let mut found = HashSet::new();
+        for candidate in candidates.iter() {
+            let stem = candidate.rsplit_once('.')
+                .filter(|(_, ext)| extension_name(murmur_hash64a(ext.as_bytes(), 0)).is_some())
+                .map(|(stem, _)| stem);
+            for name in [Some(candidate.as_str()), stem].into_iter().flatten() {
+                if names.contains(&u64::from(MurmurHash::new(name))) {
+                    found.insert(name);
+                }
+            }
+        }
+        let mut found = found.into_iter().map(str::to_string).collect::<Vec<_>>();
+        found.sort_unstable();
+        found
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn matching() {
+        let harvest = Harvest::new();
+        harvest.add(["@scripts/ui/foo.lua", "content/ui/bar", "not a name", "other"]);
+        assert_eq!(harvest.len(), 3);
+
+        let names = HashSet::from([
+            murmur_hash64a(b"scripts/ui/foo", 0),
+            murmur_hash64a(b"content/ui/bar", 0),
+        ]);
+        assert_eq!(harvest.matching(&names), ["content/ui/bar", "scripts/ui/foo"]);
+    }
+}
@@ -57,6 +57,29 @@ pub(crate) static FILE_EXTENSION: LazyLock<[(u64, &'static str); 50]> = LazyLock
     a
 });
 
+/// Look up a known file extension by its hash.
+pub fn extension_name(hash: u64) -> Option<&'static str> {
+    FILE_EXTENSION.binary_search_by(|probe| probe.0.cmp(&hash))
+        .map(|i| FILE_EXTENSION[i].1)
+        .ok()
+}
+
+/// Hash and name of a dictionary line.
+///
+/// Lines are either a name or `@<16 hex hash>=<name>` for names that do
+/// not hash to the resource they describe.
+pub fn dictionary_entry(line: &str) -> (MurmurHash, &str) {
+    if let Some(map) = line.strip_prefix("@")
+        && let Some((hash_s, key_to)) = map.split_once("=")
+        && hash_s.len() == 16
+        && let Ok(hash) = u64::from_str_radix(hash_s, 16)
+    {
+        (MurmurHash(hash), key_to)
+    } else {
+        (MurmurHash::new(line), line)
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash, Clone)]
 pub struct MurmurHash(pub(crate) u64);
 
@@ -76,6 +99,12 @@ impl From<u64> for MurmurHash {
     }
 }
 
+impl From<MurmurHash> for u64 {
+    fn from(hash: MurmurHash) -> Self {
+        hash.0
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct MurmurHash32(pub(crate) u32);
 
@@ -85,6 +114,12 @@ impl From<u32> for MurmurHash32 {
     }
 }
 
+impl From<MurmurHash32> for u32 {
+    fn from(hash: MurmurHash32) -> Self {
+        hash.0
+    }
+}
+
 pub(crate) const fn murmurhash64(key: &[u8]) -> u64 {
     murmur_hash64a(key, 0)
 }
@@ -156,6 +191,44 @@ pub const fn murmur_hash64a(mut key: &[u8], seed: u64) -> u64 {
     hash
 }
 
+/// Streaming FNV-1a digest of file contents.
+#[derive(Clone, Debug)]
+pub struct Digest(u64);
+
+impl Digest {
+    pub fn new() -> Self {
+        Self(0xcbf29ce484222325)
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for b in data {
+            self.0 ^= *b as u64;
+            self.0 = self.0.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    pub fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for Digest {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::io::Write for Digest {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -178,4 +251,13 @@ mod test {
             assert_eq!(*hash, murmur_hash64a(key, 0));
         }
     }
+
+    #[test]
+    fn fnv1a() {
+        let mut digest = Digest::new();
+        assert_eq!(0xcbf29ce484222325, digest.finish());
+        digest.update(b"fo");
+        digest.update(b"obar");
+        assert_eq!(0x85944171f73967e8, digest.finish());
+    }
 }
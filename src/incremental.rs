@@ -0,0 +1,157 @@
+//! Pick the files to extract again after bundles changed.
+//!
+//! An incremental extraction keeps a [`Catalog`] of the input next to the
+//! manifest in the output directory. Bundles with the same modified time and
+//! size are not read again, and a file is only extracted again when the
+//! bundles holding it or its variant sizes changed, or when the last manifest
+//! has no record of it because a filter left it out. Extraction options like
+//! the format or dictionary are expected to stay the same between runs.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+
+use crate::bundle::Variant;
+use crate::catalog::Catalog;
+use crate::manifest::ManifestRecord;
+
+pub const CATALOG_FILE: &str = "catalog.bin";
+
+#[derive(Debug, Default)]
+pub struct Plan {
+    /// (ext, name) of files to extract again.
+    pub changed: HashSet<(u64, u64)>,
+    /// Bundles holding a changed file.
+    pub bundles: HashSet<u64>,
+    /// Records of files that did not change.
+    pub kept: Vec<ManifestRecord>,
+    /// Records of files no longer in any bundle.
+    pub removed: Vec<ManifestRecord>,
+}
+
+// (ext, name) -> (bundle, variants) of every copy
+type Sources<'a> = HashMap<(u64, u64), Vec<(u64, &'a [Variant])>>;
+
+fn sources(catalog: &Catalog) -> Sources<'_> {
+    let mut sources = HashMap::<_, Vec<_>>::new();
+    for bundle in catalog.bundles() {
+        for file in &bundle.files {
+            sources.entry((file.ext, file.name))
+                .or_default()
+                .push((bundle.hash, &file.variants[..]));
+        }
+    }
+    for copies in sources.values_mut() {
+        copies.sort_unstable_by_key(|(bundle, _)| *bundle);
+    }
+    sources
+}
+
+/// Compare the catalog of the last extraction with the current one.
+///
+/// `records` is the manifest of the last extraction. `skipped` tells which
+/// files are never extracted, like unknown names, so a missing record of them
+/// is expected.
+pub fn plan(
+    old: &Catalog,
+    new: &Catalog,
+    records: Vec<ManifestRecord>,
+    skipped: impl Fn(u64, u64) -> bool,
+) -> Plan {
+    let old_sources = sources(old);
+    let new_sources = sources(new);
+    let recorded = records.iter()
+        .map(|record| (record.ext, record.name))
+        .collect::<HashSet<_>>();
+
+    let mut plan = Plan::default();
+    for (key, copies) in &new_sources {
+        if skipped(key.0, key.1) {
+            continue;
+        }
+        if old_sources.get(key) != Some(copies) || !recorded.contains(key) {
+            plan.changed.insert(*key);
+            plan.bundles.extend(copies.iter().map(|(bundle, _)| *bundle));
+        }
+    }
+
+    for record in records {
+        let key = (record.ext, record.name);
+        if !new_sources.contains_key(&key) {
+            plan.removed.push(record);
+        } else if !plan.changed.contains(&key) {
+            plan.kept.push(record);
+        }
+    }
+    plan
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bundle::TestBundles;
+    use crate::decompress::Passthrough;
+
+    fn record(name: u64) -> ManifestRecord {
+        ManifestRecord {
+            path: format!("{name:016x}.lua"),
+            ext: 0xa14e8dfa2cd117e2,
+            name,
+            resolved: None,
+            bundle: Some(1),
+            variants: Vec::new(),
+            converter: "raw".to_string(),
+            size: 0,
+            digest: 0,
+        }
+    }
+
+    #[test]
+    fn changed_files() {
+        let bundles = TestBundles::new("incremental");
+        bundles.write(1, &[(10, b"same"), (11, b"old"), (12, b"removed")]);
+        bundles.write(2, &[(13, b"same")]);
+
+        let mut old = Catalog::new();
+        old.refresh(&bundles.dir, &Passthrough, 1).unwrap();
+        let records = [10, 11, 12, 13].map(record).to_vec();
+
+        let mut new = old.clone();
+        // the size changes too, so refresh reads it again within the same mtime tick
+        bundles.write(1, &[(10, b"same"), (11, b"longer"), (14, b"added")]);
+        new.refresh(&bundles.dir, &Passthrough, 1).unwrap();
+
+        let plan = plan(&old, &new, records, |_, _| false);
+        let mut changed = plan.changed.iter().map(|(_, name)| *name).collect::<Vec<_>>();
+        changed.sort();
+        assert_eq!(changed, [11, 14]);
+        assert_eq!(plan.bundles, HashSet::from([1]));
+        assert_eq!(plan.kept.iter().map(|r| r.name).collect::<Vec<_>>(), [10, 13]);
+        assert_eq!(plan.removed.iter().map(|r| r.name).collect::<Vec<_>>(), [12]);
+    }
+
+    #[test]
+    fn filtered_run() {
+        let bundles = TestBundles::new("incremental-filtered");
+        bundles.write(1, &[(10, b"picked"), (11, b"filtered")]);
+        bundles.write(2, &[(12, b"unknown")]);
+        let mut catalog = Catalog::new();
+        catalog.refresh(&bundles.dir, &Passthrough, 1).unwrap();
+        let skipped = |_, name| name == 12;
+
+        // the first run was filtered down to 10
+        let first = plan(&Catalog::new(), &catalog, Vec::new(), skipped);
+        assert_eq!(first.changed.len(), 2);
+        assert_eq!(first.bundles, HashSet::from([1]));
+
+        // nothing changed on disk, but 11 was never extracted
+        let second = plan(&catalog, &catalog, vec![record(10)], skipped);
+        assert_eq!(second.changed.iter().map(|(_, name)| *name).collect::<Vec<_>>(), [11]);
+        assert_eq!(second.bundles, HashSet::from([1]));
+        assert_eq!(second.kept.iter().map(|r| r.name).collect::<Vec<_>>(), [10]);
+        assert!(second.removed.is_empty());
+
+        let third = plan(&catalog, &catalog, [10, 11].map(record).to_vec(), skipped);
+        assert!(third.changed.is_empty());
+        assert_eq!(third.kept.len(), 2);
+    }
+}
@@ -0,0 +1,236 @@
+//! Guess unknown names from the files listed in packages.
+//!
+//! Most `unit` files share the name of their `package`, and files loaded
+//! together tend to live in the same directory with related names. Names
+//! are proposed from the known names of each package and its files and only
+//! kept when they hash to a file in that package. Names found this way are
+//! used for the next round until no more are found.
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+
+use crate::bundle;
+use crate::bundle::BundleFd;
+use crate::decompress::Decompressor;
+use crate::dictionary::Dictionary;
+use crate::error::Result;
+use crate::file::Package;
+use crate::file::parse_package;
+use crate::format::Value;
+use crate::hash::murmur_hash64a;
+
+const PACKAGE: u64 = 0xad9c6d9ed1e5e77a;
+
+// endings of related files, e.g. `foo` and `foo_3p`
+const SUFFIXES: &[&str] = &[
+    "_1p",
+    "_3p",
+    "_lod",
+    "_mat",
+    "_material",
+    "_albedo",
+    "_color",
+    "_normal",
+    "_mask",
+    "_orm",
+    "_emissive",
+];
+
+// rounds before giving up on finding more names
+const MAX_ROUNDS: usize = 8;
+
+/// Name found for a hash and the known name it was derived from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Inferred {
+    pub name_hash: u64,
+    pub name: String,
+    /// Name hash of the package listing the file.
+    pub package: u64,
+    /// How `name` was derived from `source`.
+    pub rule: String,
+    pub source: String,
+}
+
+impl Inferred {
+    pub fn to_value(&self) -> Value {
+        Value::Object(vec![
+            ("name_hash".to_string(), format!("{:016x}", self.name_hash).into()),
+            ("name".to_string(), self.name.as_str().into()),
+            ("package".to_string(), format!("{:016x}", self.package).into()),
+            ("rule".to_string(), self.rule.as_str().into()),
+            ("source".to_string(), self.source.as_str().into()),
+        ])
+    }
+}
+
+/// Read the `package` files of every bundle in `dir` on `num_threads`
+/// threads.
+///
+/// Returns the name hash and contents of each package once, even when
+/// several bundles hold it.
+pub fn scan_packages(
+    dir: &Path,
+    decompressor: &dyn Decompressor,
+    num_threads: usize,
+) -> Result<Vec<(u64, Package)>> {
+    let bundles = bundle::list_bundles(dir)?;
+    let read = bundle::read_parallel(&bundles, num_threads, |_, bundle, scratch| {
+        read_packages(bundle, decompressor, scratch)
+    })?;
+    let mut packages = HashMap::new();
+    for (name, package) in read.into_iter().flatten() {
+        packages.entry(name).or_insert(package);
+    }
+
+    let mut packages = packages.into_iter().collect::<Vec<_>>();
+    packages.sort_unstable_by_key(|(name, _)| *name);
+    Ok(packages)
+}
+
+fn read_packages(
+    bundle: &mut BundleFd<'_>,
+    decompressor: &dyn Decompressor,
+    scratch: &mut Vec<u8>,
+) -> Result<Vec<(u64, Package)>> {
+    let hash = bundle.name;
+    if !bundle.index()?.any(|file| file.ext == PACKAGE) {
+        return Ok(Vec::new());
+    }
+
+    let mut packages = Vec::new();
+    let mut files = bundle.files(decompressor, scratch)?;
+    while let Some(mut file) = files.next_file()? {
+        if file.ext == PACKAGE {
+            let package = parse_package(&mut file)
+                .map_err(|e| e.with_entry(file.ext, file.name).with_bundle(hash))?;
+            packages.push((file.name, package));
+        } else {
+            file.skip()?;
+        }
+    }
+    Ok(packages)
+}
+
+fn split_dir(name: &str) -> (&str, &str) {
+    name.rsplit_once('/').unwrap_or(("", name))
+}
+
+// (candidate, rule) derived from the known name `base`
+fn candidates(base: &str, dirs: &HashSet<&str>, out: &mut Vec<(String, String)>) {
+    out.push((base.to_string(), "same name".to_string()));
+    for suffix in SUFFIXES {
+        out.push((format!("{base}{suffix}"), format!("add {suffix}")));
+        if let Some(stem) = base.strip_suffix(suffix) {
+            out.push((stem.to_string(), format!("remove {suffix}")));
+        }
+    }
+    let (_, file_name) = split_dir(base);
+    for dir in dirs {
+        if !dir.is_empty() {
+            out.push((format!("{dir}/{file_name}"), "sibling directory".to_string()));
+        }
+    }
+}
+
+/// Names for hashes missing from `dictionary` derived from `packages`.
+pub fn infer(packages: &[(u64, Package)], dictionary: &Dictionary) -> Vec<Inferred> {
+    // names found in earlier rounds
+    let mut known = HashMap::new();
+    let name_of = |known: &HashMap<u64, String>, name: u64| dictionary.get(&name.into())
+        .or_else(|| known.get(&name).map(String::as_str))
+        .map(str::to_string);
+    let mut found = Vec::new();
+    let mut candidates_buf = Vec::new();
+    for _ in 0..MAX_ROUNDS {
+        let num_found = found.len();
+        for (package, contents) in packages {
+            // the package's own name is derived the same way as its files
+            let mut seen = HashSet::new();
+            let names = contents.entries.iter()
+                .map(|(_, name)| *name)
+                .chain([*package])
+                .filter(|name| seen.insert(*name))
+                .collect::<Vec<_>>();
+            let unknown = names.iter()
+                .filter(|name| name_of(&known, **name).is_none())
+                .copied()
+                .collect::<HashSet<_>>();
+            if unknown.is_empty() {
+                continue;
+            }
+
+            let bases = names.iter()
+                .filter_map(|name| name_of(&known, *name))
+                .collect::<Vec<_>>();
+            let dirs = bases.iter()
+                .map(|name| split_dir(name).0)
+                .collect::<HashSet<_>>();
+            for base in &bases {
+                candidates_buf.clear();
+                candidates(base, &dirs, &mut candidates_buf);
+                for (name, rule) in candidates_buf.drain(..) {
+                    let hash = murmur_hash64a(name.as_bytes(), 0);
+                    if unknown.contains(&hash) && name_of(&known, hash).is_none() {
+                        known.insert(hash, name.clone());
+                        found.push(Inferred {
+                            name_hash: hash,
+                            name,
+                            package: *package,
+                            rule,
+                            source: base.clone(),
+                        });
+                    }
+                }
+            }
+        }
+        if found.len() == num_found {
+            break;
+        }
+    }
+    found
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn h(name: &str) -> u64 {
+        murmur_hash64a(name.as_bytes(), 0)
+    }
+
+    #[test]
+    fn names_from_packages() {
+        let unit = h("unit");
+        let texture = h("texture");
+        let packages = [
+            (h("content/weapons/lasgun/lasgun"), Package {
+                entries: vec![
+                    (unit, h("content/weapons/lasgun/lasgun")),
+                    (unit, h("content/weapons/lasgun/lasgun_3p")),
+                    (texture, h("content/weapons/shared/lasgun")),
+                    (texture, h("content/weapons/shared/misc")),
+                ],
+            }),
+            // only has a known name after the first package
+            (h("packages/lasgun"), Package {
+                entries: vec![
+                    (unit, h("content/weapons/lasgun/lasgun_3p")),
+                    (unit, h("content/weapons/lasgun/lasgun_3p_lod")),
+                ],
+            }),
+        ];
+        let mut dictionary = Dictionary::new();
+        dictionary.add(["content/weapons/lasgun/lasgun", "content/weapons/shared/misc"].into_iter());
+
+        let found = infer(&packages, &dictionary);
+        let found = found.iter()
+            .map(|f| (f.name.as_str(), f.rule.as_str()))
+            .collect::<Vec<_>>();
+        assert_eq!(found, [
+            ("content/weapons/lasgun/lasgun_3p", "add _3p"),
+            ("content/weapons/shared/lasgun", "sibling directory"),
+            ("content/weapons/lasgun/lasgun_3p_lod", "add _lod"),
+        ]);
+    }
+}
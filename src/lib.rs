@@ -6,13 +6,34 @@ use std::path::Path;
 use std::path::PathBuf;
 use std::panic::RefUnwindSafe;
 
+mod archive;
 pub mod bundle;
+pub mod catalog;
+pub mod crack;
+mod decompress;
+pub use decompress::Decompressor;
+pub use decompress::Passthrough;
+pub mod diff;
+pub mod dictionary;
+use dictionary::CompiledDictionary;
+use dictionary::Dictionary;
+mod error;
+pub use error::Error;
+pub use error::ErrorKind;
+pub use error::Result;
 pub mod file;
+pub mod filter;
+pub mod format;
+use format::Format;
+pub mod glob;
+pub mod harvest;
 use file::ExtractOptions;
+use file::Extractor;
 pub mod hash;
-use hash::MurmurHash;
-use hash::MurmurHash32;
-mod oodle;
+pub mod incremental;
+pub mod infer;
+pub mod manifest;
+pub mod oodle;
 pub use oodle::Oodle;
 pub mod read;
 mod scoped_fs;
@@ -22,10 +43,14 @@ use scoped_fs::ScopedFs;
 pub struct ExtractBuilder {
     input: Option<PathBuf>,
     output: Option<Box<dyn FileOpen>>,
-    oodle: Option<Oodle>,
-    dictionary: Option<HashMap<MurmurHash, String>>,
-    dictionary_short: Option<HashMap<MurmurHash32, MurmurHash>>,
+    decompressor: Option<Box<dyn Decompressor>>,
+    extractors: HashMap<u64, Box<dyn Extractor>>,
+    dictionary: Option<Dictionary>,
     config: HashSet<String>,
+    format: Format,
+    manifest: bool,
+    per_bundle: bool,
+    harvest: bool,
 
     skip_unknown: Option<bool>,
     dump_hashes: bool,
@@ -37,10 +62,17 @@ impl ExtractBuilder {
         Self {
             input: None,
             output: None,
-            oodle: None,
+            decompressor: None,
+            extractors: file::builtin_extractors()
+                .into_iter()
+                .map(|(ext, extractor)| (hash::murmur_hash64a(ext.as_bytes(), 0), extractor))
+                .collect(),
             dictionary: None,
-            dictionary_short: None,
             config: HashSet::new(),
+            format: Format::default(),
+            manifest: false,
+            per_bundle: false,
+            harvest: false,
             skip_unknown: None,
             dump_hashes: false,
             dump_raw: false,
@@ -69,6 +101,28 @@ impl ExtractBuilder {
         self
     }
 
+    /// Write files into a tar archive at `path` instead of a directory.
+    pub fn output_tar(
+        &mut self,
+        path: impl AsRef<Path>,
+    ) -> Result<&mut Self> {
+        self.output = Some(Box::new(archive::TarArchive::create(path.as_ref())?));
+        Ok(self)
+    }
+
+    /// Write files into a zip archive at `path` instead of a directory.
+    ///
+    /// Files are compressed with deflate when `deflate` is set and stored
+    /// otherwise.
+    pub fn output_zip(
+        &mut self,
+        path: impl AsRef<Path>,
+        deflate: bool,
+    ) -> Result<&mut Self> {
+        self.output = Some(Box::new(archive::ZipArchive::create(path.as_ref(), deflate)?));
+        Ok(self)
+    }
+
     pub fn output_custom(
         &mut self,
         cb: impl Fn(&str, &[u8]) + Send + Sync + RefUnwindSafe + 'static,
@@ -78,7 +132,26 @@ impl ExtractBuilder {
     }
 
     pub fn oodle(&mut self, oodle: Oodle) -> &mut Self {
-        self.oodle = Some(oodle);
+        self.decompressor(Box::new(oodle))
+    }
+
+    /// Decompressor for bundle chunks and texture streams.
+    ///
+    /// Without one, extraction fails on the first compressed chunk.
+    pub fn decompressor(&mut self, decompressor: Box<dyn Decompressor>) -> &mut Self {
+        self.decompressor = Some(decompressor);
+        self
+    }
+
+    /// Convert files with extension `ext_name` using `extractor`.
+    ///
+    /// Replaces the built-in converter for the extension if there is one.
+    pub fn register_extractor(
+        &mut self,
+        ext_name: &str,
+        extractor: Box<dyn Extractor>,
+    ) -> &mut Self {
+        self.extractors.insert(hash::murmur_hash64a(ext_name.as_bytes(), 0), extractor);
         self
     }
 
@@ -86,23 +159,47 @@ impl ExtractBuilder {
         &mut self,
         keys: impl Iterator<Item = T>,
     ) -> &mut Self {
-        let dict = self.dictionary.get_or_insert_default();
-        let dict_short = self.dictionary_short.get_or_insert_default();
-        for key in keys {
-            let mut key = key.into();
-            let hash = if let Some(map) = key.strip_prefix("@")
-                && let Some((hash_s, key_to)) = map.split_once("=")
-                && hash_s.len() == 16
-                && let Ok(hash) = u64::from_str_radix(hash_s, 16)
-            {
-                key = key_to.to_string();
-                MurmurHash(hash)
-            } else {
-                MurmurHash::new(&key)
-            };
-            dict.insert(hash.clone(), key);
-            dict_short.insert(hash.clone_short(), hash);
-        }
+        self.dictionary.get_or_insert_default().add(keys);
+        self
+    }
+
+    /// Look up names in `dictionary`, replacing names added before.
+    pub fn set_dictionary(&mut self, dictionary: Dictionary) -> &mut Self {
+        self.dictionary = Some(dictionary);
+        self
+    }
+
+    /// Look up names in a dictionary compiled by [`Dictionary::save`].
+    ///
+    /// Names from [`dictionary`](Self::dictionary) take precedence.
+    pub fn compiled_dictionary(&mut self, compiled: CompiledDictionary) -> &mut Self {
+        self.dictionary.get_or_insert_default().add_compiled(compiled);
+        self
+    }
+
+    /// Format of structured files written by converters. Default is JSON.
+    pub fn format(&mut self, format: Format) -> &mut Self {
+        self.format = format;
+        self
+    }
+
+    /// Record every written file, see [`manifest`].
+    pub fn manifest(&mut self, toggle: bool) -> &mut Self {
+        self.manifest = toggle;
+        self
+    }
+
+    /// Write files under a directory named after the bundle they came from
+    /// instead of merging every bundle into one tree.
+    pub fn per_bundle(&mut self, toggle: bool) -> &mut Self {
+        self.per_bundle = toggle;
+        self
+    }
+
+    /// Collect strings from converted files that may name other resources,
+    /// see [`harvest`].
+    pub fn harvest(&mut self, toggle: bool) -> &mut Self {
+        self.harvest = toggle;
         self
     }
 
@@ -130,16 +227,20 @@ impl ExtractBuilder {
         self
     }
 
-    pub fn build(self) -> Result<ExtractOptions, &'static str> {
+    pub fn build(self) -> Result<ExtractOptions> {
         let skip_unknown = self.skip_unknown.unwrap_or(self.dictionary.is_some());
 
         Ok(ExtractOptions {
-            target: self.input.ok_or("missing input")?,
-            out: self.output.ok_or("missing output")?,
-            oodle: self.oodle.ok_or("missing oodle")?,
+            target: self.input.ok_or(ErrorKind::MissingOption("input"))?,
+            out: self.output.ok_or(ErrorKind::MissingOption("output"))?,
+            decompressor: self.decompressor.unwrap_or_else(|| Box::new(decompress::Missing)),
+            extractors: self.extractors,
             dictionary: self.dictionary.unwrap_or_default(),
-            dictionary_short: self.dictionary_short.unwrap_or_default(),
             config: self.config,
+            format: self.format,
+            manifest: self.manifest.then(manifest::Manifest::new),
+            per_bundle: self.per_bundle,
+            harvest: self.harvest.then(harvest::Harvest::new),
             skip_extract: self.dump_hashes,
             skip_unknown,
             as_blob: self.dump_raw,
@@ -164,6 +265,7 @@ where
         thread_local!(static BUFFER: Cell<Vec<u8>> = Cell::new(Vec::new()));
 
         let mut buffer = BUFFER.take();
+        buffer.clear();
         let res = scope(&mut buffer)?;
         self.0(
             path.to_str().unwrap(),
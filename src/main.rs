@@ -1,5 +1,6 @@
 use std::collections::HashSet;
 use std::collections::HashMap;
+use std::fmt::Write;
 use std::fs;
 use std::fs::File;
 use std::sync::Arc;
@@ -8,22 +9,35 @@ use std::sync::atomic::Ordering;
 use std::sync::Mutex;
 use std::thread;
 use std::time::Instant;
-use std::io;
 use std::io::Read;
 use std::io::Seek;
 use std::panic;
+use std::path::Component;
 use std::path::Path;
 use std::path::PathBuf;
 
 use limn::ExtractBuilder;
+use limn::bundle;
 use limn::bundle::BundleFd;
+use limn::bundle::ChunkWorkers;
 use limn::file;
 use limn::file::ExtractOptions;
 use limn::file::Pool;
+use limn::catalog::Catalog;
+use limn::filter::NameFilter;
+use limn::format::Format;
+use limn::format::Value;
+use limn::harvest;
 use limn::hash;
+use limn::incremental;
+use limn::incremental::Plan;
+use limn::manifest;
 use limn::Oodle;
+use limn::oodle::LoadError;
 use limn::read::ChunkReader;
 
+mod cmd;
+
 fn print_help() {
     println!("{} {}", env!("CARGO_PKG_NAME"), env!("CARGO_PKG_VERSION"));
     println!("{}", env!("CARGO_PKG_AUTHORS"));
@@ -32,11 +46,22 @@ fn print_help() {
     println!();
     println!("limn uses oo2core_9_win64.dll to decompress the bundle. If it fails to load");
     println!("oo2core_9_win64.dll then copy it from the Darktide binaries folder next to limn.");
+    println!("On Linux limn looks for liboo2corelinux64.so.9 instead. Set LIMN_OODLE or use");
+    println!("--oodle to load the library from another path.");
+    println!();
+    println!("Every extract writes `manifest.jsonl` to the output directory with the source,");
+    println!("converter, size and digest of each output file.");
     println!();
     println!("Project home: {}", env!("CARGO_PKG_REPOSITORY"));
     println!();
     println!("USAGE:");
     println!("limn.exe [OPTIONS] <FILTER>");
+    println!("limn.exe <COMMAND> [OPTIONS]");
+    println!();
+    println!("COMMANDS:");
+    for (name, about, _) in cmd::COMMANDS {
+        println!("    {name:<10}{about}");
+    }
     println!();
     println!("ARGS:");
     println!("    <FILTER>  Extract files with matching extension. Supports \"*\" as a wildcard.");
@@ -44,20 +69,66 @@ fn print_help() {
     println!("OPTIONS:");
     println!("        --dump-hashes         Dump file extension and name hashes.");
     println!("        --dump-raw            Extract files without converting contents.");
-    println!("        --dict <PATH>         Load dictionary. Default is `dictionary.txt`.");
+    println!("        --incremental         Only extract files that changed since the last extract.");
+    println!("        --prune               With --incremental, delete outputs of removed files.");
+    println!("        --harvest             Append names found inside extracted files to `learned.txt`.");
+    println!("        --keep-going          Continue past errors and list them in `failures.json`.");
+    println!("        --dict <PATH>         Load dictionary. Default is `dictionary.bin` if newer, or `dictionary.txt` and `learned.txt`.");
     println!("        --dict-no-skip        Extract unknown files when using a dictionary.");
+    println!("        --oodle <PATH>        Load the Oodle library from PATH.");
+    println!("        --zip-stored          Store files in a zip output without compressing them.");
+    println!("        --format <FORMAT>     Converted file format (json, json-pretty, yaml, toml, csv).");
     println!("    -i, --input <PATH>        Bundle or directory of bundles to extract.");
-    println!("    -o, --output <PATH>       Extract output directory. Default is `out`.");
+    println!("    -o, --output <PATH>       Extract output directory, or archive ending in .zip or .tar.");
+    println!("                              Default is `out`.");
     println!("    -f, --filter <FILTER>     Only extract files with matching extension.");
+    println!("        --name <GLOB>         Only extract files with dictionary name matching GLOB.");
+    println!("                              A GLOB without `/` matches the last part of the name.");
+    println!("        --name-regex <REGEX>  Only extract files with dictionary name matching REGEX.");
+    println!("        --hash <HEX>          Only extract files with 16 digit name hash HEX.");
+    println!("        --bundle <BUNDLE>     Only extract bundles with package name, hash or glob.");
+    println!("        --per-bundle          Write files to a directory per bundle.");
     println!("    -c, --config <CONFIG>     Comma delimited config options (extract-lua-source).");
 }
 
+enum Archive {
+    Tar,
+    Zip,
+}
+
+impl Archive {
+    // archive written instead of a directory, by the extension of `-o`
+    fn from_path(path: &Path) -> Option<Self> {
+        let ext = path.extension()?.to_str()?;
+        if ext.eq_ignore_ascii_case("tar") {
+            Some(Self::Tar)
+        } else if ext.eq_ignore_ascii_case("zip") {
+            Some(Self::Zip)
+        } else {
+            None
+        }
+    }
+}
+
 struct Args {
     dump_hashes: bool,
 
     // always dump files raw instead of using crate::file::Extractor
     dump_raw: bool,
 
+    // record failed bundles and files instead of stopping
+    keep_going: bool,
+
+    // learn names from strings in extracted files
+    harvest: bool,
+
+    // skip files unchanged since the catalog saved in the output directory
+    incremental: bool,
+    prune: bool,
+
+    // store instead of deflate files in zip outputs
+    zip_stored: bool,
+
     // path to bundle OR directory of bundles
     target: PathBuf,
 
@@ -65,10 +136,22 @@ struct Args {
 
     dict_no_skip: bool,
 
+    // overrides the Oodle library search
+    oodle: Option<PathBuf>,
+
     output: PathBuf,
 
+    format: Format,
+
     filter_ext: HashSet<u64>,
 
+    // files matching any name filter pass, combined with `filter_ext`
+    name_filter: NameFilter,
+
+    // bundles are named after the package they ship
+    bundle_filter: NameFilter,
+    per_bundle: bool,
+
     darktide_path: Option<PathBuf>,
 
     config: Vec<String>,
@@ -80,12 +163,22 @@ fn parse_args() -> Args {
 
     let mut dump_hashes = false;
     let mut dump_raw = false;
+    let mut keep_going = false;
+    let mut harvest = false;
+    let mut incremental = false;
+    let mut prune = false;
+    let mut zip_stored = false;
 
     let mut dictionary = Vec::new();
     let mut dict_no_skip = false;
+    let mut oodle = None;
     let mut target = None;
     let mut output = None;
+    let mut format = Format::default();
     let mut filter_ext = HashSet::new();
+    let mut name_filter = NameFilter::new();
+    let mut bundle_filter = NameFilter::new();
+    let mut per_bundle = false;
     let mut config = Vec::new();
 
     let mut num_args = 0;
@@ -102,6 +195,16 @@ fn parse_args() -> Args {
 
             "--dump-raw" => dump_raw = true,
 
+            "--keep-going" => keep_going = true,
+
+            "--harvest" => harvest = true,
+
+            "--incremental" => incremental = true,
+
+            "--prune" => prune = true,
+
+            "--zip-stored" => zip_stored = true,
+
             "--dict" => {
                 let Some(param) = args.next() else {
                     eprintln!("ERROR: missing parameter to {}", opt);
@@ -112,6 +215,26 @@ fn parse_args() -> Args {
 
             "--dict-no-skip" => dict_no_skip = true,
 
+            "--oodle" => {
+                let Some(param) = args.next() else {
+                    eprintln!("ERROR: missing parameter to {}", opt);
+                    std::process::exit(1);
+                };
+                oodle = Some(PathBuf::from(param));
+            }
+
+            "--format" => {
+                let Some(param) = args.next() else {
+                    eprintln!("ERROR: missing parameter to {}", opt);
+                    std::process::exit(1);
+                };
+                let Some(val) = param.to_str().and_then(Format::from_name) else {
+                    eprintln!("ERROR: unknown format {param:?}");
+                    std::process::exit(1);
+                };
+                format = val;
+            }
+
             "-i" | "--input" => {
                 let Some(param) = args.next() else {
                     eprintln!("ERROR: missing parameter to {}", opt);
@@ -128,6 +251,50 @@ fn parse_args() -> Args {
                 output = Some(PathBuf::from(param));
             }
 
+            "--name" | "--name-regex" | "--hash" => {
+                let Some(param) = args.next() else {
+                    eprintln!("ERROR: missing parameter to {}", opt);
+                    std::process::exit(1);
+                };
+
+                let Some(val) = param.to_str() else {
+                    eprintln!("ERROR: invalid UTF-8 in parameter to {}", opt);
+                    std::process::exit(1);
+                };
+
+                if opt == "--name" {
+                    name_filter.glob(val);
+                } else if opt == "--name-regex" {
+                    if let Err(e) = name_filter.regex(val) {
+                        eprintln!("ERROR: invalid regex {val:?}\n{e}");
+                        std::process::exit(1);
+                    }
+                } else if val.len() == 16
+                    && let Ok(hash) = u64::from_str_radix(val, 16)
+                {
+                    name_filter.hash(hash);
+                } else {
+                    eprintln!("ERROR: expected 16 digit hex hash, got {val:?}");
+                    std::process::exit(1);
+                }
+            }
+
+            "--bundle" => {
+                let Some(param) = args.next() else {
+                    eprintln!("ERROR: missing parameter to {}", opt);
+                    std::process::exit(1);
+                };
+
+                let Some(val) = param.to_str() else {
+                    eprintln!("ERROR: invalid UTF-8 in parameter to {}", opt);
+                    std::process::exit(1);
+                };
+
+                bundle_filter.bundle(val);
+            }
+
+            "--per-bundle" => per_bundle = true,
+
             "-c" | "--config" => {
                 let Some(param) = args.next() else {
                     eprintln!("ERROR: missing parameter to {}", opt);
@@ -209,77 +376,115 @@ fn parse_args() -> Args {
     Args {
         dump_hashes,
         dump_raw,
+        keep_going,
+        harvest,
+        incremental,
+        prune,
+        zip_stored,
 
         dictionary,
         dict_no_skip,
+        oodle,
         target,
         output,
+        format,
         filter_ext,
+        name_filter,
+        bundle_filter,
+        per_bundle,
         darktide_path: darktide_path.ok(),
         config,
     }
 }
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
+    if let Some(name) = std::env::args_os().nth(1)
+        && let Some(run) = cmd::find(&name)
+    {
+        return run(&mut std::env::args_os().skip(2));
+    }
+
     let Args {
         dump_hashes,
         dump_raw,
+        keep_going,
+        harvest,
+        incremental,
+        prune,
+        zip_stored,
 
         dictionary,
         dict_no_skip,
+        oodle,
         target,
         output,
+        format,
         filter_ext,
+        name_filter,
+        bundle_filter,
+        per_bundle,
         darktide_path,
         config,
     } = parse_args();
-
-    let mut dictionary_load = Vec::new();
-    if dictionary.is_empty() {
-        if let Ok(dict) = fs::read_to_string("dictionary.txt") {
-            dictionary_load.push(dict);
-        }
+    let archive = if dump_hashes {
+        None
     } else {
-        let mut failed = false;
-        for path in dictionary {
-            let Ok(dict) = fs::read_to_string(&path) else {
-                eprintln!("ERROR: failed to load dictionary \"{}\"", path.display());
-                failed = true;
-                continue;
-            };
-            dictionary_load.push(dict);
-        }
-
-        if failed {
-            std::process::exit(1);
-        }
+        Archive::from_path(&output)
+    };
+    if incremental && archive.is_some() {
+        eprintln!("WARN: --incremental needs an output directory, extracting everything");
     }
+    let incremental = incremental && !dump_hashes && archive.is_none();
+    let output_dir = output.clone();
 
-    let oodle = match load_oodle("oo2core_9_win64.dll", &target, darktide_path.as_ref())
-        .or_else(|_| load_oodle("oo2core_8_win64.dll", &target, darktide_path.as_ref()))
-    {
-        Ok(oodle) => oodle,
+    let dictionary = match cmd::load_dictionary(&dictionary) {
+        Ok(dictionary) => dictionary,
         Err(e) => {
-            eprintln!("oo2core_9_win64.dll could not be loaded");
-            eprintln!("copy the dll from the Darktide binaries folder next to limn");
-            eprintln!();
-            return Err(Box::new(e));
+            eprintln!("ERROR: {e}");
+            std::process::exit(1);
         }
     };
 
-    let output = if dump_hashes {
+    // dumping hashes only reads the bundle index
+    let oodle = if dump_hashes {
         None
     } else {
-        Some(output)
+        match load_oodle(oodle, &target, darktide_path.as_ref()) {
+            Ok(oodle) => Some(oodle),
+            Err(e) => {
+                eprintln!("{e}");
+                eprintln!();
+                eprintln!("copy {} next to limn", limn::oodle::LIBRARY_NAMES[0]);
+                eprintln!("or point LIMN_OODLE or --oodle at the library");
+                std::process::exit(1);
+            }
+        }
     };
 
     let mut builder = ExtractBuilder::new();
-    builder.output(output)
-        .oodle(oodle)
+    match archive {
+        Some(Archive::Tar) => {
+            builder.output_tar(&output)?;
+        }
+        Some(Archive::Zip) => {
+            builder.output_zip(&output, !zip_stored)?;
+        }
+        None => {
+            builder.output(if dump_hashes { None } else { Some(&output) });
+        }
+    }
+    builder
         .dump_hashes(dump_hashes)
-        .dump_raw(dump_raw);
-    for dict in dictionary_load {
-        builder.dictionary(dict.lines());
+        .dump_raw(dump_raw)
+        .format(format)
+        .manifest(!dump_hashes)
+        .per_bundle(per_bundle)
+        .harvest(harvest && !dump_hashes);
+    if let Some(oodle) = oodle {
+        builder.oodle(oodle);
+    }
+    if !dictionary.is_empty() {
+        builder.set_dictionary(dictionary);
     }
     if dict_no_skip {
         builder.skip_unknown(false);
@@ -289,65 +494,101 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         builder.config(&key, true);
     }
 
-    let duplicates = Mutex::new(HashMap::new());
     let start = Instant::now();
     let options;
-    let num_files = if let Ok(read_dir) = fs::read_dir(&target) {
-        builder.input(target);
+    let shared;
+    let mut catalog = None;
+    let plan;
+    let num_files = if let Ok(mut bundles) = bundle::list_bundles(&target) {
+        builder.input(&target);
         options = builder.build()?;
+        warn_collisions(&options);
+        let num_threads = num_threads();
 
-        let mut bundles = Vec::new();
-        for fd in read_dir {
-            let fd = fd.as_ref().unwrap();
-            let meta = fd.metadata().unwrap();
-            if meta.is_file() {
-                let path = fd.path();
-                if path.extension().is_some() {
-                    continue;
-                }
+        bundles.retain(|(_, hash)| bundle_filter.is_match(*hash, options.lookup(&(*hash).into())));
 
-                if let Some(bundle_hash) = bundle_hash_from(&path) {
-                    bundles.push((path, bundle_hash));
-                }
-            }
+        // --incremental plans from the catalog kept in the output instead
+        if !incremental
+            && !dump_hashes
+            && !harvest
+            && (!filter_ext.is_empty() || !name_filter.is_empty())
+            && let Some(wanted) = catalog_bundles(&target, &options, &filter_ext, &name_filter, num_threads)
+        {
+            bundles.retain(|(_, hash)| wanted.contains(hash));
         }
 
-        let num_threads = thread::available_parallelism()
-            .map(|i| i.get())
-            .unwrap_or(0)
-            .saturating_sub(1)
-            .max(1);
+        plan = if incremental {
+            // files left out by filters have no record and are planned again next time
+            let (new_catalog, plan) = plan_incremental(&target, &output_dir, &options, &name_filter, num_threads, prune)?;
+            bundles.retain(|(_, hash)| plan.bundles.contains(hash));
+            catalog = Some(new_catalog);
+            Some(plan)
+        } else {
+            None
+        };
+        shared = Shared::new(&options, &filter_ext, &name_filter, keep_going, plan.as_ref().map(|plan| &plan.changed));
 
-        let mut dupes = duplicates.lock().unwrap();
+        let mut dupes = shared.duplicates.lock().unwrap();
         dupes.reserve(0x10000);
         drop(dupes);
         batch_threads(
             num_threads,
             &bundles,
-            &duplicates,
-            &options,
-            &filter_ext,
+            &shared,
         )
     } else if let Ok(bundle) = File::open(&target) {
+        if incremental {
+            eprintln!("WARN: --incremental needs a directory of bundles, extracting everything");
+        }
         builder.input(target.parent().unwrap().to_path_buf());
         options = builder.build()?;
+        warn_collisions(&options);
+        shared = Shared::new(&options, &filter_ext, &name_filter, keep_going, None);
 
-        let bundle_hash = bundle_hash_from(&target);
-        let mut buf = vec![0; 0x80000];
-        let mut rdr = ChunkReader::new(&mut buf, bundle);
-        Some(extract_bundle(
-            &mut Pool::new(),
-            &mut rdr,
-            &mut Vec::new(),
-            bundle_hash,
-            &duplicates,
-            &options,
-            &filter_ext,
-        ).unwrap())
+        let bundle_hash = bundle::bundle_hash_from(&target);
+        if bundle_filter.is_empty()
+            || bundle_hash.is_some_and(|hash| bundle_filter.is_match(hash, options.lookup(&hash.into())))
+        {
+            let mut buf = vec![0; 0x80000];
+            let mut rdr = ChunkReader::new(&mut buf, bundle);
+            let num_workers = thread::available_parallelism()
+                .map(|i| i.get())
+                .unwrap_or(1)
+                .min(MAX_CHUNK_WORKERS);
+            match extract_bundle(
+                &mut Pool::new(),
+                &mut rdr,
+                &mut Vec::new(),
+                &mut ChunkWorkers::new(num_workers),
+                num_workers,
+                bundle_hash,
+                &shared,
+            ) {
+                Ok(num_files) => Some(num_files),
+                Err(e) if keep_going => {
+                    shared.fail(Failure::bundle(e.with_bundle(bundle_hash)));
+                    Some(0)
+                }
+                Err(e) => {
+                    eprintln!("{e}");
+                    None
+                }
+            }
+        } else {
+            eprintln!("WARN: bundle does not match --bundle, extracting nothing");
+            Some(0)
+        }
     } else {
         panic!("PATH argument was invalid");
     };
 
+    let Shared {
+        duplicates,
+        failures,
+        ..
+    } = shared;
+    let failures = failures.into_inner().unwrap();
+
     println!();
     if let Some(num_files) = num_files {
         let ms = start.elapsed().as_millis();
@@ -355,6 +596,46 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         println!("took {}.{}s", ms / 1000, ms % 1000);
         if !options.skip_extract() {
             println!("extracted {num_files} files");
+            options.finish()?;
+        }
+
+        // an aborted run keeps the old catalog so every bundle is compared again
+        if let Some(mut catalog) = catalog {
+            // failed files get another try next time
+            for failure in &failures {
+                if let Some(bundle) = failure.bundle {
+                    catalog.remove(bundle);
+                }
+            }
+            catalog.save(&output_dir.join(incremental::CATALOG_FILE))?;
+        }
+
+        if let Some(harvest) = options.harvest() {
+            // only names of files in the bundles read are learned
+            let names = duplicates.lock()
+                .unwrap()
+                .keys()
+                .map(|(_, name)| *name)
+                .filter(|name| !options.contains_key(&(*name).into()))
+                .collect::<HashSet<_>>();
+            let learned = harvest.matching(&names);
+            if !learned.is_empty() {
+                let mut text = String::new();
+                for name in &learned {
+                    writeln!(text, "{name}").unwrap();
+                }
+                let mut fd = fs::OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(harvest::LEARNED_FILE)?;
+                std::io::Write::write_all(&mut fd, text.as_bytes())?;
+            }
+            print!("learned {} names from {} strings", learned.len(), harvest.len());
+            if learned.is_empty() {
+                println!();
+            } else {
+                println!(", appended to \"{}\"", harvest::LEARNED_FILE);
+            }
         }
 
         if dump_hashes {
@@ -386,36 +667,155 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
             println!("{} file extension and name hashes written to \"hashes.bin\"", bin.len() / 16);
         }
     } else {
-        // TODO app exit code
         println!("did not finish due to errors");
     }
 
+    if keep_going {
+        fs::write("failures.json", failures_json(&failures, &options)?)?;
+        if !failures.is_empty() {
+            let bundles = failures.iter().filter(|f| f.is_bundle).count();
+            println!("{} bundles and {} files failed, see \"failures.json\"",
+                bundles,
+                failures.len() - bundles);
+        }
+    }
+
+    if num_files.is_none() || !failures.is_empty() {
+        // exit skips destructors, dropping writes the end of tar and zip outputs
+        drop(options);
+        std::process::exit(1);
+    }
+
     Ok(())
 }
 
+// state shared between extraction threads
+struct Shared<'a> {
+    duplicates: Mutex<HashMap<(u64, u64), u64>>,
+    options: &'a ExtractOptions,
+    filter_ext: &'a HashSet<u64>,
+    name_filter: &'a NameFilter,
+    keep_going: bool,
+    // only these files are extracted with --incremental
+    changed: Option<&'a HashSet<(u64, u64)>>,
+    failures: Mutex<Vec<Failure>>,
+}
+
+impl<'a> Shared<'a> {
+    fn new(
+        options: &'a ExtractOptions,
+        filter_ext: &'a HashSet<u64>,
+        name_filter: &'a NameFilter,
+        keep_going: bool,
+        changed: Option<&'a HashSet<(u64, u64)>>,
+    ) -> Self {
+        Self {
+            duplicates: Mutex::new(HashMap::new()),
+            options,
+            filter_ext,
+            name_filter,
+            keep_going,
+            changed,
+            failures: Mutex::new(Vec::new()),
+        }
+    }
+
+    fn fail(&self, failure: Failure) {
+        if !self.keep_going {
+            eprintln!("{}", failure.reason);
+        }
+        self.failures.lock().unwrap().push(failure);
+    }
+}
+
+struct Failure {
+    // whole bundle was abandoned instead of a single file
+    is_bundle: bool,
+    bundle: Option<u64>,
+    entry: Option<(u64, u64)>,
+    offset: Option<u64>,
+    reason: String,
+}
+
+impl Failure {
+    fn bundle(e: limn::Error) -> Self {
+        Self {
+            is_bundle: true,
+            ..Self::file(e)
+        }
+    }
+
+    fn file(e: limn::Error) -> Self {
+        Self {
+            is_bundle: false,
+            bundle: e.bundle(),
+            entry: e.entry(),
+            offset: e.offset(),
+            reason: e.to_string(),
+        }
+    }
+}
+
+// strings keys can only be told apart by the upper 32 bits of their hash
+fn warn_collisions(options: &ExtractOptions) {
+    let num_collisions = options.dictionary().num_collisions();
+    if num_collisions > 0 {
+        eprintln!("WARN: {num_collisions} short hashes are shared by several dictionary names, `limn dict compile` lists them");
+    }
+}
+
+fn failures_json(failures: &[Failure], options: &ExtractOptions) -> limn::Result<Vec<u8>> {
+    let failures = failures.iter().map(|failure| {
+        let kind = if failure.is_bundle { "bundle" } else { "file" };
+        let mut fields = vec![
+            ("kind".to_string(), kind.into()),
+            ("bundle".to_string(), failure.bundle.map_or(Value::Null, |bundle| format!("{bundle:016x}").into())),
+        ];
+        if let Some((ext, name)) = failure.entry {
+            fields.push(("ext_hash".to_string(), format!("{ext:016x}").into()));
+            fields.push(("name_hash".to_string(), format!("{name:016x}").into()));
+            if let Some(ext) = hash::extension_name(ext) {
+                fields.push(("ext".to_string(), ext.into()));
+            }
+            if let Some(name) = options.lookup(&name.into()) {
+                fields.push(("name".to_string(), name.into()));
+            }
+        }
+        if let Some(offset) = failure.offset {
+            fields.push(("offset".to_string(), Value::Int(offset as i64)));
+        }
+        fields.push(("reason".to_string(), failure.reason.as_str().into()));
+        Value::Object(fields)
+    }).collect();
+
+    let mut out = Vec::new();
+    Format::JsonPretty.write(&mut out, &Value::Array(failures))?;
+    Ok(out)
+}
+
 fn batch_threads(
     num_threads: usize,
     bundles: &[(PathBuf, u64)],
-    duplicates: &Mutex<HashMap<(u64, u64), u64>>,
-    options: &ExtractOptions,
-    filter_ext: &HashSet<u64>,
+    shared: &Shared,
 ) -> Option<u32> {
     let bundle_index = Arc::new(AtomicUsize::new(0));
+    // chunk workers running at once across all bundles
+    let worker_budget = AtomicUsize::new(num_threads);
     let thread_errors = Arc::new(Mutex::new(Vec::with_capacity(num_threads)));
 
     let total = bundles.len();
+    let keep_going = shared.keep_going;
     {
         let bundle_index = bundle_index.clone();
         let thread_errors = thread_errors.clone();
         panic::set_hook(Box::new(move |p| {
+            if keep_going {
+                // recorded as a bundle failure by thread_work
+                return;
+            }
+
             let location = p.location().map(|l| l.to_string()).unwrap_or(String::new());
-            let payload = if let Some(s) = p.payload().downcast_ref::<&str>() {
-                s.to_string()
-            } else if let Some(s) = p.payload().downcast_ref::<String>() {
-                s.to_string()
-            } else {
-                String::new()
-            };
+            let payload = panic_message(p.payload());
 
             let mut thread_errors = thread_errors.lock().unwrap();
             if thread_errors.is_empty() {
@@ -431,15 +831,14 @@ fn batch_threads(
         for _ in 0..num_threads {
             threads.push(s.spawn(|| {
                 panic::catch_unwind(|| thread_work(
-                    &bundles,
+                    num_threads,
+                    bundles,
                     &bundle_index,
-                    &duplicates,
-                    &options,
-                    filter_ext,
+                    &worker_budget,
+                    shared,
                 ))
             }));
         }
-
         let mut prev = (0, Instant::now());
         loop {
             thread::sleep(std::time::Duration::from_millis(1));
@@ -471,10 +870,25 @@ fn batch_threads(
 
         if threads.iter().all(|t| t.is_ok()) {
             let mut num_files = 0;
+            let mut failed = false;
             for thread in threads {
-                num_files += thread.unwrap();
+                match thread.unwrap() {
+                    Ok(count) => num_files += count,
+                    Err(e) => {
+                        if !failed {
+                            eprintln!();
+                        }
+                        eprintln!("{e}");
+                        failed = true;
+                    }
+                }
+            }
+
+            if failed {
+                None
+            } else {
+                Some(num_files)
             }
-            Some(num_files)
         } else {
             let thread_errors = thread_errors.lock().unwrap();
             if thread_errors.is_empty() {
@@ -513,59 +927,144 @@ fn batch_threads(
     })
 }
 
+// decompression threads per bundle once other threads run out of bundles
+const MAX_CHUNK_WORKERS: usize = 4;
+
 fn thread_work(
+    num_threads: usize,
     bundles: &[(PathBuf, u64)],
     bundle_index: &AtomicUsize,
-    duplicates: &Mutex<HashMap<(u64, u64), u64>>,
-    options: &ExtractOptions,
-    filter_ext: &HashSet<u64>,
-) -> u32 {
+    worker_budget: &AtomicUsize,
+    shared: &Shared,
+) -> limn::Result<u32> {
     let mut pool = Pool::new();
     let mut buffer_reader = vec![0_u8; 0x80000];
     let mut bundle_buf = Vec::new();
+    let mut workers = ChunkWorkers::new(num_threads.min(MAX_CHUNK_WORKERS));
     let mut num_files = 0;
 
-    while let Some((path, bundle_hash)) =
-        bundles.get(bundle_index.fetch_add(1, Ordering::AcqRel))
-    {
-        let bundle = File::open(&path).unwrap();
-        let mut rdr = ChunkReader::new(&mut buffer_reader, bundle);
-        num_files += extract_bundle(
-            &mut pool,
-            &mut rdr,
-            &mut bundle_buf,
-            Some(*bundle_hash),
-            &duplicates,
-            &options,
-            filter_ext,
-        ).unwrap();
+    loop {
+        let index = bundle_index.fetch_add(1, Ordering::AcqRel);
+        let Some((path, bundle_hash)) = bundles.get(index) else {
+            break;
+        };
+
+        // the last bundles are left to fewer threads than there are cores,
+        // so split their chunks across idle cores instead
+        let num_workers = if index + num_threads >= bundles.len() {
+            let want = workers.len();
+            worker_budget.fetch_update(Ordering::AcqRel, Ordering::Acquire, |left| Some(left - left.min(want)))
+                .map_or(0, |left| left.min(want))
+        } else {
+            0
+        };
+
+        let mut work = || File::open(path)
+            .map_err(limn::Error::from)
+            .and_then(|bundle| {
+                let mut rdr = ChunkReader::new(&mut buffer_reader, bundle);
+                extract_bundle(
+                    &mut pool,
+                    &mut rdr,
+                    &mut bundle_buf,
+                    &mut workers,
+                    num_workers,
+                    Some(*bundle_hash),
+                    shared,
+                )
+            });
+
+        let res = if shared.keep_going {
+            panic::catch_unwind(panic::AssertUnwindSafe(work))
+        } else {
+            Ok(work())
+        };
+        worker_budget.fetch_add(num_workers, Ordering::AcqRel);
+        let res = match res {
+            Ok(res) => res,
+            Err(payload) => {
+                shared.fail(Failure {
+                    is_bundle: true,
+                    bundle: Some(*bundle_hash),
+                    entry: None,
+                    offset: None,
+                    reason: format!("panic: {}", panic_message(&*payload)),
+                });
+                continue;
+            }
+        };
+
+        match res {
+            Ok(count) => num_files += count,
+            Err(e) if shared.keep_going => shared.fail(Failure::bundle(e.with_bundle(Some(*bundle_hash)))),
+            Err(e) => {
+                // stop other threads from taking more bundles
+                bundle_index.store(bundles.len() + usize::BITS as usize, Ordering::Release);
+                return Err(e.with_bundle(Some(*bundle_hash)));
+            }
+        }
     }
 
-    num_files
+    Ok(num_files)
+}
+
+// leave a core for the main thread
+fn num_threads() -> usize {
+    thread::available_parallelism()
+        .map(|i| i.get())
+        .unwrap_or(0)
+        .saturating_sub(1)
+        .max(1)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.to_string()
+    } else {
+        String::new()
+    }
 }
 
 fn extract_bundle(
     pool: &mut Pool,
     mut rdr: impl Read + Seek,
     bundle_buf: &mut Vec<u8>,
+    workers: &mut ChunkWorkers,
+    num_workers: usize,
     bundle_hash: Option<u64>,
-    duplicates: &Mutex<HashMap<(u64, u64), u64>>,
-    options: &ExtractOptions,
-    filter_ext: &HashSet<u64>,
-) -> io::Result<u32> {
+    shared: &Shared,
+) -> limn::Result<u32> {
+    let Shared {
+        duplicates,
+        options,
+        filter_ext,
+        name_filter,
+        changed,
+        ..
+    } = shared;
+
     bundle_buf.clear();
     let mut bundle = BundleFd::new(bundle_hash, &mut rdr)?;
-    let targets = if !filter_ext.is_empty() {
+    let targets = if !filter_ext.is_empty() || !name_filter.is_empty() || changed.is_some() {
         let mut targets = Vec::new();
         let mut dupes = duplicates.lock().unwrap();
-        for file in bundle.index() {
+        for file in bundle.index()? {
             let key = (file.ext, file.name);
             let entry = dupes.entry(key).or_insert(0);
             *entry += 1;
 
-            if *entry == 1 && filter_ext.contains(&file.ext) {
+            // every bundle gets its own copy with --per-bundle
+            if (*entry == 1 || options.per_bundle())
+                && (filter_ext.is_empty() || filter_ext.contains(&file.ext))
+                && changed.is_none_or(|changed| changed.contains(&key))
+                && name_filter.is_match(file.name, options.lookup(&file.name.into()))
+            {
+                // files picked by hash usually have no dictionary name
                 if options.skip_unknown()
                     && !options.contains_key(&file.name.into())
+                    && !name_filter.contains_hash(file.name)
                 {
                     continue;
                 }
@@ -580,6 +1079,13 @@ fn extract_bundle(
             Some(targets)
         }
     } else {
+        if options.harvest().is_some() {
+            // harvested names are checked against every name in the index
+            let mut dupes = duplicates.lock().unwrap();
+            for file in bundle.index()? {
+                *dupes.entry((file.ext, file.name)).or_insert(0) += 1;
+            }
+        }
         None
     };
 
@@ -589,63 +1095,191 @@ fn extract_bundle(
 
     let mut targets = targets.as_ref().map(|t| &t[..]);
     let mut count = 0;
-    let mut files = bundle.files(options.oodle(), bundle_buf);
-    while let Ok(Some(file)) = files.next_file().map_err(|e| panic!("{:016x} - {}", bundle_hash.unwrap_or(0), e)) {
-        if options.skip_unknown()
-            && file.ext != /*lua*/0xa14e8dfa2cd117e2
-            && !(filter_ext.contains(&file.ext) && file.ext == /*strings*/0x0d972bab10b40fd3)
-            && !options.contains_key(&file.name.into())
-        {
-            continue;
-        }
-
-        if let Some(targets) = &mut targets {
-            let (ext, name) = targets.first().unwrap();
-            if *ext == file.ext && *name == file.name {
-                (_, *targets) = targets.split_at(1);
-            } else {
+    bundle.files_pipelined(options.decompressor(), bundle_buf, workers, num_workers, |files| {
+        while let Some(mut file) = files.next_file()? {
+            if options.skip_unknown()
+                && file.ext != /*lua*/0xa14e8dfa2cd117e2
+                && !(filter_ext.contains(&file.ext) && file.ext == /*strings*/0x0d972bab10b40fd3)
+                && !options.contains_key(&file.name.into())
+                && !name_filter.contains_hash(file.name)
+            {
+                file.skip()?;
                 continue;
             }
-        }
 
-        match file::extract(file, pool, options) {
-            Ok(_wrote) => count += 1,
-            Err(_e) => (),//eprintln!("{e}"),
-        }
+            if let Some(targets) = &mut targets {
+                let (ext, name) = targets.first().unwrap();
+                if *ext == file.ext && *name == file.name {
+                    (_, *targets) = targets.split_at(1);
+                    if targets.is_empty() {
+                        file.stop_read_ahead();
+                    }
+                } else {
+                    file.skip()?;
+                    continue;
+                }
+            }
 
-        if let Some(targets) = &targets {
-            if targets.is_empty() {
+            match file::extract(file, pool, options) {
+                Ok(_wrote) => count += 1,
+                Err(e) => shared.fail(Failure::file(e.with_bundle(bundle_hash))),
+            }
+
+            if targets.is_some_and(|targets| targets.is_empty()) {
                 break;
             }
         }
+
+        Ok(count)
+    })
+}
+
+// bundles holding a file that passes the filters, from the catalog written
+// by `limn index`
+//
+// Bundles changed since are read again and the catalog is saved like `limn
+// index` would, so later runs do not read them again.
+fn catalog_bundles(
+    target: &Path,
+    options: &ExtractOptions,
+    filter_ext: &HashSet<u64>,
+    name_filter: &NameFilter,
+    num_threads: usize,
+) -> Option<HashSet<u64>> {
+    let path = Path::new(cmd::DEFAULT_CATALOG);
+    if !path.exists() {
+        return None;
+    }
+    let mut catalog = match Catalog::load(path) {
+        Ok(catalog) => catalog,
+        Err(e) => {
+            eprintln!("WARN: ignoring catalog \"{}\": {e}", path.display());
+            return None;
+        }
+    };
+    match catalog.refresh(target, options.decompressor(), num_threads) {
+        Ok(refresh) if refresh.added + refresh.updated + refresh.removed > 0 => {
+            if let Err(e) = catalog.save(path) {
+                eprintln!("WARN: could not save catalog \"{}\": {e}", path.display());
+            }
+        }
+        Ok(_) => (),
+        Err(e) => {
+            eprintln!("WARN: ignoring catalog \"{}\": {e}", path.display());
+            return None;
+        }
     }
 
-    Ok(count)
+    Some(catalog.bundles().iter()
+        .filter(|bundle| bundle.files.iter().any(|file| {
+            (filter_ext.is_empty() || filter_ext.contains(&file.ext))
+                && name_filter.is_match(file.name, options.lookup(&file.name.into()))
+        }))
+        .map(|bundle| bundle.hash)
+        .collect())
 }
 
-fn bundle_hash_from(path: &Path) -> Option<u64> {
-    let name = path.file_stem()?;
-    u64::from_str_radix(name.to_str()?, 16).ok()
+// compare the bundles with the catalog saved by the last extract into `output`
+fn plan_incremental(
+    target: &Path,
+    output: &Path,
+    options: &ExtractOptions,
+    name_filter: &NameFilter,
+    num_threads: usize,
+    prune: bool,
+) -> limn::Result<(Catalog, Plan)> {
+    let catalog_path = output.join(incremental::CATALOG_FILE);
+    let old = if catalog_path.exists() {
+        Catalog::load(&catalog_path)?
+    } else {
+        Catalog::new()
+    };
+    let mut catalog = old.clone();
+    catalog.refresh(target, options.decompressor(), num_threads)?;
+
+    let records = match fs::read_to_string(output.join(manifest::FILE_NAME)) {
+        Ok(text) => manifest::read_records(&text)?,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+        Err(e) => return Err(e.into()),
+    };
+    // same check as picking targets in `extract_bundle`
+    let mut plan = incremental::plan(&old, &catalog, records, |_, name| {
+        options.skip_unknown() && !options.contains_key(&name.into()) && !name_filter.contains_hash(name)
+    });
+    println!("{} changed files in {} bundles, {} removed",
+        plan.changed.len(),
+        plan.bundles.len(),
+        plan.removed.len());
+
+    if prune {
+        for record in &plan.removed {
+            // manifest.jsonl is read from disk, never delete outside the output
+            let relative = Path::new(&record.path);
+            if record.path.is_empty()
+                || !relative.components().all(|part| matches!(part, Component::Normal(_)))
+            {
+                return Err(std::io::Error::new(
+                    std::io::ErrorKind::InvalidData,
+                    format!("manifest path {:?} is outside the output directory", record.path),
+                ).into());
+            }
+            let path = output.join(relative);
+            match fs::remove_file(&path) {
+                Ok(()) => (),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => (),
+                Err(e) => return Err(e.into()),
+            }
+            // drop directories left empty, stops at the first one that is not
+            let mut dir = path.parent();
+            while let Some(parent) = dir
+                && parent != output
+                && fs::remove_dir(parent).is_ok()
+            {
+                dir = parent.parent();
+            }
+        }
+    } else {
+        // still on disk, keep listing them
+        plan.kept.append(&mut plan.removed);
+    }
+
+    if let Some(manifest) = options.manifest() {
+        for record in plan.kept.drain(..) {
+            manifest.push(record);
+        }
+    }
+    Ok((catalog, plan))
 }
 
 fn load_oodle(
-    name: &str,
+    path_override: Option<PathBuf>,
     path: &Path,
     darktide_path: Option<&PathBuf>,
-) -> Result<Oodle, io::Error> {
-    match Oodle::load(name) {
-        Ok(out) => Ok(out),
-        Err(e) => {
-            let oodle_path = format!("binaries/{name}");
-            if let Some(oodle) = path.parent().map(|p| p.join(&oodle_path))
-                .and_then(|p| Oodle::load(p).ok())
-                .or_else(|| darktide_path.map(|path| path.join(&oodle_path))
-                    .and_then(|p| Oodle::load(p).ok()))
-            {
-                Ok(oodle)
-            } else {
-                Err(e)
-            }
+) -> Result<Oodle, LoadError> {
+    let path_override = path_override.or_else(|| std::env::var_os("LIMN_OODLE")
+        .filter(|path| !path.is_empty())
+        .map(PathBuf::from));
+    if let Some(path) = path_override {
+        return Oodle::load_first([path]);
+    }
+
+    let exe_dir = std::env::current_exe().ok()
+        .and_then(|exe| exe.parent().map(|p| p.to_path_buf()));
+    let mut candidates = Vec::new();
+    for name in limn::oodle::LIBRARY_NAMES {
+        // bare names go through the system library search
+        candidates.push(PathBuf::from(name));
+        if let Some(exe_dir) = &exe_dir {
+            candidates.push(exe_dir.join(name));
+        }
+
+        let oodle_path = format!("binaries/{name}");
+        if let Some(path) = path.parent() {
+            candidates.push(path.join(&oodle_path));
+        }
+        if let Some(path) = darktide_path {
+            candidates.push(path.join(&oodle_path));
         }
     }
+    Oodle::load_first(candidates)
 }
@@ -0,0 +1,267 @@
+//! Record of every file written by an extraction.
+//!
+//! Enable with [`ExtractBuilder::manifest`](crate::ExtractBuilder::manifest).
+//! [`ExtractOptions::finish`](crate::file::ExtractOptions::finish) writes the
+//! records to `manifest.jsonl` in the output directory, one JSON object per
+//! line.
+
+use std::cell::RefCell;
+use std::io;
+use std::io::Write;
+use std::path::Component;
+use std::path::Path;
+use std::sync::Mutex;
+
+use crate::bundle::Variant;
+use crate::error::Error;
+use crate::error::Result;
+use crate::format::Format;
+use crate::format::Value;
+use crate::format::parse_json;
+use crate::hash::Digest;
+use crate::hash::extension_name;
+
+pub const FILE_NAME: &str = "manifest.jsonl";
+
+/// One output file and the bundle file it came from.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ManifestRecord {
+    /// Path relative to the output directory, separated by `/`.
+    pub path: String,
+    pub ext: u64,
+    pub name: u64,
+    /// Name from the dictionary.
+    pub resolved: Option<String>,
+    pub bundle: Option<u64>,
+    pub variants: Vec<Variant>,
+    /// Name of the [`Extractor`](crate::file::Extractor), `raw` when the
+    /// file was copied unconverted.
+    pub converter: String,
+    pub size: u64,
+    /// FNV-1a of the output.
+    pub digest: u64,
+}
+
+impl ManifestRecord {
+    pub fn to_value(&self) -> Value {
+        let mut fields = vec![
+            ("path".to_string(), self.path.as_str().into()),
+            ("ext_hash".to_string(), format!("{:016x}", self.ext).into()),
+            ("name_hash".to_string(), format!("{:016x}", self.name).into()),
+        ];
+        if let Some(ext) = extension_name(self.ext) {
+            fields.push(("ext".to_string(), ext.into()));
+        }
+        if let Some(name) = &self.resolved {
+            fields.push(("name".to_string(), name.as_str().into()));
+        }
+        fields.push(("bundle".to_string(), match self.bundle {
+            Some(bundle) => format!("{bundle:016x}").into(),
+            None => Value::Null,
+        }));
+        fields.push(("variants".to_string(), Value::Array(self.variants.iter().map(|variant| {
+            Value::Object(vec![
+                ("kind".to_string(), variant.kind.into()),
+                ("unknown1".to_string(), u32::from(variant.unknown1).into()),
+                ("body_size".to_string(), variant.body_size.into()),
+                ("unknown2".to_string(), u32::from(variant.unknown2).into()),
+                ("tail_size".to_string(), variant.tail_size.into()),
+            ])
+        }).collect())));
+        fields.push(("converter".to_string(), self.converter.as_str().into()));
+        fields.push(("size".to_string(), Value::Int(self.size as i64)));
+        fields.push(("digest".to_string(), format!("{:016x}", self.digest).into()));
+        Value::Object(fields)
+    }
+
+    /// Inverse of [`to_value`](Self::to_value).
+    pub fn from_value(value: &Value) -> Option<Self> {
+        let Value::Object(fields) = value else {
+            return None;
+        };
+        let field = |key: &str| fields.iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, field)| field);
+        let str_field = |key: &str| match field(key) {
+            Some(Value::Str(s)) => Some(s.as_str()),
+            _ => None,
+        };
+        let hash_field = |key: &str| str_field(key)
+            .and_then(|s| u64::from_str_radix(s, 16).ok());
+        let int_field = |fields: &[(String, Value)], key: &str| match fields.iter().find(|(k, _)| k == key) {
+            Some((_, Value::Int(n))) => Some(*n),
+            _ => None,
+        };
+
+        let Some(Value::Array(variants)) = field("variants") else {
+            return None;
+        };
+        let variants = variants.iter().map(|variant| {
+            let Value::Object(variant) = variant else {
+                return None;
+            };
+            Some(Variant {
+                kind: int_field(variant, "kind")?.try_into().ok()?,
+                unknown1: int_field(variant, "unknown1")?.try_into().ok()?,
+                body_size: int_field(variant, "body_size")?.try_into().ok()?,
+                unknown2: int_field(variant, "unknown2")?.try_into().ok()?,
+                tail_size: int_field(variant, "tail_size")?.try_into().ok()?,
+            })
+        }).collect::<Option<Vec<_>>>()?;
+
+        Some(Self {
+            path: str_field("path")?.to_string(),
+            ext: hash_field("ext_hash")?,
+            name: hash_field("name_hash")?,
+            resolved: str_field("name").map(str::to_string),
+            bundle: match field("bundle")? {
+                Value::Null => None,
+                _ => Some(hash_field("bundle")?),
+            },
+            variants,
+            converter: str_field("converter")?.to_string(),
+            size: int_field(fields, "size")?.try_into().ok()?,
+            digest: hash_field("digest")?,
+        })
+    }
+}
+
+/// Parse the records of a `manifest.jsonl`.
+pub fn read_records(text: &str) -> Result<Vec<ManifestRecord>> {
+    let mut records = Vec::new();
+    for (i, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let Some(record) = ManifestRecord::from_value(&parse_json(line)?) else {
+            return Err(Error::layout(format!("manifest line {} is not a record", i + 1)));
+        };
+        records.push(record);
+    }
+    Ok(records)
+}
+
+/// Records collected from every extraction thread.
+#[derive(Default)]
+pub struct Manifest {
+    records: Mutex<Vec<ManifestRecord>>,
+}
+
+impl Manifest {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&self, record: ManifestRecord) {
+        self.records.lock().unwrap().push(record);
+    }
+
+    /// Records sorted by path.
+    pub fn records(&self) -> Vec<ManifestRecord> {
+        let mut records = self.records.lock().unwrap().clone();
+        records.sort_unstable_by(|a, b| a.path.cmp(&b.path));
+        records
+    }
+
+    /// Append the records as JSON Lines.
+    pub fn write(&self, out: &mut Vec<u8>) {
+        for record in self.records() {
+            Format::Json.write(out, &record.to_value()).unwrap();
+            out.push(b'\n');
+        }
+    }
+}
+
+// outputs written by the file currently extracted on this thread
+thread_local!(static WRITTEN: RefCell<Vec<(String, u64, u64)>> = const { RefCell::new(Vec::new()) });
+
+pub(crate) fn take_written() -> Vec<(String, u64, u64)> {
+    WRITTEN.with_borrow_mut(std::mem::take)
+}
+
+pub(crate) fn record_written(path: &Path, size: u64, digest: u64) {
+    let mut rel = String::new();
+    for part in path.components() {
+        if let Component::Normal(part) = part {
+            if !rel.is_empty() {
+                rel.push('/');
+            }
+            rel.push_str(&part.to_string_lossy());
+        }
+    }
+    WRITTEN.with_borrow_mut(|written| written.push((rel, size, digest)));
+}
+
+/// Counts and digests everything written through it.
+pub(crate) struct DigestWriter<'a> {
+    pub inner: &'a mut dyn Write,
+    pub digest: Digest,
+    pub size: u64,
+}
+
+impl Write for DigestWriter<'_> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let n = self.inner.write(buf)?;
+        self.digest.update(&buf[..n]);
+        self.size += n as u64;
+        Ok(n)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+    use crate::ExtractBuilder;
+    use crate::bundle::BundleFd;
+    use crate::bundle::BundleWriter;
+    use crate::decompress::Passthrough;
+    use crate::file::Pool;
+    use crate::file::extract;
+
+    #[test]
+    fn records_outputs() {
+        let mut writer = BundleWriter::new(8).unwrap();
+        writer.add_file(0xa14e8dfa2cd117e2, 1, 0)
+            .variant(0, 0, b"body", b"");
+        let mut buf = Vec::new();
+        writer.write(&mut buf).unwrap();
+
+        let written = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let out = written.clone();
+        let mut builder = ExtractBuilder::new();
+        builder.input(".")
+            .output_custom(move |path, data| out.lock().unwrap().push((path.to_string(), data.to_vec())))
+            .decompressor(Box::new(Passthrough))
+            .dump_raw(true)
+            .manifest(true);
+        let options = builder.build().unwrap();
+
+        let mut rdr = std::io::Cursor::new(buf);
+        let mut bundle = BundleFd::new(Some(0xb0), &mut rdr).unwrap();
+        let mut scratch = Vec::new();
+        let mut files = bundle.files(options.decompressor(), &mut scratch).unwrap();
+        let file = files.next_file().unwrap().unwrap();
+        let size = extract(file, &mut Pool::new(), &options).unwrap();
+
+        let records = options.manifest().unwrap().records();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].path, "0000000000000001.lua");
+        assert_eq!(records[0].bundle, Some(0xb0));
+        assert_eq!(records[0].converter, "raw");
+        assert_eq!(records[0].size, size);
+        let mut digest = crate::hash::Digest::new();
+        digest.update(&written.lock().unwrap()[0].1);
+        assert_eq!(records[0].digest, digest.finish());
+
+        options.finish().unwrap();
+        let written = written.lock().unwrap();
+        assert!(written[1].0.ends_with(super::FILE_NAME));
+        let line = std::str::from_utf8(&written[1].1).unwrap();
+        assert!(line.starts_with("{\"path\":\"0000000000000001.lua\",\"ext_hash\":\"a14e8dfa2cd117e2\""), "{line}");
+        assert_eq!(super::read_records(line).unwrap(), records);
+    }
+}
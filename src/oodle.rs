@@ -1,9 +1,16 @@
+use std::fmt;
 use std::io;
 use std::path::Path;
+use std::path::PathBuf;
 use std::ptr;
 use libloading::Symbol;
 use libloading::Library;
 
+use crate::decompress::Decompressor;
+use crate::error::Error;
+use crate::error::ErrorKind;
+use crate::error::Result;
+
 // https://github.com/gildor2/UEViewer/blob/c444911a6ad65bff5266f273dd5bdf7dd6fb506e/Unreal/UnCoreCompression.cpp#L272
 // https://github.com/gildor2/UEViewer/blob/c444911a6ad65bff5266f273dd5bdf7dd6fb506e/Unreal/UnCoreCompression.cpp#L205
 #[allow(non_camel_case_types)]
@@ -28,53 +35,197 @@ type OodleLZ_Decompress = extern "C" fn(
 #[allow(non_camel_case_types)]
 type OodleLZDecoder_MemorySizeNeeded = unsafe extern "C" fn(i32, i64) -> u64;
 
+/// Oodle library names for the current platform, newest first.
+#[cfg(windows)]
+pub const LIBRARY_NAMES: &[&str] = &[
+    "oo2core_9_win64.dll",
+    "oo2core_8_win64.dll",
+];
+
+/// Oodle library names for the current platform, newest first.
+#[cfg(target_os = "macos")]
+pub const LIBRARY_NAMES: &[&str] = &[
+    "liboo2coremac64.2.9.dylib",
+    "liboo2coremac64.2.8.dylib",
+    "liboo2coremac64.dylib",
+];
+
+/// Oodle library names for the current platform, newest first.
+#[cfg(not(any(windows, target_os = "macos")))]
+pub const LIBRARY_NAMES: &[&str] = &[
+    "liboo2corelinux64.so.9",
+    "liboo2corelinux64.so.8",
+    "liboo2corelinux64.so",
+];
+
 pub struct Oodle {
-    lib: Library,
+    // keeps `decompress_fn` valid
+    _lib: Library,
+    decompress_fn: OodleLZ_Decompress,
+    scratch_size: usize,
 }
 
 impl Oodle {
     fn load_(path: &Path) -> io::Result<Self> {
-        let lib = unsafe {
-            Library::new(path).map_err(|e| io::Error::new(io::ErrorKind::Other, e))?
-        };
-        Ok(Self {
-            lib,
-        })
+        unsafe {
+            let lib = Library::new(path).map_err(io::Error::other)?;
+            let decompress: Symbol<OodleLZ_Decompress> = lib.get(b"OodleLZ_Decompress")
+                .map_err(io::Error::other)?;
+            let decompress_fn = *decompress;
+            let msn: Symbol<OodleLZDecoder_MemorySizeNeeded> = lib.get(b"OodleLZDecoder_MemorySizeNeeded")
+                .map_err(io::Error::other)?;
+            let scratch_size = msn(-1, -1) as usize;
+
+            Ok(Self {
+                _lib: lib,
+                decompress_fn,
+                scratch_size,
+            })
+        }
     }
 
     pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
         Self::load_(path.as_ref())
     }
 
-    pub fn memory_size_needed(&self) -> io::Result<u64> {
-        unsafe {
-            let msn: Symbol<OodleLZDecoder_MemorySizeNeeded> = self.lib.get(b"OodleLZDecoder_MemorySizeNeeded")
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-            Ok(msn(-1, -1))
+    /// Load the first candidate that works.
+    ///
+    /// The error lists every candidate and why it failed to load.
+    pub fn load_first<P: AsRef<Path>>(
+        candidates: impl IntoIterator<Item = P>,
+    ) -> Result<Self, LoadError> {
+        let mut tried = Vec::new();
+        for path in candidates {
+            let path = path.as_ref();
+            match Self::load_(path) {
+                Ok(oodle) => return Ok(oodle),
+                Err(e) => tried.push((path.to_path_buf(), e)),
+            }
         }
+        Err(LoadError { tried })
+    }
+
+    pub fn memory_size_needed(&self) -> io::Result<u64> {
+        Ok(self.scratch_size as u64)
     }
 
-    pub fn decompress(&self, data: &[u8], out: &mut [u8], scratch: &mut [u8]) -> io::Result<u64> {
-        let ret = unsafe {
-            // TODO cache
-            let decompress: Symbol<OodleLZ_Decompress> = self.lib.get(b"OodleLZ_Decompress")
-                .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-
-            decompress(
-                data.as_ptr(), data.len() as u64,
-                out.as_mut_ptr(), out.len() as u64,
-                1/*true*/, 0/*false*/, 3,
-                ptr::null_mut(), 0, ptr::null_mut(),
-                //ptr::null_mut(), ptr::null_mut(), 0,
-                ptr::null_mut(), scratch.as_mut_ptr(), scratch.len() as u64,
-                3)
-        };
+    pub fn decompress(&self, data: &[u8], out: &mut [u8], scratch: &mut [u8]) -> Result<u64> {
+        let ret = (self.decompress_fn)(
+            data.as_ptr(), data.len() as u64,
+            out.as_mut_ptr(), out.len() as u64,
+            1/*true*/, 0/*false*/, 3,
+            ptr::null_mut(), 0, ptr::null_mut(),
+            //ptr::null_mut(), ptr::null_mut(), 0,
+            ptr::null_mut(), scratch.as_mut_ptr(), scratch.len() as u64,
+            3);
 
         if ret != out.len() as u64 {
-            Err(io::Error::new(io::ErrorKind::Other, "failed to decompress data"))
+            Err(Error::new(ErrorKind::Decompress))
         } else {
             Ok(ret)
         }
     }
 }
+
+impl Decompressor for Oodle {
+    fn decompress(&self, input: &[u8], out: &mut [u8], scratch: &mut [u8]) -> Result<usize> {
+        Oodle::decompress(self, input, out, scratch).map(|size| size as usize)
+    }
+
+    fn scratch_size(&self) -> usize {
+        self.scratch_size
+    }
+}
+
+/// None of the candidate Oodle libraries could be loaded.
+#[derive(Debug)]
+pub struct LoadError {
+    tried: Vec<(PathBuf, io::Error)>,
+}
+
+impl LoadError {
+    /// Candidates in the order they were tried.
+    pub fn tried(&self) -> impl Iterator<Item = (&Path, &io::Error)> {
+        self.tried.iter().map(|(path, e)| (path.as_path(), e))
+    }
+}
+
+impl fmt::Display for LoadError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.tried.is_empty() {
+            return f.write_str("no oodle library to load");
+        }
+
+        f.write_str("failed to load oodle library, tried:")?;
+        for (path, e) in &self.tried {
+            write!(f, "\n    {}: {e}", path.display())?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for LoadError {}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process::Command;
+
+    const STUB: &str = r#"
+#[unsafe(no_mangle)]
+pub extern "C" fn OodleLZDecoder_MemorySizeNeeded(_: i32, _: i64) -> u64 {
+    0x1234
+}
+
+#[unsafe(no_mangle)]
+pub unsafe extern "C" fn OodleLZ_Decompress(
+    input: *const u8, input_len: u64,
+    out: *mut u8, out_len: u64,
+    _: i32, _: i32, _: i32,
+    _: *mut u8, _: u64, _: *mut u8, _: *mut u8, _: *mut u8, _: u64,
+    _: i32,
+) -> u64 {
+    let len = input_len.min(out_len);
+    unsafe { std::ptr::copy_nonoverlapping(input, out, len as usize) };
+    len
+}
+"#;
+
+    // build a shared library exporting the two symbols used by `Oodle`
+    fn build_stub() -> PathBuf {
+        let dir = env::temp_dir().join(format!("limn-oodle-stub-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let src = dir.join("stub.rs");
+        fs::write(&src, STUB).unwrap();
+        let lib = dir.join(format!("{}oo2core_stub{}", env::consts::DLL_PREFIX, env::consts::DLL_SUFFIX));
+        let status = Command::new(env::var_os("RUSTC").unwrap_or("rustc".into()))
+            .args(["--edition", "2024", "--crate-type", "cdylib", "-o"])
+            .arg(&lib)
+            .arg(&src)
+            .status()
+            .unwrap();
+        assert!(status.success());
+        lib
+    }
+
+    #[test]
+    fn load_stub() {
+        let stub = build_stub();
+        let missing = stub.with_file_name("missing");
+
+        let oodle = Oodle::load_first([&missing, &stub]).unwrap();
+        assert_eq!(0x1234, Decompressor::scratch_size(&oodle));
+        let mut out = [0; 4];
+        assert_eq!(4, Decompressor::decompress(&oodle, b"data", &mut out, &mut []).unwrap());
+        assert_eq!(&out, b"data");
+        assert!(Decompressor::decompress(&oodle, b"da", &mut out, &mut []).is_err());
+
+        let e = Oodle::load_first([&missing]).err().unwrap();
+        assert_eq!(e.tried().map(|(path, _)| path).collect::<Vec<_>>(), [missing.as_path()]);
+        assert!(e.to_string().contains("missing"));
+
+        fs::remove_dir_all(stub.parent().unwrap()).unwrap();
+    }
+}
@@ -25,7 +25,7 @@ impl<'a, R: Read + Seek> ChunkReader<'a, R> {
 
     fn next_chunk(&mut self) -> io::Result<()> {
         self.offset = 0;
-        self.len = self.inner.read(&mut self.buffer[..]).unwrap();
+        self.len = self.inner.read(&mut self.buffer[..])?;
         Ok(())
     }
 }
@@ -70,7 +70,12 @@ impl<'a, R: Read + Seek> Seek for ChunkReader<'a, R> {
                 let start = self.inner.seek(SeekFrom::Current(0))? - self.len as u64;
                 offset as i64 - start as i64
             }
-            SeekFrom::End(_offset) => unimplemented!(),
+            SeekFrom::End(offset) => {
+                self.offset = 0;
+                self.len = 0;
+                self.read_chunk = true;
+                return self.inner.seek(SeekFrom::End(offset));
+            }
         };
 
         if seek_to < 0 || seek_to > self.len as i64 {
@@ -10,6 +10,11 @@ pub(crate) trait FileOpen: Send + Sync + std::panic::RefUnwindSafe {
         path: &Path,
         scope: &mut dyn FnMut(&mut dyn io::Write) -> io::Result<u64>,
     ) -> io::Result<u64>;
+
+    /// Complete the output after the last file was written.
+    fn finish(&self) -> io::Result<()> {
+        Ok(())
+    }
 }
 
 pub(crate) struct ScopedFs {